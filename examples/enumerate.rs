@@ -20,6 +20,10 @@ fn main() -> Result<(), failure::Error> {
         println!("  Devices: ");
         for (device_index, device) in devices.enumerate() {
             println!("  {}. \"{}\"", device_index + 1, device.name()?);
+            println!("    Id:\n      {:?}", device.id()?);
+            if let Ok(info) = device.info() {
+                println!("    Info:\n      {:?}", info);
+            }
 
             // Input formats
             if let Ok(fmt) = device.default_input_format() {
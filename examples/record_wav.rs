@@ -49,36 +49,10 @@ fn main() -> Result<(), failure::Error> {
                 return;
             }
             // Otherwise write to the wav writer.
-            match data {
-                cpal::StreamData::Input { buffer: cpal::UnknownTypeInputBuffer::U16(buffer) } => {
-                    if let Ok(mut guard) = writer_2.try_lock() {
-                        if let Some(writer) = guard.as_mut() {
-                            for sample in buffer.iter() {
-                                let sample = cpal::Sample::to_i16(sample);
-                                writer.write_sample(sample).ok();
-                            }
-                        }
-                    }
-                },
-                cpal::StreamData::Input { buffer: cpal::UnknownTypeInputBuffer::I16(buffer) } => {
-                    if let Ok(mut guard) = writer_2.try_lock() {
-                        if let Some(writer) = guard.as_mut() {
-                            for &sample in buffer.iter() {
-                                writer.write_sample(sample).ok();
-                            }
-                        }
-                    }
-                },
-                cpal::StreamData::Input { buffer: cpal::UnknownTypeInputBuffer::F32(buffer) } => {
-                    if let Ok(mut guard) = writer_2.try_lock() {
-                        if let Some(writer) = guard.as_mut() {
-                            for &sample in buffer.iter() {
-                                writer.write_sample(sample).ok();
-                            }
-                        }
-                    }
-                },
-                _ => (),
+            if let Ok(mut guard) = writer_2.try_lock() {
+                if let Some(writer) = guard.as_mut() {
+                    write_input_data(&data, writer);
+                }
             }
         });
     });
@@ -91,19 +65,63 @@ fn main() -> Result<(), failure::Error> {
     Ok(())
 }
 
+type WavWriter = hound::WavWriter<std::io::BufWriter<std::fs::File>>;
+
+// Writes the samples of an input buffer, converting them to the sample type used in the file.
+fn write_input_data(data: &cpal::StreamData, writer: &mut WavWriter) {
+    fn write<T, U>(buffer: &[T], writer: &mut WavWriter)
+    where
+        T: cpal::Sample,
+        U: cpal::Sample + hound::Sample,
+    {
+        for sample in buffer.iter() {
+            writer.write_sample(U::from(sample)).ok();
+        }
+    }
+
+    use cpal::UnknownTypeInputBuffer::*;
+    match *data {
+        cpal::StreamData::Input { buffer: U8(ref buffer) } => write::<_, i8>(buffer, writer),
+        cpal::StreamData::Input { buffer: I8(ref buffer) } => write::<_, i8>(buffer, writer),
+        cpal::StreamData::Input { buffer: U16(ref buffer) } => write::<_, i16>(buffer, writer),
+        cpal::StreamData::Input { buffer: I16(ref buffer) } => write::<_, i16>(buffer, writer),
+        // 24-bit WAV samples are written as `i32` values within the 24-bit range.
+        cpal::StreamData::Input { buffer: I24(ref buffer) } => for sample in buffer.iter() {
+            writer.write_sample(sample.value()).ok();
+        },
+        cpal::StreamData::Input { buffer: I24In32(ref buffer) } => for sample in buffer.iter() {
+            writer.write_sample(sample.value()).ok();
+        },
+        cpal::StreamData::Input { buffer: U32(ref buffer) } => write::<_, i32>(buffer, writer),
+        cpal::StreamData::Input { buffer: I32(ref buffer) } => write::<_, i32>(buffer, writer),
+        cpal::StreamData::Input { buffer: F32(ref buffer) } => write::<_, f32>(buffer, writer),
+        cpal::StreamData::Input { buffer: F64(ref buffer) } => write::<_, f32>(buffer, writer),
+        _ => (),
+    }
+}
+
 fn sample_format(format: cpal::SampleFormat) -> hound::SampleFormat {
     match format {
-        cpal::SampleFormat::U16 => hound::SampleFormat::Int,
-        cpal::SampleFormat::I16 => hound::SampleFormat::Int,
-        cpal::SampleFormat::F32 => hound::SampleFormat::Float,
+        cpal::SampleFormat::U8 | cpal::SampleFormat::I8 |
+        cpal::SampleFormat::U16 | cpal::SampleFormat::I16 |
+        cpal::SampleFormat::I24 | cpal::SampleFormat::I24In32 |
+        cpal::SampleFormat::U32 | cpal::SampleFormat::I32 => hound::SampleFormat::Int,
+        cpal::SampleFormat::F32 | cpal::SampleFormat::F64 => hound::SampleFormat::Float,
     }
 }
 
 fn wav_spec_from_format(format: &cpal::Format) -> hound::WavSpec {
+    // WAV files do not support 64-bit floats, so these are written as 32-bit floats. Padded 24-bit
+    // samples are written packed.
+    let bits_per_sample = match format.data_type {
+        cpal::SampleFormat::F64 => 32,
+        cpal::SampleFormat::I24In32 => 24,
+        data_type => data_type.sample_size() * 8,
+    };
     hound::WavSpec {
         channels: format.channels as _,
         sample_rate: format.sample_rate.0 as _,
-        bits_per_sample: (format.data_type.sample_size() * 8) as _,
+        bits_per_sample: bits_per_sample as _,
         sample_format: sample_format(format.data_type),
     }
 }
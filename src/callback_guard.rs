@@ -0,0 +1,219 @@
+//! Containment of panics in the `run` callback to the stream whose data was being processed.
+//!
+//! Without it, a panic unwinds through the event loop of the host, possibly across FFI
+//! boundaries, and ends every stream of the event loop.
+
+use std::any::Any;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+
+use {
+    OutputBuffer,
+    Sample,
+    StreamData,
+    StreamDataResult,
+    UnknownTypeOutputBuffer,
+};
+
+// The streams of an event loop for which the callback has panicked or is no longer called.
+pub struct CallbackGuard<S> {
+    silenced: Mutex<HashSet<S>>,
+}
+
+impl<S> CallbackGuard<S>
+where
+    S: Clone + Eq + Hash,
+{
+    pub fn new() -> Self {
+        CallbackGuard {
+            silenced: Mutex::new(HashSet::new()),
+        }
+    }
+
+    // Stops calling the callback for the stream and silences its output instead, until the
+    // stream is removed. Used for streams that are waiting to be destroyed.
+    pub fn silence(&self, stream: &S) {
+        self.silenced.lock().unwrap().insert(stream.clone());
+    }
+
+    // Forgets that the callback has panicked for the stream or was silenced, once the stream is
+    // destroyed or a new stream with the same ID is built.
+    pub fn remove(&self, stream: &S) {
+        self.silenced.lock().unwrap().remove(stream);
+    }
+
+    // Passes the data of a stream to `f`, catching a panic.
+    //
+    // If `f` panics, the output of the stream is silenced and the panic message is returned.
+    // From then on `f` is no longer called for the stream, its output is silenced instead and
+    // `R::default()` is returned, until the stream is removed.
+    pub fn call<F, R>(&self, stream: &S, result: StreamDataResult, f: F) -> Result<R, String>
+    where
+        F: FnOnce(StreamDataResult) -> R,
+        R: Default,
+    {
+        if self.silenced.lock().unwrap().contains(stream) {
+            if let Ok(StreamData::Output { buffer }) = result {
+                silence(buffer);
+            }
+            return Ok(R::default());
+        }
+        let caught = match result {
+            Ok(StreamData::Output { buffer: mut output }) => {
+                let buffer = reborrow(&mut output);
+                let caught = panic::catch_unwind(AssertUnwindSafe(|| {
+                    f(Ok(StreamData::Output { buffer }))
+                }));
+                if caught.is_err() {
+                    silence(output);
+                }
+                caught
+            },
+            result => panic::catch_unwind(AssertUnwindSafe(|| f(result))),
+        };
+        caught.map_err(|payload| {
+            self.silenced.lock().unwrap().insert(stream.clone());
+            panic_message(payload)
+        })
+    }
+}
+
+// The message passed to `panic!`, if any.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "the panic payload is not a string".to_string(),
+        },
+    }
+}
+
+// Borrows an output buffer for a shorter lifetime, so that it can still be written after the
+// callback has panicked.
+fn reborrow<'a>(buffer: &'a mut UnknownTypeOutputBuffer<'_>) -> UnknownTypeOutputBuffer<'a> {
+    match *buffer {
+        UnknownTypeOutputBuffer::I8(ref mut b) => UnknownTypeOutputBuffer::I8(OutputBuffer { buffer: &mut b[..] }),
+        UnknownTypeOutputBuffer::U8(ref mut b) => UnknownTypeOutputBuffer::U8(OutputBuffer { buffer: &mut b[..] }),
+        UnknownTypeOutputBuffer::I16(ref mut b) => UnknownTypeOutputBuffer::I16(OutputBuffer { buffer: &mut b[..] }),
+        UnknownTypeOutputBuffer::U16(ref mut b) => UnknownTypeOutputBuffer::U16(OutputBuffer { buffer: &mut b[..] }),
+        UnknownTypeOutputBuffer::I24(ref mut b) => UnknownTypeOutputBuffer::I24(OutputBuffer { buffer: &mut b[..] }),
+        UnknownTypeOutputBuffer::I24In32(ref mut b) => UnknownTypeOutputBuffer::I24In32(OutputBuffer { buffer: &mut b[..] }),
+        UnknownTypeOutputBuffer::I32(ref mut b) => UnknownTypeOutputBuffer::I32(OutputBuffer { buffer: &mut b[..] }),
+        UnknownTypeOutputBuffer::U32(ref mut b) => UnknownTypeOutputBuffer::U32(OutputBuffer { buffer: &mut b[..] }),
+        UnknownTypeOutputBuffer::F32(ref mut b) => UnknownTypeOutputBuffer::F32(OutputBuffer { buffer: &mut b[..] }),
+        UnknownTypeOutputBuffer::F64(ref mut b) => UnknownTypeOutputBuffer::F64(OutputBuffer { buffer: &mut b[..] }),
+    }
+}
+
+fn silence(buffer: UnknownTypeOutputBuffer) {
+    match buffer {
+        UnknownTypeOutputBuffer::I8(mut buffer) => fill_equilibrium(&mut buffer),
+        UnknownTypeOutputBuffer::U8(mut buffer) => fill_equilibrium(&mut buffer),
+        UnknownTypeOutputBuffer::I16(mut buffer) => fill_equilibrium(&mut buffer),
+        UnknownTypeOutputBuffer::U16(mut buffer) => fill_equilibrium(&mut buffer),
+        UnknownTypeOutputBuffer::I24(mut buffer) => fill_equilibrium(&mut buffer),
+        UnknownTypeOutputBuffer::I24In32(mut buffer) => fill_equilibrium(&mut buffer),
+        UnknownTypeOutputBuffer::I32(mut buffer) => fill_equilibrium(&mut buffer),
+        UnknownTypeOutputBuffer::U32(mut buffer) => fill_equilibrium(&mut buffer),
+        UnknownTypeOutputBuffer::F32(mut buffer) => fill_equilibrium(&mut buffer),
+        UnknownTypeOutputBuffer::F64(mut buffer) => fill_equilibrium(&mut buffer),
+    }
+}
+
+fn fill_equilibrium<T: Sample>(samples: &mut [T]) {
+    for sample in samples.iter_mut() {
+        *sample = T::EQUILIBRIUM;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CallbackGuard;
+    use {InputBuffer, OutputBuffer, StreamData, UnknownTypeInputBuffer, UnknownTypeOutputBuffer};
+
+    fn output(guard: &CallbackGuard<u32>, stream: u32, samples: &mut [u16]) -> Result<(), String> {
+        let buffer = UnknownTypeOutputBuffer::U16(OutputBuffer { buffer: samples });
+        guard.call(&stream, Ok(StreamData::Output { buffer }), |result| match result {
+            Ok(StreamData::Output { buffer: UnknownTypeOutputBuffer::U16(mut buffer) }) => {
+                buffer[0] = 1;
+                if stream == 0 {
+                    panic!("callback of stream {}", stream);
+                }
+            },
+            _ => panic!("unexpected stream data"),
+        })
+    }
+
+    #[test]
+    fn panics_silence_the_stream() {
+        let guard = CallbackGuard::new();
+        let mut samples = [7u16; 2];
+        let message = output(&guard, 0, &mut samples).unwrap_err();
+        assert_eq!(message, "callback of stream 0");
+        assert_eq!(samples, [0x8000; 2]);
+        // The callback is not called again for the stream.
+        samples = [7; 2];
+        assert_eq!(output(&guard, 0, &mut samples), Ok(()));
+        assert_eq!(samples, [0x8000; 2]);
+        let mut called = false;
+        let buffer = UnknownTypeInputBuffer::U16(InputBuffer { buffer: &[0] });
+        guard.call(&0, Ok(StreamData::Input { buffer }), |_| called = true).unwrap();
+        assert!(!called);
+    }
+
+    #[test]
+    fn other_streams_keep_running() {
+        let guard = CallbackGuard::new();
+        let mut samples = [7u16; 2];
+        assert!(output(&guard, 0, &mut samples).is_err());
+        samples = [7; 2];
+        assert_eq!(output(&guard, 1, &mut samples), Ok(()));
+        assert_eq!(samples, [1, 7]);
+        // Removed streams are called again.
+        guard.remove(&0);
+        assert!(output(&guard, 0, &mut samples).is_err());
+    }
+
+    // Some hosts reuse the IDs of destroyed streams.
+    #[test]
+    fn rebuilt_streams_are_called_again() {
+        let guard = CallbackGuard::new();
+        let mut samples = [7u16; 2];
+        assert!(output(&guard, 0, &mut samples).is_err());
+        let mut calls = 0;
+        {
+            let buffer = UnknownTypeInputBuffer::U16(InputBuffer { buffer: &[0] });
+            guard.call(&0, Ok(StreamData::Input { buffer }), |_| calls += 1).unwrap();
+        }
+        assert_eq!(calls, 0);
+        // The stream is destroyed by the host, and a new stream is built in its slot.
+        guard.remove(&0);
+        let buffer = UnknownTypeInputBuffer::U16(InputBuffer { buffer: &[0] });
+        guard.call(&0, Ok(StreamData::Input { buffer }), |_| calls += 1).unwrap();
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn silenced_streams() {
+        let guard = CallbackGuard::new();
+        guard.silence(&1);
+        let mut samples = [7u16; 2];
+        assert_eq!(output(&guard, 1, &mut samples), Ok(()));
+        assert_eq!(samples, [0x8000; 2]);
+        guard.remove(&1);
+        assert_eq!(output(&guard, 1, &mut samples), Ok(()));
+        assert_eq!(samples, [1, 0x8000]);
+    }
+
+    #[test]
+    fn static_panic_messages() {
+        let guard = CallbackGuard::<u32>::new();
+        let result = guard.call(&0, Ok(StreamData::Input {
+            buffer: UnknownTypeInputBuffer::U16(InputBuffer { buffer: &[0] }),
+        }), |_| -> () { panic!("static message") });
+        assert_eq!(result, Err("static message".to_string()));
+    }
+}
@@ -0,0 +1,176 @@
+use ChannelCount;
+
+/// The position of a single channel within a `ChannelLayout`.
+///
+/// The named positions correspond to the speaker positions commonly found in surround setups.
+/// Channels that are not associated with a speaker, for example the inputs of a multi-channel
+/// audio interface, are `Discrete`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ChannelPosition {
+    /// The position of the channel is not known.
+    Unknown,
+    /// The channel is not connected to any speaker and should be silent.
+    NotApplicable,
+    Mono,
+    FrontLeft,
+    FrontRight,
+    FrontCenter,
+    LowFrequency,
+    RearLeft,
+    RearRight,
+    RearCenter,
+    SideLeft,
+    SideRight,
+    FrontLeftOfCenter,
+    FrontRightOfCenter,
+    RearLeftOfCenter,
+    RearRightOfCenter,
+    FrontLeftWide,
+    FrontRightWide,
+    FrontLeftHigh,
+    FrontCenterHigh,
+    FrontRightHigh,
+    TopCenter,
+    TopFrontLeft,
+    TopFrontRight,
+    TopFrontCenter,
+    TopRearLeft,
+    TopRearRight,
+    TopRearCenter,
+    TopFrontLeftOfCenter,
+    TopFrontRightOfCenter,
+    TopSideLeft,
+    TopSideRight,
+    LowFrequencyLeft,
+    LowFrequencyRight,
+    BottomCenter,
+    BottomLeftOfCenter,
+    BottomRightOfCenter,
+    /// A channel without a speaker position, identified by its index among the discrete channels.
+    Discrete(u16),
+}
+
+/// The order and meaning of the channels of a stream.
+///
+/// The position at index `n` describes the `n`th sample of each frame, so the number of positions
+/// is always equal to the number of channels of the format that the layout belongs to.
+///
+/// With the `serde` feature enabled this is serialized as a sequence of positions, e.g.
+/// `["FrontLeft","FrontRight"]` in JSON.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChannelLayout(Vec<ChannelPosition>);
+
+impl ChannelLayout {
+    /// Creates a layout from the position of each channel, in the order of the samples in a frame.
+    #[inline]
+    pub fn new(positions: Vec<ChannelPosition>) -> ChannelLayout {
+        ChannelLayout(positions)
+    }
+
+    /// A single `Mono` channel.
+    #[inline]
+    pub fn mono() -> ChannelLayout {
+        ChannelLayout(vec![ChannelPosition::Mono])
+    }
+
+    /// `FrontLeft`, `FrontRight`.
+    #[inline]
+    pub fn stereo() -> ChannelLayout {
+        use self::ChannelPosition::*;
+        ChannelLayout(vec![FrontLeft, FrontRight])
+    }
+
+    /// 5.1 surround in the order `FrontLeft`, `FrontRight`, `FrontCenter`, `LowFrequency`,
+    /// `RearLeft`, `RearRight`.
+    #[inline]
+    pub fn surround_5_1() -> ChannelLayout {
+        use self::ChannelPosition::*;
+        ChannelLayout(vec![FrontLeft, FrontRight, FrontCenter, LowFrequency, RearLeft, RearRight])
+    }
+
+    /// 7.1 surround in the order `FrontLeft`, `FrontRight`, `FrontCenter`, `LowFrequency`,
+    /// `RearLeft`, `RearRight`, `SideLeft`, `SideRight`.
+    #[inline]
+    pub fn surround_7_1() -> ChannelLayout {
+        use self::ChannelPosition::*;
+        ChannelLayout(vec![
+            FrontLeft, FrontRight, FrontCenter, LowFrequency,
+            RearLeft, RearRight, SideLeft, SideRight,
+        ])
+    }
+
+    /// The given number of `Discrete` channels without any speaker positions.
+    #[inline]
+    pub fn discrete(channels: ChannelCount) -> ChannelLayout {
+        ChannelLayout((0..channels).map(ChannelPosition::Discrete).collect())
+    }
+
+    /// The layout commonly assumed for the given number of channels when a device does not
+    /// report one: mono, stereo, 5.1 or 7.1 for 1, 2, 6 or 8 channels, otherwise `discrete`.
+    pub fn default_for_channels(channels: ChannelCount) -> ChannelLayout {
+        match channels {
+            1 => ChannelLayout::mono(),
+            2 => ChannelLayout::stereo(),
+            6 => ChannelLayout::surround_5_1(),
+            8 => ChannelLayout::surround_7_1(),
+            channels => ChannelLayout::discrete(channels),
+        }
+    }
+
+    /// The position of each channel, in the order of the samples in a frame.
+    #[inline]
+    pub fn positions(&self) -> &[ChannelPosition] {
+        &self.0
+    }
+
+    /// The number of channels described by the layout.
+    #[inline]
+    pub fn channels(&self) -> ChannelCount {
+        self.0.len() as ChannelCount
+    }
+
+    /// The index of the channel at the given position, if any.
+    #[inline]
+    pub fn index_of(&self, position: ChannelPosition) -> Option<usize> {
+        self.0.iter().position(|&p| p == position)
+    }
+
+    // Whether any channel has a speaker position, as opposed to being `Unknown`, `NotApplicable`
+    // or `Discrete`.
+    pub(crate) fn has_speaker_positions(&self) -> bool {
+        self.0.iter().any(|&position| {
+            if let ChannelPosition::Discrete(_) = position {
+                return false;
+            }
+            position != ChannelPosition::Unknown && position != ChannelPosition::NotApplicable
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ChannelLayout, ChannelPosition};
+
+    #[test]
+    fn predefined_layouts() {
+        assert_eq!(ChannelLayout::mono().channels(), 1);
+        assert_eq!(ChannelLayout::stereo().channels(), 2);
+        assert_eq!(ChannelLayout::surround_5_1().channels(), 6);
+        assert_eq!(ChannelLayout::surround_7_1().channels(), 8);
+        assert_eq!(ChannelLayout::surround_5_1().index_of(ChannelPosition::LowFrequency), Some(3));
+        assert_eq!(ChannelLayout::default_for_channels(6), ChannelLayout::surround_5_1());
+        assert_eq!(ChannelLayout::default_for_channels(3), ChannelLayout::discrete(3));
+    }
+
+    #[test]
+    fn discrete_layout() {
+        let layout = ChannelLayout::discrete(3);
+        assert_eq!(
+            layout.positions(),
+            &[ChannelPosition::Discrete(0), ChannelPosition::Discrete(1), ChannelPosition::Discrete(2)],
+        );
+        assert_eq!(layout.index_of(ChannelPosition::FrontLeft), None);
+    }
+}
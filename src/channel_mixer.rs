@@ -0,0 +1,370 @@
+//! Up- and down-mixing of interleaved frames between numbers of channels.
+//!
+//! A `ChannelMixer` multiplies each frame with a matrix of coefficients, one row per output
+//! channel and one column per input channel. Standard matrices are derived from the
+//! `ChannelLayout`s of the input and output, following the downmix coefficients of ITU-R BS.775:
+//! the center and surround channels are mixed into the front channels at -3 dB and the low
+//! frequency channel is dropped. Upmixing only routes channels to the matching speakers, leaving
+//! the additional speakers silent.
+//!
+//! ```
+//! use cpal::ChannelLayout;
+//! use cpal::channel_mixer::ChannelMixer;
+//!
+//! let mixer = ChannelMixer::between_layouts(&ChannelLayout::surround_5_1(), &ChannelLayout::stereo());
+//! let input = [0.5f32, 0.5, 0.0, 1.0, 0.0, 0.0];
+//! let mut output = [0.0f32; 2];
+//! assert_eq!(mixer.process(&input, &mut output), 1);
+//! assert_eq!(output, [0.5, 0.5]);
+//! ```
+
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use {ChannelCount, ChannelLayout, ChannelPosition, Sample};
+
+/// A matrix mixing interleaved frames of one number of channels into another.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelMixer {
+    input_channels: usize,
+    output_channels: usize,
+    // One row of `input_channels` coefficients per output channel.
+    matrix: Vec<f32>,
+}
+
+impl ChannelMixer {
+    /// Creates a mixer from a custom matrix, with one row per output channel holding the
+    /// coefficient of each input channel.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the matrix is empty or the rows differ in length.
+    pub fn new(matrix: Vec<Vec<f32>>) -> Self {
+        let input_channels = matrix.first().map(Vec::len).unwrap_or(0);
+        assert!(input_channels > 0, "a channel matrix needs at least one row and column");
+        assert!(
+            matrix.iter().all(|row| row.len() == input_channels),
+            "all rows of a channel matrix must have the same length",
+        );
+        ChannelMixer {
+            input_channels,
+            output_channels: matrix.len(),
+            matrix: matrix.into_iter().flatten().collect(),
+        }
+    }
+
+    /// A mixer passing each channel through unchanged.
+    pub fn identity(channels: ChannelCount) -> Self {
+        let channels = channels as usize;
+        let mut matrix = vec![0.0; channels * channels];
+        for ch in 0..channels {
+            matrix[ch * channels + ch] = 1.0;
+        }
+        ChannelMixer { input_channels: channels, output_channels: channels, matrix }
+    }
+
+    /// The standard matrix between two layouts.
+    ///
+    /// Channels at the same position are passed through. Other named positions are mixed into
+    /// the nearest available speakers. `Discrete` channels are passed through to the matching
+    /// `Discrete` output channel if there is one, and `Unknown` channels to the output channel
+    /// with the same index.
+    ///
+    /// If the output has no named positions, every input channel is passed through to the output
+    /// channel with the same index.
+    pub fn between_layouts(input: &ChannelLayout, output: &ChannelLayout) -> Self {
+        let input_channels = input.channels() as usize;
+        let output_channels = output.channels() as usize;
+        let mut matrix = vec![0.0; input_channels * output_channels];
+        let output_is_named = output.has_speaker_positions();
+        for (in_ch, &position) in input.positions().iter().enumerate() {
+            let by_index = || -> Vec<(usize, f32)> {
+                match output.positions().get(in_ch) {
+                    Some(&ChannelPosition::NotApplicable) | None => vec![],
+                    Some(_) => vec![(in_ch, 1.0)],
+                }
+            };
+            let routes = match position {
+                _ if !output_is_named => by_index(),
+                ChannelPosition::Unknown => by_index(),
+                ChannelPosition::Discrete(_) => match output.index_of(position) {
+                    Some(out_ch) => vec![(out_ch, 1.0)],
+                    None => by_index(),
+                },
+                position => route(position, output),
+            };
+            for (out_ch, coefficient) in routes {
+                matrix[out_ch * input_channels + in_ch] += coefficient;
+            }
+        }
+        ChannelMixer { input_channels, output_channels, matrix }
+    }
+
+    /// The standard matrix between the `ChannelLayout::default_for_channels` of two channel
+    /// counts.
+    pub fn between_channel_counts(input: ChannelCount, output: ChannelCount) -> Self {
+        Self::between_layouts(
+            &ChannelLayout::default_for_channels(input),
+            &ChannelLayout::default_for_channels(output),
+        )
+    }
+
+    /// Scales the matrix down so that no output channel can exceed the level of a full scale
+    /// input, i.e. so that the coefficients of each row sum to at most 1.
+    ///
+    /// The ITU downmix coefficients may otherwise clip when all input channels are loud.
+    pub fn normalized(mut self) -> Self {
+        let max_sum = self.matrix
+            .chunks(self.input_channels)
+            .map(|row| row.iter().map(|c| c.abs()).sum::<f32>())
+            .fold(0.0, f32::max);
+        if max_sum > 1.0 {
+            for coefficient in &mut self.matrix {
+                *coefficient /= max_sum;
+            }
+        }
+        self
+    }
+
+    /// The number of channels of each input frame.
+    #[inline]
+    pub fn input_channels(&self) -> usize {
+        self.input_channels
+    }
+
+    /// The number of channels of each output frame.
+    #[inline]
+    pub fn output_channels(&self) -> usize {
+        self.output_channels
+    }
+
+    /// The coefficient with which the given input channel is mixed into the given output channel.
+    #[inline]
+    pub fn coefficient(&self, output_channel: usize, input_channel: usize) -> f32 {
+        self.matrix[output_channel * self.input_channels + input_channel]
+    }
+
+    /// Mixes the interleaved frames of `input` into `output`, as many as both buffers hold.
+    ///
+    /// Returns the number of frames written.
+    pub fn process<I, O>(&self, input: &[I], output: &mut [O]) -> usize
+    where
+        I: Sample,
+        O: Sample,
+    {
+        let input_frames = input.chunks(self.input_channels);
+        let output_frames = output.chunks_mut(self.output_channels);
+        let mut frames = 0;
+        for (input, output) in input_frames.zip(output_frames) {
+            if input.len() < self.input_channels || output.len() < self.output_channels {
+                break;
+            }
+            for (out, row) in output.iter_mut().zip(self.matrix.chunks(self.input_channels)) {
+                let sum: f32 = row.iter().zip(input).map(|(&c, s)| c * s.to_f32()).sum();
+                *out = O::from(&sum);
+            }
+            frames += 1;
+        }
+        frames
+    }
+}
+
+// The output channels and coefficients that a named input position is mixed into.
+fn route(position: ChannelPosition, output: &ChannelLayout) -> Vec<(usize, f32)> {
+    use ChannelPosition::*;
+
+    if let Some(out_ch) = output.index_of(position) {
+        return vec![(out_ch, 1.0)];
+    }
+    let scaled = |position, coefficient: f32| -> Vec<(usize, f32)> {
+        route(position, output).into_iter().map(|(ch, c)| (ch, c * coefficient)).collect()
+    };
+    let has = |position| output.index_of(position).is_some();
+    let pair = |left, right, coefficient: f32| -> Vec<(usize, f32)> {
+        let mut routes = scaled(left, coefficient);
+        routes.extend(scaled(right, coefficient));
+        routes
+    };
+
+    match position {
+        Mono | FrontCenter => {
+            let other = if position == Mono { FrontCenter } else { Mono };
+            if has(other) {
+                scaled(other, 1.0)
+            } else if has(FrontLeft) || has(FrontRight) {
+                pair(FrontLeft, FrontRight, FRAC_1_SQRT_2)
+            } else {
+                vec![]
+            }
+        },
+        FrontLeft | FrontRight => {
+            if has(FrontCenter) {
+                scaled(FrontCenter, FRAC_1_SQRT_2)
+            } else if has(Mono) {
+                scaled(Mono, FRAC_1_SQRT_2)
+            } else {
+                vec![]
+            }
+        },
+        RearLeft | SideLeft => {
+            let other = if position == RearLeft { SideLeft } else { RearLeft };
+            if has(other) { scaled(other, 1.0) } else { scaled(FrontLeft, FRAC_1_SQRT_2) }
+        },
+        RearRight | SideRight => {
+            let other = if position == RearRight { SideRight } else { RearRight };
+            if has(other) { scaled(other, 1.0) } else { scaled(FrontRight, FRAC_1_SQRT_2) }
+        },
+        RearCenter => {
+            if has(RearLeft) || has(RearRight) {
+                pair(RearLeft, RearRight, FRAC_1_SQRT_2)
+            } else if has(SideLeft) || has(SideRight) {
+                pair(SideLeft, SideRight, FRAC_1_SQRT_2)
+            } else {
+                pair(FrontLeft, FrontRight, 0.5)
+            }
+        },
+        // Height, wide and other additional speakers fall back to the nearest main speaker.
+        FrontLeftOfCenter | FrontLeftWide | FrontLeftHigh | TopFrontLeft | TopFrontLeftOfCenter |
+        BottomLeftOfCenter => scaled(FrontLeft, 1.0),
+        FrontRightOfCenter | FrontRightWide | FrontRightHigh | TopFrontRight |
+        TopFrontRightOfCenter | BottomRightOfCenter => scaled(FrontRight, 1.0),
+        FrontCenterHigh | TopFrontCenter | TopCenter | BottomCenter => scaled(FrontCenter, 1.0),
+        RearLeftOfCenter | TopRearLeft => scaled(RearLeft, 1.0),
+        RearRightOfCenter | TopRearRight => scaled(RearRight, 1.0),
+        TopRearCenter => scaled(RearCenter, 1.0),
+        TopSideLeft => scaled(SideLeft, 1.0),
+        TopSideRight => scaled(SideRight, 1.0),
+        LowFrequencyLeft | LowFrequencyRight => scaled(LowFrequency, 1.0),
+        // The low frequency channel is dropped when downmixing, as recommended by ITU-R BS.775.
+        LowFrequency | NotApplicable | Unknown | Discrete(_) => vec![],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    use {ChannelLayout, ChannelPosition};
+    use super::ChannelMixer;
+
+    fn rows(mixer: &ChannelMixer) -> Vec<Vec<f32>> {
+        (0..mixer.output_channels())
+            .map(|out| (0..mixer.input_channels()).map(|i| mixer.coefficient(out, i)).collect())
+            .collect()
+    }
+
+    const H: f32 = FRAC_1_SQRT_2;
+
+    #[test]
+    fn mono_and_stereo() {
+        assert_eq!(rows(&ChannelMixer::between_channel_counts(1, 2)), vec![vec![H], vec![H]]);
+        assert_eq!(rows(&ChannelMixer::between_channel_counts(2, 1)), vec![vec![H, H]]);
+        assert_eq!(ChannelMixer::between_channel_counts(2, 2), ChannelMixer::identity(2));
+    }
+
+    #[test]
+    fn stereo_to_surround() {
+        let mixer = ChannelMixer::between_channel_counts(2, 6);
+        assert_eq!(rows(&mixer), vec![
+            vec![1.0, 0.0],
+            vec![0.0, 1.0],
+            vec![0.0, 0.0],
+            vec![0.0, 0.0],
+            vec![0.0, 0.0],
+            vec![0.0, 0.0],
+        ]);
+    }
+
+    #[test]
+    fn itu_downmix() {
+        // FL, FR, FC, LFE, RL, RR
+        let mixer = ChannelMixer::between_channel_counts(6, 2);
+        assert_eq!(rows(&mixer), vec![
+            vec![1.0, 0.0, H, 0.0, H, 0.0],
+            vec![0.0, 1.0, H, 0.0, 0.0, H],
+        ]);
+        // FL, FR, FC, LFE, RL, RR, SL, SR
+        let mixer = ChannelMixer::between_channel_counts(8, 2);
+        assert_eq!(rows(&mixer), vec![
+            vec![1.0, 0.0, H, 0.0, H, 0.0, H, 0.0],
+            vec![0.0, 1.0, H, 0.0, 0.0, H, 0.0, H],
+        ]);
+        // Side channels are kept as surround channels in 5.1.
+        let mixer = ChannelMixer::between_channel_counts(8, 6);
+        assert_eq!(mixer.coefficient(4, 6), 1.0);
+        assert_eq!(mixer.coefficient(5, 7), 1.0);
+    }
+
+    #[test]
+    fn reordering_and_discrete_channels() {
+        let input = ChannelLayout::new(vec![
+            ChannelPosition::FrontRight,
+            ChannelPosition::FrontLeft,
+        ]);
+        let mixer = ChannelMixer::between_layouts(&input, &ChannelLayout::stereo());
+        assert_eq!(rows(&mixer), vec![vec![0.0, 1.0], vec![1.0, 0.0]]);
+
+        let mixer = ChannelMixer::between_channel_counts(3, 4);
+        assert_eq!(rows(&mixer), vec![
+            vec![1.0, 0.0, 0.0],
+            vec![0.0, 1.0, 0.0],
+            vec![0.0, 0.0, 1.0],
+            vec![0.0, 0.0, 0.0],
+        ]);
+    }
+
+    #[test]
+    fn unknown_channels_are_passed_through_by_index() {
+        let unknown = ChannelLayout::new(vec![ChannelPosition::Unknown; 2]);
+        let mixer = ChannelMixer::between_layouts(&unknown, &unknown);
+        assert_eq!(mixer, ChannelMixer::identity(2));
+        let mixer = ChannelMixer::between_layouts(&unknown, &ChannelLayout::surround_5_1());
+        assert_eq!(rows(&mixer)[..2], [vec![1.0, 0.0], vec![0.0, 1.0]]);
+    }
+
+    #[test]
+    fn outputs_without_named_positions() {
+        let unknown = ChannelLayout::new(vec![
+            ChannelPosition::Unknown,
+            ChannelPosition::Unknown,
+            ChannelPosition::NotApplicable,
+        ]);
+        let mixer = ChannelMixer::between_layouts(&ChannelLayout::surround_5_1(), &unknown);
+        assert_eq!(rows(&mixer), vec![
+            vec![1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            vec![0.0, 1.0, 0.0, 0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+        ]);
+        let mixer = ChannelMixer::between_layouts(&ChannelLayout::stereo(), &ChannelLayout::discrete(2));
+        assert_eq!(mixer, ChannelMixer::identity(2));
+    }
+
+    #[test]
+    fn normalization() {
+        let mixer = ChannelMixer::between_channel_counts(6, 2).normalized();
+        let sum: f32 = rows(&mixer)[0].iter().sum();
+        assert!((sum - 1.0).abs() < 1e-6);
+        // Matrices that cannot clip are left unchanged.
+        let mixer = ChannelMixer::between_channel_counts(1, 2);
+        assert_eq!(mixer.clone().normalized(), mixer);
+    }
+
+    #[test]
+    fn custom_matrix() {
+        let mixer = ChannelMixer::new(vec![vec![0.5, 0.5], vec![1.0, -1.0], vec![0.0, 0.25]]);
+        let input = [0.5f32, 0.25, -0.5, 0.0];
+        let mut output = [0.0f32; 6];
+        assert_eq!(mixer.process(&input, &mut output), 2);
+        assert_eq!(output, [0.375, 0.25, 0.0625, -0.25, -0.5, 0.0]);
+
+        // Only complete frames that fit into both buffers are processed, in any sample type.
+        let mut output = [0u16; 4];
+        assert_eq!(mixer.process(&input, &mut output), 1);
+        assert_eq!(output[3], 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn ragged_matrix() {
+        ChannelMixer::new(vec![vec![1.0, 0.0], vec![1.0]]);
+    }
+}
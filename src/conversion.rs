@@ -0,0 +1,877 @@
+//! Opt-in conversion of stream formats that a device does not support natively.
+//!
+//! Wrapping an event loop in a `ConvertingEventLoop` allows streams to be built with any `Format`.
+//! If the device does not support the requested format, the stream is opened in the closest
+//! format the device does support and the data passed to the `run` callback is converted between
+//! the two formats: the sample type is converted via the `Sample` trait, channels are mixed with a
+//! normalized [`ChannelMixer`](../channel_mixer/struct.ChannelMixer.html) and the sample rate is
+//! converted with a [`Resampler`](../resampler/struct.Resampler.html). Samples are processed as
+//! `f32` and dithered with a [`Ditherer`](../dither/struct.Ditherer.html) when they are written as
+//! integer samples.
+//!
+//! ```no_run
+//! use cpal::conversion::ConvertingEventLoop;
+//! use cpal::traits::{EventLoopTrait, HostTrait};
+//!
+//! let host = cpal::default_host();
+//! let event_loop = ConvertingEventLoop::new(host.event_loop());
+//! let device = host.default_output_device().expect("no output device available");
+//! let format = cpal::Format {
+//!     channels: 2,
+//!     sample_rate: cpal::SampleRate(44_100),
+//!     data_type: cpal::SampleFormat::F32,
+//!     channel_layout: None,
+//! };
+//! let stream_id = event_loop.build_output_stream(&device, &format).unwrap();
+//! println!("{:?}", event_loop.stream_conversion(stream_id.clone()));
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use {
+    BuildStreamError,
+    ChannelLayout,
+    Format,
+    FormatRequest,
+    I24,
+    I24In32,
+    InputBuffer,
+    OutputBuffer,
+    PauseStreamError,
+    PlayStreamError,
+    Requirement,
+    Sample,
+    SampleFormat,
+    StreamData,
+    StreamDataResult,
+    SupportedFormat,
+    SupportedFormatsError,
+    UnknownTypeInputBuffer,
+    UnknownTypeOutputBuffer,
+    convert_slice,
+};
+use channel_mixer::ChannelMixer;
+use dither::{Dither, Ditherer};
+use resampler::{Quality, Resampler};
+use traits::{DeviceTrait, EventLoopTrait};
+
+/// The formats between which the data of a stream is converted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamConversion {
+    /// The format that the stream was requested with, and in which data is exchanged with the
+    /// `run` callback.
+    pub requested: Format,
+    /// The format that the device was opened with.
+    pub device: Format,
+}
+
+impl StreamConversion {
+    /// Whether or not the device was opened in the requested format, so that no conversion
+    /// takes place.
+    #[inline]
+    pub fn is_identity(&self) -> bool {
+        !self.converts_data_type() && !self.mixes_channels() && !self.resamples()
+    }
+
+    /// Whether or not samples are converted to another sample format.
+    #[inline]
+    pub fn converts_data_type(&self) -> bool {
+        self.requested.data_type != self.device.data_type
+    }
+
+    /// Whether or not channels are mixed into another number of channels or another layout.
+    ///
+    /// Formats without a `channel_layout` are assumed to have the
+    /// `ChannelLayout::default_for_channels` of their channel count, as are devices whose layout
+    /// has no speaker positions.
+    #[inline]
+    pub fn mixes_channels(&self) -> bool {
+        self.requested_layout() != self.device_layout()
+    }
+
+    /// The channel layout of the requested format.
+    pub fn requested_layout(&self) -> ChannelLayout {
+        layout_of(&self.requested)
+    }
+
+    /// The channel layout of the device format.
+    ///
+    /// Devices often do not know the speakers that their channels are connected to, e.g. ALSA
+    /// devices whose channel map is all `Unknown`. Their channels are assumed to have the
+    /// `ChannelLayout::default_for_channels` positions instead.
+    pub fn device_layout(&self) -> ChannelLayout {
+        let layout = layout_of(&self.device);
+        if layout.has_speaker_positions() {
+            layout
+        } else {
+            ChannelLayout::default_for_channels(self.device.channels)
+        }
+    }
+
+    /// Whether or not the sample rate is converted.
+    #[inline]
+    pub fn resamples(&self) -> bool {
+        self.requested.sample_rate != self.device.sample_rate
+    }
+}
+
+/// An event loop that converts the data of streams whose format the device does not support.
+///
+/// See the [module documentation](index.html) for details.
+pub struct ConvertingEventLoop<E>
+where
+    E: EventLoopTrait,
+{
+    event_loop: E,
+    options: Options,
+    streams: Mutex<HashMap<E::StreamId, ConvertingStream<E::StreamId>>>,
+}
+
+struct ConvertingStream<S> {
+    conversion: StreamConversion,
+    // `None` for streams that do not need any conversion.
+    converter: Option<Arc<Mutex<Box<dyn Converter<S>>>>>,
+    // Set if the stream is still called back after `destroy_stream`, e.g. while fading out.
+    destroyed: bool,
+}
+
+impl<E> ConvertingEventLoop<E>
+where
+    E: EventLoopTrait,
+{
+    /// Wraps the given event loop, resampling with `Quality::MEDIUM` and applying
+    /// `Dither::Triangular`.
+    pub fn new(event_loop: E) -> Self {
+        ConvertingEventLoop {
+            event_loop,
+            options: Options {
+                resampler_quality: Quality::MEDIUM,
+                dither: Dither::Triangular,
+            },
+            streams: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Resamples the streams built from now on with the given quality.
+    pub fn with_resampler_quality(mut self, quality: Quality) -> Self {
+        self.options.resampler_quality = quality;
+        self
+    }
+
+    /// Applies the given dither to the streams built from now on.
+    pub fn with_dither(mut self, dither: Dither) -> Self {
+        self.options.dither = dither;
+        self
+    }
+
+    /// The quality with which streams are resampled.
+    #[inline]
+    pub fn resampler_quality(&self) -> Quality {
+        self.options.resampler_quality
+    }
+
+    /// The dither applied when converting to integer samples.
+    #[inline]
+    pub fn dither(&self) -> Dither {
+        self.options.dither
+    }
+
+    /// The wrapped event loop.
+    #[inline]
+    pub fn inner(&self) -> &E {
+        &self.event_loop
+    }
+
+    /// The conversion applied to the stream with the given ID, or `None` if the stream was not
+    /// built by this event loop or has been destroyed.
+    pub fn stream_conversion(&self, stream: E::StreamId) -> Option<StreamConversion> {
+        self.streams.lock().unwrap().get(&stream).map(|s| s.conversion.clone())
+    }
+
+    fn add_stream(
+        &self,
+        stream: E::StreamId,
+        requested: &Format,
+        device: Format,
+        direction: Direction,
+    ) {
+        let conversion = StreamConversion { requested: requested.clone(), device };
+        let converter = if conversion.is_identity() {
+            None
+        } else {
+            let converter = new_converter(&conversion, direction, self.options);
+            Some(Arc::new(Mutex::new(converter)))
+        };
+        let stream_state = ConvertingStream { conversion, converter, destroyed: false };
+        let mut streams = self.streams.lock().unwrap();
+        self.remove_destroyed(&mut streams);
+        streams.insert(stream, stream_state);
+    }
+
+    // Removes the streams that the wrapped event loop has destroyed after a deferred destroy.
+    fn remove_destroyed(&self, streams: &mut HashMap<E::StreamId, ConvertingStream<E::StreamId>>) {
+        let event_loop = &self.event_loop;
+        streams.retain(|stream, s| !s.destroyed || event_loop.is_destroy_pending(stream.clone()));
+    }
+}
+
+impl<E> EventLoopTrait for ConvertingEventLoop<E>
+where
+    E: EventLoopTrait,
+    E::StreamId: Send + 'static,
+{
+    type Device = E::Device;
+    type StreamId = E::StreamId;
+
+    fn build_input_stream(
+        &self,
+        device: &Self::Device,
+        format: &Format,
+    ) -> Result<Self::StreamId, BuildStreamError> {
+        let formats = device.supported_input_formats().map_err(build_stream_err)?;
+        let device_format = device_format(formats, format)?;
+        let stream = self.event_loop.build_input_stream(device, &device_format)?;
+        self.add_stream(stream.clone(), format, device_format, Direction::Input);
+        Ok(stream)
+    }
+
+    fn build_output_stream(
+        &self,
+        device: &Self::Device,
+        format: &Format,
+    ) -> Result<Self::StreamId, BuildStreamError> {
+        let formats = device.supported_output_formats().map_err(build_stream_err)?;
+        let device_format = device_format(formats, format)?;
+        let stream = self.event_loop.build_output_stream(device, &device_format)?;
+        self.add_stream(stream.clone(), format, device_format, Direction::Output);
+        Ok(stream)
+    }
+
+    fn play_stream(&self, stream: Self::StreamId) -> Result<(), PlayStreamError> {
+        self.event_loop.play_stream(stream.clone())?;
+        // Playing the stream may cancel a pending destroy.
+        if !self.event_loop.is_destroy_pending(stream.clone()) {
+            if let Some(s) = self.streams.lock().unwrap().get_mut(&stream) {
+                s.destroyed = false;
+            }
+        }
+        Ok(())
+    }
+
+    fn pause_stream(&self, stream: Self::StreamId) -> Result<(), PauseStreamError> {
+        self.event_loop.pause_stream(stream)
+    }
+
+    fn destroy_stream(&self, stream: Self::StreamId) {
+        self.event_loop.destroy_stream(stream.clone());
+        // The converter is kept for as long as the wrapped event loop calls back for the stream.
+        let mut streams = self.streams.lock().unwrap();
+        if self.event_loop.is_destroy_pending(stream.clone()) {
+            if let Some(s) = streams.get_mut(&stream) {
+                s.destroyed = true;
+            }
+        } else {
+            streams.remove(&stream);
+        }
+        self.remove_destroyed(&mut streams);
+    }
+
+    fn is_destroy_pending(&self, stream: Self::StreamId) -> bool {
+        self.event_loop.is_destroy_pending(stream)
+    }
+
+    fn stream_channel_layout(&self, stream: Self::StreamId) -> Option<ChannelLayout> {
+        self.event_loop.stream_channel_layout(stream)
+    }
+
+    fn run<F>(&self, mut callback: F) -> !
+    where
+        F: FnMut(Self::StreamId, StreamDataResult) + Send,
+    {
+        let streams = &self.streams;
+        self.event_loop.run(move |stream, result| {
+            let data = match result {
+                Ok(data) => data,
+                Err(err) => {
+                    // The stream is removed by the wrapped event loop after unrecoverable errors.
+                    if !err.is_recoverable() {
+                        streams.lock().unwrap().remove(&stream);
+                    }
+                    return callback(stream, Err(err));
+                },
+            };
+            // The converter is cloned out of the map so that the callback may build or destroy
+            // streams without deadlocking.
+            let converter = streams.lock().unwrap()
+                .get(&stream)
+                .and_then(|s| s.converter.clone());
+            let converter = match converter {
+                Some(converter) => converter,
+                None => return callback(stream, Ok(data)),
+            };
+            let mut converter = converter.lock().unwrap();
+            match data {
+                StreamData::Output { buffer } => converter.output(stream, buffer, &mut callback),
+                StreamData::Input { buffer } => converter.input(stream, buffer, &mut callback),
+            }
+        })
+    }
+}
+
+// Selects the format to open the device with: the requested format if the device supports it,
+// otherwise the closest supported format. A request without a channel layout accepts any layout.
+fn device_format<I>(formats: I, requested: &Format) -> Result<Format, BuildStreamError>
+where
+    I: IntoIterator<Item = SupportedFormat>,
+{
+    let formats: Vec<_> = formats.into_iter().collect();
+    let supported = formats.iter().any(|f| {
+        f.channels == requested.channels
+            && f.data_type == requested.data_type
+            && f.min_sample_rate <= requested.sample_rate
+            && requested.sample_rate <= f.max_sample_rate
+            && (requested.channel_layout.is_none() || f.channel_layout == requested.channel_layout)
+    });
+    if supported {
+        return Ok(requested.clone());
+    }
+    let request = FormatRequest {
+        channels: Some(Requirement::Preferred(requested.channels)),
+        sample_rate: Some(Requirement::Preferred(requested.sample_rate)),
+        data_type: Some(Requirement::Preferred(requested.data_type)),
+    };
+    request.closest_format(formats)
+        .map(|closest| closest.format)
+        .ok_or(BuildStreamError::FormatNotSupported)
+}
+
+fn build_stream_err(err: SupportedFormatsError) -> BuildStreamError {
+    match err {
+        SupportedFormatsError::DeviceNotAvailable => BuildStreamError::DeviceNotAvailable,
+        SupportedFormatsError::DeviceBusy { err } => BuildStreamError::DeviceBusy { err },
+        SupportedFormatsError::InvalidArgument => BuildStreamError::InvalidArgument,
+        SupportedFormatsError::BackendSpecific { err } => err.into(),
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Options {
+    resampler_quality: Quality,
+    dither: Dither,
+}
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Input,
+    Output,
+}
+
+// Converts the data of a single stream. Implemented for each sample type of the requested format.
+trait Converter<S>: Send {
+    // Asks the callback for data in the requested format and writes it to the device buffer.
+    fn output(
+        &mut self,
+        stream: S,
+        buffer: UnknownTypeOutputBuffer,
+        callback: &mut dyn FnMut(S, StreamDataResult),
+    );
+
+    // Converts the data of the device buffer and passes it to the callback in the requested format.
+    fn input(
+        &mut self,
+        stream: S,
+        buffer: UnknownTypeInputBuffer,
+        callback: &mut dyn FnMut(S, StreamDataResult),
+    );
+}
+
+fn new_converter<S>(
+    conversion: &StreamConversion,
+    direction: Direction,
+    options: Options,
+) -> Box<dyn Converter<S>> {
+    match conversion.requested.data_type {
+        SampleFormat::I8 => Box::new(TypedConverter::<i8>::new(conversion, direction, options)),
+        SampleFormat::U8 => Box::new(TypedConverter::<u8>::new(conversion, direction, options)),
+        SampleFormat::I16 => Box::new(TypedConverter::<i16>::new(conversion, direction, options)),
+        SampleFormat::U16 => Box::new(TypedConverter::<u16>::new(conversion, direction, options)),
+        SampleFormat::I24 => Box::new(TypedConverter::<I24>::new(conversion, direction, options)),
+        SampleFormat::I24In32 => {
+            Box::new(TypedConverter::<I24In32>::new(conversion, direction, options))
+        },
+        SampleFormat::I32 => Box::new(TypedConverter::<i32>::new(conversion, direction, options)),
+        SampleFormat::U32 => Box::new(TypedConverter::<u32>::new(conversion, direction, options)),
+        SampleFormat::F32 => Box::new(TypedConverter::<f32>::new(conversion, direction, options)),
+        SampleFormat::F64 => Box::new(TypedConverter::<f64>::new(conversion, direction, options)),
+    }
+}
+
+// The sample types that may be passed to the `run` callback.
+pub(crate) trait BufferSample: Sample + Send + 'static {
+    fn input_buffer<'a>(buffer: &'a [Self]) -> UnknownTypeInputBuffer<'a>;
+    fn output_buffer<'a>(buffer: &'a mut [Self]) -> UnknownTypeOutputBuffer<'a>;
+}
+
+macro_rules! impl_buffer_sample {
+    ($($T:ty => $Variant:ident,)*) => {
+        $(
+            impl BufferSample for $T {
+                #[inline]
+                fn input_buffer<'a>(buffer: &'a [Self]) -> UnknownTypeInputBuffer<'a> {
+                    UnknownTypeInputBuffer::$Variant(InputBuffer { buffer })
+                }
+
+                #[inline]
+                fn output_buffer<'a>(buffer: &'a mut [Self]) -> UnknownTypeOutputBuffer<'a> {
+                    UnknownTypeOutputBuffer::$Variant(OutputBuffer { buffer })
+                }
+            }
+        )*
+    };
+}
+
+impl_buffer_sample! {
+    i8 => I8,
+    u8 => U8,
+    i16 => I16,
+    u16 => U16,
+    I24 => I24,
+    I24In32 => I24In32,
+    i32 => I32,
+    u32 => U32,
+    f32 => F32,
+    f64 => F64,
+}
+
+struct TypedConverter<T> {
+    requested_channels: usize,
+    device_channels: usize,
+    // Mixes from the requested to the device channels for output, the other way around for input.
+    // `None` if the channels are passed through unchanged.
+    mixer: Option<ChannelMixer>,
+    // Operates on frames with the requested number of channels.
+    resampler: Option<Resampler>,
+    // Converts to the device format for output, to the requested format for input.
+    ditherer: Ditherer,
+    // Samples exchanged with the callback.
+    user: Vec<T>,
+    // Intermediate buffers of `f32` samples.
+    requested: Vec<f32>,
+    resampled: Vec<f32>,
+    device: Vec<f32>,
+}
+
+impl<T> TypedConverter<T>
+where
+    T: BufferSample,
+{
+    fn new(conversion: &StreamConversion, direction: Direction, options: Options) -> Self {
+        let requested_channels = conversion.requested.channels as usize;
+        let device_channels = conversion.device.channels as usize;
+        let requested_layout = conversion.requested_layout();
+        let device_layout = conversion.device_layout();
+        let mixer = if requested_channels == device_channels && !conversion.mixes_channels() {
+            None
+        } else {
+            let mixer = match direction {
+                Direction::Input => ChannelMixer::between_layouts(&device_layout, &requested_layout),
+                Direction::Output => ChannelMixer::between_layouts(&requested_layout, &device_layout),
+            };
+            Some(mixer.normalized())
+        };
+        let resampler = if conversion.resamples() {
+            let (from, to) = match direction {
+                Direction::Input => (conversion.device.sample_rate, conversion.requested.sample_rate),
+                Direction::Output => (conversion.requested.sample_rate, conversion.device.sample_rate),
+            };
+            Some(Resampler::new(requested_channels, from.0, to.0, options.resampler_quality))
+        } else {
+            None
+        };
+        let ditherer = match direction {
+            Direction::Input => Ditherer::new(options.dither, requested_channels),
+            Direction::Output => Ditherer::new(options.dither, device_channels),
+        };
+        TypedConverter {
+            requested_channels,
+            device_channels,
+            mixer,
+            resampler,
+            ditherer,
+            user: Vec::new(),
+            requested: Vec::new(),
+            resampled: Vec::new(),
+            device: Vec::new(),
+        }
+    }
+
+    fn output_typed<S, D>(
+        &mut self,
+        stream: S,
+        buffer: &mut [D],
+        callback: &mut dyn FnMut(S, StreamDataResult),
+    )
+    where
+        D: Sample,
+    {
+        let frames = buffer.len() / self.device_channels;
+        let requested_frames = match self.resampler {
+            Some(ref resampler) => resampler.input_frames_needed(frames),
+            None => frames,
+        };
+
+        // Silence in case the callback does not write the whole buffer.
+        self.user.clear();
+        self.user.resize(requested_frames * self.requested_channels, T::EQUILIBRIUM);
+        {
+            let buffer = T::output_buffer(&mut self.user);
+            callback(stream, Ok(StreamData::Output { buffer }));
+        }
+        self.requested.clear();
+        self.requested.resize(self.user.len(), 0.0);
+        convert_slice(&self.user, &mut self.requested);
+
+        let requested = match self.resampler {
+            Some(ref mut resampler) => {
+                resampler.push(&self.requested);
+                self.resampled.clear();
+                self.resampled.resize(frames * self.requested_channels, 0.0);
+                resampler.pop(&mut self.resampled);
+                &self.resampled
+            },
+            None => &self.requested,
+        };
+        self.device.clear();
+        self.device.resize(buffer.len(), 0.0);
+        match self.mixer {
+            Some(ref mixer) => {
+                mixer.process(requested, &mut self.device);
+            },
+            None => {
+                let len = requested.len().min(self.device.len());
+                self.device[..len].copy_from_slice(&requested[..len]);
+            },
+        }
+        self.ditherer.process(&self.device, buffer);
+    }
+
+    fn input_typed<S, D>(
+        &mut self,
+        stream: S,
+        buffer: &[D],
+        callback: &mut dyn FnMut(S, StreamDataResult),
+    )
+    where
+        D: Sample,
+    {
+        let frames = buffer.len() / self.device_channels;
+        self.requested.clear();
+        self.requested.resize(frames * self.requested_channels, 0.0);
+        match self.mixer {
+            Some(ref mixer) => {
+                mixer.process(buffer, &mut self.requested);
+            },
+            None => convert_slice(buffer, &mut self.requested),
+        }
+        let requested = match self.resampler {
+            Some(ref mut resampler) => {
+                self.resampled.clear();
+                resampler.process(&self.requested, &mut self.resampled);
+                &self.resampled
+            },
+            None => &self.requested,
+        };
+        if requested.is_empty() {
+            return;
+        }
+        self.user.clear();
+        self.user.resize(requested.len(), T::EQUILIBRIUM);
+        self.ditherer.process(requested, &mut self.user);
+        let buffer = T::input_buffer(&self.user);
+        callback(stream, Ok(StreamData::Input { buffer }));
+    }
+}
+
+impl<S, T> Converter<S> for TypedConverter<T>
+where
+    T: BufferSample,
+{
+    fn output(
+        &mut self,
+        stream: S,
+        buffer: UnknownTypeOutputBuffer,
+        callback: &mut dyn FnMut(S, StreamDataResult),
+    ) {
+        match buffer {
+            UnknownTypeOutputBuffer::I8(mut buffer) => self.output_typed(stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::U8(mut buffer) => self.output_typed(stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::I16(mut buffer) => self.output_typed(stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::U16(mut buffer) => self.output_typed(stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::I24(mut buffer) => self.output_typed(stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::I24In32(mut buffer) => self.output_typed(stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::I32(mut buffer) => self.output_typed(stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::U32(mut buffer) => self.output_typed(stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::F32(mut buffer) => self.output_typed(stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::F64(mut buffer) => self.output_typed(stream, &mut buffer[..], callback),
+        }
+    }
+
+    fn input(
+        &mut self,
+        stream: S,
+        buffer: UnknownTypeInputBuffer,
+        callback: &mut dyn FnMut(S, StreamDataResult),
+    ) {
+        match buffer {
+            UnknownTypeInputBuffer::I8(buffer) => self.input_typed(stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::U8(buffer) => self.input_typed(stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::I16(buffer) => self.input_typed(stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::U16(buffer) => self.input_typed(stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::I24(buffer) => self.input_typed(stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::I24In32(buffer) => self.input_typed(stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::I32(buffer) => self.input_typed(stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::U32(buffer) => self.input_typed(stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::F32(buffer) => self.input_typed(stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::F64(buffer) => self.input_typed(stream, &buffer[..], callback),
+        }
+    }
+}
+
+fn layout_of(format: &Format) -> ChannelLayout {
+    format.channel_layout.clone()
+        .unwrap_or_else(|| ChannelLayout::default_for_channels(format.channels))
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        ChannelLayout, ChannelPosition, Format, InputBuffer, OutputBuffer, SampleFormat, SampleRate,
+        StreamData, SupportedFormat, UnknownTypeInputBuffer, UnknownTypeOutputBuffer,
+    };
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use {
+        BuildStreamError, PauseStreamError, PlayStreamError, StreamDataResult,
+    };
+    use dither::Dither;
+    use host::null;
+    use resampler::{Quality, Resampler};
+    use traits::{EventLoopTrait, StreamIdTrait};
+    use super::{
+        ConvertingEventLoop, Direction, Options, StreamConversion, device_format, new_converter,
+    };
+
+    fn format(channels: u16, rate: u32, data_type: SampleFormat) -> Format {
+        Format { channels, sample_rate: SampleRate(rate), data_type, channel_layout: None }
+    }
+
+    fn supported(channels: u16, rate: u32, data_type: SampleFormat) -> SupportedFormat {
+        format(channels, rate, data_type).into()
+    }
+
+    fn options() -> Options {
+        Options { resampler_quality: Quality::MEDIUM, dither: Dither::Triangular }
+    }
+
+    #[test]
+    fn supported_format_is_used_directly() {
+        let formats = vec![supported(2, 44_100, SampleFormat::I16), supported(2, 48_000, SampleFormat::F32)];
+        let requested = format(2, 48_000, SampleFormat::F32);
+        assert_eq!(device_format(formats, &requested).unwrap(), requested);
+    }
+
+    #[test]
+    fn closest_format_is_used_otherwise() {
+        let formats = vec![supported(6, 48_000, SampleFormat::I16), supported(2, 48_000, SampleFormat::I16)];
+        let requested = format(2, 44_100, SampleFormat::F32);
+        let device = device_format(formats, &requested).unwrap();
+        assert_eq!(device, format(2, 48_000, SampleFormat::I16));
+        let conversion = StreamConversion { requested, device };
+        assert!(conversion.converts_data_type());
+        assert!(!conversion.mixes_channels());
+        assert!(conversion.resamples());
+        assert!(!conversion.is_identity());
+    }
+
+    #[test]
+    fn unsupported_channel_layout_is_mixed() {
+        let rear_first = ChannelLayout::new(vec![
+            ChannelPosition::RearLeft,
+            ChannelPosition::RearRight,
+            ChannelPosition::FrontLeft,
+            ChannelPosition::FrontRight,
+            ChannelPosition::FrontCenter,
+            ChannelPosition::LowFrequency,
+        ]);
+        let mut device = supported(6, 48_000, SampleFormat::F32);
+        device.channel_layout = Some(rear_first.clone());
+        let mut requested = format(6, 48_000, SampleFormat::F32);
+        requested.channel_layout = Some(ChannelLayout::surround_5_1());
+        let device = device_format(vec![device], &requested).unwrap();
+        assert_eq!(device.channel_layout, Some(rear_first.clone()));
+        let conversion = StreamConversion { requested: requested.clone(), device };
+        assert!(conversion.mixes_channels());
+        // The layout of the device is used directly when requested, or when any layout will do.
+        let mut supported_format = supported(6, 48_000, SampleFormat::F32);
+        supported_format.channel_layout = Some(rear_first.clone());
+        requested.channel_layout = Some(rear_first);
+        assert_eq!(device_format(vec![supported_format.clone()], &requested).unwrap(), requested);
+        requested.channel_layout = None;
+        assert_eq!(device_format(vec![supported_format], &requested).unwrap(), requested);
+    }
+
+    #[test]
+    fn channel_layouts_are_compared() {
+        let mut conversion = StreamConversion {
+            requested: format(2, 48_000, SampleFormat::F32),
+            device: format(2, 48_000, SampleFormat::F32),
+        };
+        assert!(conversion.is_identity());
+        conversion.device.channel_layout = Some(ChannelLayout::stereo());
+        assert!(conversion.is_identity());
+        conversion.device.channel_layout = Some(ChannelLayout::new(vec![
+            ChannelPosition::FrontRight,
+            ChannelPosition::FrontLeft,
+        ]));
+        assert!(conversion.mixes_channels());
+    }
+
+    #[test]
+    fn device_layouts_without_speaker_positions() {
+        let mut device = format(2, 48_000, SampleFormat::I16);
+        device.channel_layout = Some(ChannelLayout::new(vec![ChannelPosition::Unknown; 2]));
+        let conversion = StreamConversion {
+            requested: format(2, 48_000, SampleFormat::F32),
+            device,
+        };
+        assert_eq!(conversion.device_layout(), ChannelLayout::stereo());
+        assert!(!conversion.mixes_channels());
+        let mut converter = new_converter::<()>(&conversion, Direction::Output, options());
+        let mut device = vec![0i16; 4];
+        {
+            let buffer = UnknownTypeOutputBuffer::I16(OutputBuffer { buffer: &mut device });
+            converter.output((), buffer, &mut |_, result| match result {
+                Ok(StreamData::Output { buffer: UnknownTypeOutputBuffer::F32(mut buffer) }) => {
+                    buffer.copy_from_slice(&[1.0, -1.0, 0.5, -0.5]);
+                },
+                _ => panic!("unexpected stream data"),
+            });
+        }
+        // Both channels are played at their own level, as without conversion of the channels.
+        let expected = [32767.0, -32768.0, 16383.5, -16384.0];
+        for (&sample, &expected) in device.iter().zip(&expected) {
+            assert!((sample as f32 - expected).abs() <= 2.0, "{} != {}", sample, expected);
+        }
+    }
+
+    #[derive(Clone, Debug, Hash, PartialEq, Eq)]
+    struct StreamId(u32);
+
+    impl StreamIdTrait for StreamId {}
+
+    // Defers destroying its streams while `fading` is set, like a platform `EventLoop` whose
+    // streams fade out.
+    struct FadingEventLoop {
+        fading: AtomicBool,
+    }
+
+    impl EventLoopTrait for FadingEventLoop {
+        type Device = null::Device;
+        type StreamId = StreamId;
+
+        fn build_input_stream(&self, _: &null::Device, _: &Format) -> Result<StreamId, BuildStreamError> {
+            Ok(StreamId(0))
+        }
+
+        fn build_output_stream(&self, _: &null::Device, _: &Format) -> Result<StreamId, BuildStreamError> {
+            Ok(StreamId(0))
+        }
+
+        fn play_stream(&self, _: StreamId) -> Result<(), PlayStreamError> {
+            Ok(())
+        }
+
+        fn pause_stream(&self, _: StreamId) -> Result<(), PauseStreamError> {
+            Ok(())
+        }
+
+        fn destroy_stream(&self, _: StreamId) {}
+
+        fn is_destroy_pending(&self, _: StreamId) -> bool {
+            self.fading.load(Ordering::Relaxed)
+        }
+
+        fn run<F>(&self, _: F) -> !
+        where
+            F: FnMut(StreamId, StreamDataResult) + Send,
+        {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn converters_are_kept_until_the_stream_is_destroyed() {
+        let event_loop = ConvertingEventLoop::new(FadingEventLoop { fading: AtomicBool::new(true) });
+        let requested = format(2, 48_000, SampleFormat::F32);
+        let device = format(2, 48_000, SampleFormat::I16);
+        event_loop.add_stream(StreamId(0), &requested, device, Direction::Output);
+        event_loop.destroy_stream(StreamId(0));
+        // The stream is still called back while fading out.
+        assert!(event_loop.stream_conversion(StreamId(0)).is_some());
+        // Once destroyed, the stream is forgotten the next time streams are built or destroyed.
+        event_loop.inner().fading.store(false, Ordering::Relaxed);
+        event_loop.destroy_stream(StreamId(1));
+        assert!(event_loop.stream_conversion(StreamId(0)).is_none());
+    }
+
+    #[test]
+    fn output_conversion() {
+        let conversion = StreamConversion {
+            requested: format(2, 44_100, SampleFormat::F32),
+            device: format(1, 48_000, SampleFormat::I16),
+        };
+        let mut converter = new_converter::<()>(&conversion, Direction::Output, options());
+        let mut device = vec![0i16; 480];
+        let mut requested_samples = 0;
+        {
+            let buffer = UnknownTypeOutputBuffer::I16(OutputBuffer { buffer: &mut device });
+            converter.output((), buffer, &mut |_, result| match result {
+                Ok(StreamData::Output { buffer: UnknownTypeOutputBuffer::F32(mut buffer) }) => {
+                    requested_samples = buffer.len();
+                    for sample in buffer.iter_mut() {
+                        *sample = 0.5;
+                    }
+                },
+                _ => panic!("unexpected stream data"),
+            });
+        }
+        // 480 frames at 48 kHz take 441 frames at 44.1 kHz, plus the latency of the resampler.
+        let resampler = Resampler::new(2, 44_100, 48_000, Quality::MEDIUM);
+        assert_eq!(requested_samples, 2 * resampler.input_frames_needed(480));
+        assert!(requested_samples > 2 * 441);
+        // After the start of the stream, the device receives the mixed signal.
+        let expected = 0.5 * ::std::i16::MAX as f32;
+        let skip = resampler.latency() * 2;
+        assert!(device[skip..].iter().all(|&sample| (sample as f32 - expected).abs() < 0.01 * expected));
+    }
+
+    #[test]
+    fn input_conversion() {
+        let conversion = StreamConversion {
+            requested: format(2, 48_000, SampleFormat::F32),
+            device: format(2, 48_000, SampleFormat::U16),
+        };
+        let mut converter = new_converter::<()>(&conversion, Direction::Input, options());
+        let device = vec![32768u16; 64];
+        let mut received = None;
+        let buffer = UnknownTypeInputBuffer::U16(InputBuffer { buffer: &device });
+        converter.input((), buffer, &mut |_, result| match result {
+            Ok(StreamData::Input { buffer: UnknownTypeInputBuffer::F32(buffer) }) => {
+                received = Some(buffer.to_vec());
+            },
+            _ => panic!("unexpected stream data"),
+        });
+        assert_eq!(received, Some(vec![0.0; 64]));
+    }
+}
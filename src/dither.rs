@@ -0,0 +1,289 @@
+//! Dithering of `f32` samples when converting them to 8 or 16-bit integer samples.
+//!
+//! Converting a sample to an integer type with fewer bits of precision adds a quantization error.
+//! Without dither this error is correlated with the signal and is heard as distortion on quiet
+//! material. Adding a small amount of noise before rounding decorrelates the error, turning it
+//! into a constant noise floor. Noise shaping additionally moves most of that noise to high
+//! frequencies, where it is less audible.
+//!
+//! ```
+//! use cpal::dither::{Dither, Ditherer};
+//!
+//! let mut ditherer = Ditherer::new(Dither::Triangular, 2);
+//! let input = [0.25f32, -0.25, 0.5, -0.5];
+//! let mut output = [0i16; 4];
+//! ditherer.process(&input, &mut output);
+//! ```
+
+use {Sample, SampleFormat, convert_slice};
+
+/// The kind of dither applied by a `Ditherer`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dither {
+    /// Samples are rounded to the nearest integer value without adding noise.
+    None,
+    /// Rectangular (uniform) noise with a peak amplitude of half a quantization step. This
+    /// decorrelates the mean of the error from the signal, but not its power.
+    Rectangular,
+    /// Triangular (TPDF) noise with a peak amplitude of one quantization step, which fully
+    /// decorrelates the error from the signal.
+    Triangular,
+    /// Triangular noise, with the quantization error fed back through a second-order filter that
+    /// moves the noise towards high frequencies.
+    NoiseShaped,
+}
+
+// The coefficients of the error feedback filter, giving a noise transfer function of
+// `(1 - z^-1)^2`.
+const NOISE_SHAPING: [f32; 2] = [2.0, -1.0];
+
+// The largest quantization error fed back, preventing the filter from running away when the
+// output clips.
+const MAX_ERROR: f32 = 2.0;
+
+/// Converts interleaved `f32` samples to other sample types, applying dither when the target type
+/// is an 8 or 16-bit integer type.
+///
+/// 24 and 32-bit integer types have at least the precision of `f32` samples, so these are converted
+/// directly.
+///
+/// The random generator and the error feedback state of each channel are kept between calls to
+/// `process`, so that consecutive buffers of a stream are treated as one continuous signal.
+#[derive(Clone, Debug)]
+pub struct Ditherer {
+    dither: Dither,
+    channels: usize,
+    rng: u32,
+    // The last two quantization errors of each channel, most recent first.
+    errors: Vec<[f32; 2]>,
+    // The channel of the next sample processed.
+    channel: usize,
+}
+
+impl Ditherer {
+    /// Creates a ditherer for interleaved frames of `channels` samples.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channels` is zero.
+    pub fn new(dither: Dither, channels: usize) -> Self {
+        assert!(channels > 0, "a ditherer needs at least one channel");
+        Ditherer {
+            dither,
+            channels,
+            rng: 0x9e37_79b9,
+            errors: vec![[0.0; 2]; channels],
+            channel: 0,
+        }
+    }
+
+    /// The kind of dither applied.
+    #[inline]
+    pub fn dither(&self) -> Dither {
+        self.dither
+    }
+
+    /// The number of channels per frame.
+    #[inline]
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Clears the error feedback state, e.g. when a stream is restarted.
+    pub fn reset(&mut self) {
+        for errors in &mut self.errors {
+            *errors = [0.0; 2];
+        }
+        self.channel = 0;
+    }
+
+    /// Converts the samples of `input` into `output`, as many as both buffers hold.
+    pub fn process<S>(&mut self, input: &[f32], output: &mut [S])
+    where
+        S: Sample,
+    {
+        match S::get_format() {
+            SampleFormat::I24 |
+            SampleFormat::I24In32 |
+            SampleFormat::I32 |
+            SampleFormat::U32 |
+            SampleFormat::F32 |
+            SampleFormat::F64 => {
+                convert_slice(input, output);
+            },
+            SampleFormat::I8 | SampleFormat::U8 => {
+                for (out, &sample) in output.iter_mut().zip(input) {
+                    let quantized = self.quantize(sample, 127.0);
+                    *out = S::from(&(quantized as i8));
+                }
+            },
+            SampleFormat::I16 | SampleFormat::U16 => {
+                for (out, &sample) in output.iter_mut().zip(input) {
+                    let quantized = self.quantize(sample, 32_767.0);
+                    *out = S::from(&(quantized as i16));
+                }
+            },
+        }
+    }
+
+    // Scales the sample to the range from `-max - 1` to `max`, applies dither and rounds it to an
+    // integer within the range.
+    //
+    // Like `Sample::from`, positive samples are scaled by `max` and negative samples by `max + 1`,
+    // in `f64`, so that no dither gives exactly the same result.
+    fn quantize(&mut self, sample: f32, max: f64) -> f64 {
+        let channel = self.channel;
+        self.channel = (channel + 1) % self.channels;
+
+        let value = if sample >= 0.0 {
+            sample as f64 * max
+        } else {
+            sample as f64 * (max + 1.0)
+        };
+        let (target, noise) = match self.dither {
+            Dither::None => (value, 0.0),
+            Dither::Rectangular => (value, self.uniform() - 0.5),
+            Dither::Triangular => (value, self.uniform() - self.uniform()),
+            Dither::NoiseShaped => {
+                let errors = self.errors[channel];
+                let feedback = NOISE_SHAPING[0] * errors[0] + NOISE_SHAPING[1] * errors[1];
+                (value - feedback as f64, self.uniform() - self.uniform())
+            },
+        };
+        let quantized = (target + noise as f64).round();
+        if self.dither == Dither::NoiseShaped {
+            let error = (quantized - target) as f32;
+            let error = error.signum() * error.abs().min(MAX_ERROR);
+            let errors = &mut self.errors[channel];
+            *errors = [error, errors[0]];
+        }
+        quantized.max(-max - 1.0).min(max)
+    }
+
+    // A uniformly distributed value in `[0, 1)`, from a xorshift generator.
+    #[inline]
+    fn uniform(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32::consts::PI;
+
+    use convert_slice;
+    use super::{Dither, Ditherer};
+
+    const LSB: f32 = 1.0 / 32_768.0;
+
+    fn sine(amplitude: f32, frames: usize) -> Vec<f32> {
+        (0..frames).map(|i| amplitude * (2.0 * PI * i as f32 / 100.0).sin()).collect()
+    }
+
+    fn quantize(dither: Dither, input: &[f32]) -> Vec<i16> {
+        let mut output = vec![0i16; input.len()];
+        Ditherer::new(dither, 1).process(input, &mut output);
+        output
+    }
+
+    // The quantization error of each sample, in quantization steps.
+    fn errors(input: &[f32], output: &[i16]) -> Vec<f32> {
+        let scaled = |x: f32| if x >= 0.0 { x * 32_767.0 } else { x * 32_768.0 };
+        input.iter().zip(output).map(|(&x, &y)| y as f32 - scaled(x)).collect()
+    }
+
+    #[test]
+    fn no_dither_rounds() {
+        let input = [0.4 * LSB, 0.6 * LSB, -0.6 * LSB, 1.0, -1.0, 2.0];
+        assert_eq!(quantize(Dither::None, &input), vec![0, 1, -1, 32_767, -32_768, 32_767]);
+    }
+
+    #[test]
+    fn error_is_bounded() {
+        let input = sine(0.5, 10_000);
+        for &(dither, bound) in &[(Dither::Rectangular, 1.0), (Dither::Triangular, 1.5)] {
+            let errors = errors(&input, &quantize(dither, &input));
+            assert!(errors.iter().all(|e| e.abs() <= bound), "{:?}", dither);
+            let mean = errors.iter().sum::<f32>() / errors.len() as f32;
+            assert!(mean.abs() < 0.05, "{:?}: mean {}", dither, mean);
+        }
+    }
+
+    #[test]
+    fn quiet_signals_are_preserved() {
+        // A sine with a peak below half a quantization step is lost entirely without dither.
+        let input = sine(0.4 * LSB, 100_000);
+        assert!(quantize(Dither::None, &input).iter().all(|&s| s == 0));
+        // With dither, the signal survives on average.
+        for &dither in &[Dither::Triangular, Dither::NoiseShaped] {
+            let output = quantize(dither, &input);
+            let correlation: f32 = input.iter().zip(&output).map(|(&x, &y)| x / LSB * y as f32).sum();
+            let power: f32 = input.iter().map(|&x| (x / LSB) * (x / LSB)).sum();
+            let gain = correlation / power;
+            assert!((gain - 1.0).abs() < 0.1, "{:?}: gain {}", dither, gain);
+        }
+    }
+
+    #[test]
+    fn noise_shaping_reduces_low_frequency_noise() {
+        let input = sine(0.1, 10_000);
+        // The power of the error after a moving average, which passes mainly low frequencies.
+        let low_frequency_power = |dither| {
+            let errors = errors(&input, &quantize(dither, &input));
+            let averaged: Vec<f32> = errors.windows(32).map(|w| w.iter().sum::<f32>() / 32.0).collect();
+            averaged.iter().map(|e| e * e).sum::<f32>() / averaged.len() as f32
+        };
+        let flat = low_frequency_power(Dither::Triangular);
+        let shaped = low_frequency_power(Dither::NoiseShaped);
+        assert!(shaped < flat / 4.0, "flat {}, shaped {}", flat, shaped);
+    }
+
+    #[test]
+    fn state_carries_across_buffers() {
+        let input: Vec<f32> = sine(0.01, 1_000).iter().flat_map(|&s| vec![s, -s]).collect();
+        let mut whole = vec![0i16; input.len()];
+        Ditherer::new(Dither::NoiseShaped, 2).process(&input, &mut whole);
+
+        let mut ditherer = Ditherer::new(Dither::NoiseShaped, 2);
+        let mut parts = vec![0u16; input.len()];
+        // An odd split point also checks that the channel position is kept.
+        ditherer.process(&input[..301], &mut parts[..301]);
+        ditherer.process(&input[301..], &mut parts[301..]);
+        let parts: Vec<i16> = parts.iter().map(|&s| (s as i32 - 32_768) as i16).collect();
+        assert_eq!(parts, whole);
+    }
+
+    #[test]
+    fn no_dither_matches_sample_conversion() {
+        let mut input = sine(1.0, 1_000);
+        input.extend_from_slice(&[0.5, -0.5, 1.0, -1.0, 0.5 / 32_767.0, -0.5 / 32_768.0, 1.5]);
+        let mut expected = vec![0i16; input.len()];
+        convert_slice(&input, &mut expected);
+        assert_eq!(quantize(Dither::None, &input), expected);
+
+        let mut output = vec![0u8; input.len()];
+        Ditherer::new(Dither::None, 1).process(&input, &mut output);
+        let mut expected = vec![0u8; input.len()];
+        convert_slice(&input, &mut expected);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn eight_bit_output() {
+        let input = [0.5f32, -0.5, 1.0, -1.0];
+        let mut output = [0u8; 4];
+        Ditherer::new(Dither::None, 2).process(&input, &mut output);
+        assert_eq!(output, [192, 64, 255, 0]);
+    }
+
+    #[test]
+    fn float_output_is_unchanged() {
+        let input = [0.1f32, -0.2, 0.3];
+        let mut output = [0.0f32; 3];
+        Ditherer::new(Dither::NoiseShaped, 1).process(&input, &mut output);
+        assert_eq!(output, input);
+    }
+}
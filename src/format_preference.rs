@@ -0,0 +1,228 @@
+use std::cmp::Ordering;
+
+use {ChannelCount, Format, SampleFormat, SampleRate, SupportedFormat};
+
+/// A ranking of stream formats, used to select a default format on hosts that do not provide one.
+///
+/// Each list is in order of preference. The `Default` implementation describes the order of
+/// `SupportedFormat::cmp_default_heuristics`: stereo, then mono, then the most channels; `F32`,
+/// then `I16`, then `U16`; and 44.1 kHz, then the maximum sample rate.
+///
+/// For example, to prefer 48 kHz and 6 channels:
+///
+/// ```
+/// # use cpal::{FormatPreference, SampleRate};
+/// let preference = FormatPreference {
+///     channels: vec![6],
+///     sample_rates: vec![SampleRate(48_000)],
+///     ..FormatPreference::default()
+/// };
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormatPreference {
+    /// Preferred channel counts. Other counts rank below these, the most channels first.
+    pub channels: Vec<ChannelCount>,
+    /// Preferred sample formats. Other formats rank below these.
+    pub data_types: Vec<SampleFormat>,
+    /// Preferred sample rates. Formats supporting none of these rank below, the highest maximum
+    /// sample rate first, and are used at their maximum sample rate.
+    pub sample_rates: Vec<SampleRate>,
+}
+
+// The ranking of `FormatPreference::default()`, usable without allocating.
+const DEFAULT_RANKING: Ranking<'static> = Ranking {
+    channels: &[2, 1],
+    data_types: &[SampleFormat::F32, SampleFormat::I16, SampleFormat::U16],
+    sample_rates: &[SampleRate(44_100)],
+};
+
+// The lists of a `FormatPreference`, borrowed.
+struct Ranking<'a> {
+    channels: &'a [ChannelCount],
+    data_types: &'a [SampleFormat],
+    sample_rates: &'a [SampleRate],
+}
+
+impl Default for FormatPreference {
+    fn default() -> Self {
+        FormatPreference {
+            channels: DEFAULT_RANKING.channels.to_vec(),
+            data_types: DEFAULT_RANKING.data_types.to_vec(),
+            sample_rates: DEFAULT_RANKING.sample_rates.to_vec(),
+        }
+    }
+}
+
+// Compares two `SupportedFormat`s by `FormatPreference::default()`.
+pub(crate) fn cmp_default_formats(a: &SupportedFormat, b: &SupportedFormat) -> Ordering {
+    DEFAULT_RANKING.cmp_formats(a, b)
+}
+
+impl FormatPreference {
+    /// Compares two `SupportedFormat`s by this preference, the greater one being preferred.
+    ///
+    /// Channels are compared first, then the sample format and then the sample rate.
+    pub fn cmp_formats(&self, a: &SupportedFormat, b: &SupportedFormat) -> Ordering {
+        self.ranking().cmp_formats(a, b)
+    }
+
+    /// The sample rate to use for the given `SupportedFormat`: the first preferred sample rate
+    /// within its range, or its maximum sample rate.
+    pub fn sample_rate(&self, format: &SupportedFormat) -> SampleRate {
+        match self.ranking().preferred_rate_rank(format) {
+            Some(rank) => self.sample_rates[rank],
+            None => format.max_sample_rate,
+        }
+    }
+
+    /// The preferred format among the given supported formats, or `None` if there are none.
+    pub fn best_format<I>(&self, formats: I) -> Option<Format>
+    where
+        I: IntoIterator<Item = SupportedFormat>,
+    {
+        let best = formats.into_iter().max_by(|a, b| self.cmp_formats(a, b))?;
+        let sample_rate = self.sample_rate(&best);
+        let mut format = best.with_max_sample_rate();
+        format.sample_rate = sample_rate;
+        Some(format)
+    }
+
+    fn ranking(&self) -> Ranking<'_> {
+        Ranking {
+            channels: &self.channels,
+            data_types: &self.data_types,
+            sample_rates: &self.sample_rates,
+        }
+    }
+}
+
+impl<'a> Ranking<'a> {
+    fn cmp_formats(&self, a: &SupportedFormat, b: &SupportedFormat) -> Ordering {
+        // The position in the given list, `None` if absent. Lower positions are preferred.
+        fn rank<T: PartialEq>(list: &[T], value: &T) -> Option<usize> {
+            list.iter().position(|v| v == value)
+        }
+        fn cmp_ranks(a: Option<usize>, b: Option<usize>) -> Ordering {
+            match (a, b) {
+                (Some(a), Some(b)) => b.cmp(&a),
+                (Some(_), None) => Ordering::Greater,
+                (None, Some(_)) => Ordering::Less,
+                (None, None) => Ordering::Equal,
+            }
+        }
+
+        let cmp_channels = cmp_ranks(rank(self.channels, &a.channels), rank(self.channels, &b.channels))
+            .then_with(|| a.channels.cmp(&b.channels));
+        if cmp_channels != Ordering::Equal {
+            return cmp_channels;
+        }
+
+        let cmp_data_type = cmp_ranks(
+            rank(self.data_types, &a.data_type),
+            rank(self.data_types, &b.data_type),
+        );
+        if cmp_data_type != Ordering::Equal {
+            return cmp_data_type;
+        }
+
+        cmp_ranks(self.preferred_rate_rank(a), self.preferred_rate_rank(b))
+            .then_with(|| a.max_sample_rate.cmp(&b.max_sample_rate))
+    }
+
+    fn preferred_rate_rank(&self, format: &SupportedFormat) -> Option<usize> {
+        self.sample_rates.iter()
+            .position(|&rate| format.min_sample_rate <= rate && rate <= format.max_sample_rate)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {Format, SampleFormat, SampleRate, SupportedFormat};
+    use super::FormatPreference;
+
+    fn supported(channels: u16, min: u32, max: u32, data_type: SampleFormat) -> SupportedFormat {
+        SupportedFormat {
+            channels,
+            min_sample_rate: SampleRate(min),
+            max_sample_rate: SampleRate(max),
+            data_type,
+            channel_layout: None,
+        }
+    }
+
+    fn formats() -> Vec<SupportedFormat> {
+        vec![
+            supported(1, 8_000, 96_000, SampleFormat::F32),
+            supported(2, 8_000, 44_100, SampleFormat::I16),
+            supported(2, 8_000, 44_100, SampleFormat::F32),
+            supported(6, 48_000, 48_000, SampleFormat::F32),
+            supported(8, 8_000, 192_000, SampleFormat::I16),
+        ]
+    }
+
+    // The order of the original `cmp_default_heuristics` implementation: stereo, then mono, then the
+    // most channels; `F32`, then `I16`, then `U16`; and rate ranges containing 44.1 kHz, then the
+    // highest maximum rate.
+    #[test]
+    fn default_order() {
+        let ascending = vec![
+            supported(6, 48_000, 48_000, SampleFormat::F32),
+            supported(8, 48_000, 48_000, SampleFormat::I16),
+            supported(1, 8_000, 44_100, SampleFormat::F32),
+            supported(2, 8_000, 44_100, SampleFormat::I32),
+            supported(2, 8_000, 44_100, SampleFormat::U16),
+            supported(2, 8_000, 44_100, SampleFormat::I16),
+            supported(2, 48_000, 48_000, SampleFormat::F32),
+            supported(2, 48_000, 96_000, SampleFormat::F32),
+            supported(2, 8_000, 44_100, SampleFormat::F32),
+            supported(2, 8_000, 96_000, SampleFormat::F32),
+        ];
+        let preference = FormatPreference::default();
+        for (i, a) in ascending.iter().enumerate() {
+            for (j, b) in ascending.iter().enumerate() {
+                let expected = i.cmp(&j);
+                assert_eq!(preference.cmp_formats(a, b), expected, "{:?} and {:?}", a, b);
+                assert_eq!(a.cmp_default_heuristics(b), expected, "{:?} and {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn default_best_format() {
+        let best = FormatPreference::default().best_format(formats()).unwrap();
+        assert_eq!(best, Format {
+            channels: 2,
+            sample_rate: SampleRate(44_100),
+            data_type: SampleFormat::F32,
+            channel_layout: None,
+        });
+    }
+
+    #[test]
+    fn custom_preference() {
+        let preference = FormatPreference {
+            channels: vec![6],
+            sample_rates: vec![SampleRate(48_000)],
+            ..FormatPreference::default()
+        };
+        let best = preference.best_format(formats()).unwrap();
+        assert_eq!(best.channels, 6);
+        assert_eq!(best.sample_rate, SampleRate(48_000));
+
+        // Without a preferred channel count the most channels are preferred.
+        let preference = FormatPreference {
+            channels: vec![],
+            data_types: vec![SampleFormat::I16],
+            sample_rates: vec![SampleRate(48_000)],
+        };
+        let best = preference.best_format(formats()).unwrap();
+        assert_eq!(best.channels, 8);
+        assert_eq!(best.data_type, SampleFormat::I16);
+        assert_eq!(best.sample_rate, SampleRate(48_000));
+    }
+
+    #[test]
+    fn no_formats() {
+        assert_eq!(FormatPreference::default().best_format(vec![]), None);
+    }
+}
@@ -0,0 +1,274 @@
+use std::cmp::{self, Ordering};
+
+use {ChannelCount, Format, SampleFormat, SampleRate, SupportedFormat};
+
+/// How strictly a property of a `FormatRequest` has to be met.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Requirement<T> {
+    /// Only formats with exactly this value are acceptable.
+    Required(T),
+    /// Formats with this value are preferred, but the closest available value is acceptable.
+    Preferred(T),
+}
+
+impl<T: Copy> Requirement<T> {
+    /// The requested value.
+    #[inline]
+    pub fn value(&self) -> T {
+        match *self {
+            Requirement::Required(value) | Requirement::Preferred(value) => value,
+        }
+    }
+
+    /// Whether or not the value is required.
+    #[inline]
+    pub fn is_required(&self) -> bool {
+        match *self {
+            Requirement::Required(_) => true,
+            Requirement::Preferred(_) => false,
+        }
+    }
+}
+
+/// The stream format that a user would like to use, passed to
+/// `DeviceTrait::closest_input_format` and `DeviceTrait::closest_output_format`.
+///
+/// Properties left as `None` are chosen by the `SupportedFormat::cmp_default_heuristics` order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FormatRequest {
+    pub channels: Option<Requirement<ChannelCount>>,
+    pub sample_rate: Option<Requirement<SampleRate>>,
+    pub data_type: Option<Requirement<SampleFormat>>,
+}
+
+/// A property in which the format returned for a `FormatRequest` differs from the request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormatDifference {
+    Channels { requested: ChannelCount, actual: ChannelCount },
+    SampleRate { requested: SampleRate, actual: SampleRate },
+    DataType { requested: SampleFormat, actual: SampleFormat },
+}
+
+/// The supported format closest to a `FormatRequest`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClosestFormat {
+    /// The format to build a stream with.
+    pub format: Format,
+    /// The preferred properties of the request that `format` does not meet. Empty if the format
+    /// matches the request exactly.
+    pub differences: Vec<FormatDifference>,
+}
+
+impl FormatRequest {
+    /// Selects the format closest to the request from the given supported formats.
+    ///
+    /// Formats that do not meet the required properties are ignored. Of the remaining ones, the
+    /// sample rate is matched first, then the number of channels and then the sample format. Ties
+    /// are broken by `SupportedFormat::cmp_default_heuristics`.
+    ///
+    /// Returns `None` if no format meets the required properties.
+    pub fn closest_format<I>(&self, formats: I) -> Option<ClosestFormat>
+    where
+        I: IntoIterator<Item = SupportedFormat>,
+    {
+        let best = formats.into_iter()
+            .filter(|format| self.is_acceptable(format))
+            .max_by(|a, b| self.cmp_closeness(a, b))?;
+        let sample_rate = self.select_sample_rate(&best);
+        let format = Format {
+            channels: best.channels,
+            sample_rate,
+            data_type: best.data_type,
+            channel_layout: best.channel_layout,
+        };
+        let differences = self.differences(&format);
+        Some(ClosestFormat { format, differences })
+    }
+
+    fn is_acceptable(&self, format: &SupportedFormat) -> bool {
+        if let Some(Requirement::Required(channels)) = self.channels {
+            if format.channels != channels {
+                return false;
+            }
+        }
+        if let Some(Requirement::Required(rate)) = self.sample_rate {
+            if rate < format.min_sample_rate || format.max_sample_rate < rate {
+                return false;
+            }
+        }
+        if let Some(Requirement::Required(data_type)) = self.data_type {
+            if format.data_type != data_type {
+                return false;
+            }
+        }
+        true
+    }
+
+    // The concrete sample rate to use from the range of the given format.
+    fn select_sample_rate(&self, format: &SupportedFormat) -> SampleRate {
+        const HZ_44100: SampleRate = SampleRate(44_100);
+        match self.sample_rate {
+            Some(requirement) => {
+                let rate = requirement.value();
+                cmp::max(format.min_sample_rate, cmp::min(rate, format.max_sample_rate))
+            },
+            None if format.min_sample_rate <= HZ_44100 && HZ_44100 <= format.max_sample_rate => {
+                HZ_44100
+            },
+            None => format.max_sample_rate,
+        }
+    }
+
+    // Compares two acceptable formats, the greater one being closer to the request.
+    fn cmp_closeness(&self, a: &SupportedFormat, b: &SupportedFormat) -> Ordering {
+        fn distance(a: u32, b: u32) -> u32 {
+            cmp::max(a, b) - cmp::min(a, b)
+        }
+
+        if let Some(requirement) = self.sample_rate {
+            let rate = requirement.value().0;
+            let distance_a = distance(self.select_sample_rate(a).0, rate);
+            let distance_b = distance(self.select_sample_rate(b).0, rate);
+            let cmp_rate = distance_b.cmp(&distance_a);
+            if cmp_rate != Ordering::Equal {
+                return cmp_rate;
+            }
+        }
+
+        if let Some(requirement) = self.channels {
+            let channels = requirement.value() as u32;
+            let distance_a = distance(a.channels as u32, channels);
+            let distance_b = distance(b.channels as u32, channels);
+            let cmp_channels = distance_b.cmp(&distance_a);
+            if cmp_channels != Ordering::Equal {
+                return cmp_channels;
+            }
+        }
+
+        if let Some(requirement) = self.data_type {
+            let data_type = requirement.value();
+            let cmp_data_type = (a.data_type == data_type).cmp(&(b.data_type == data_type));
+            if cmp_data_type != Ordering::Equal {
+                return cmp_data_type;
+            }
+        }
+
+        a.cmp_default_heuristics(b)
+    }
+
+    fn differences(&self, format: &Format) -> Vec<FormatDifference> {
+        let mut differences = Vec::new();
+        if let Some(requirement) = self.channels {
+            if requirement.value() != format.channels {
+                differences.push(FormatDifference::Channels {
+                    requested: requirement.value(),
+                    actual: format.channels,
+                });
+            }
+        }
+        if let Some(requirement) = self.sample_rate {
+            if requirement.value() != format.sample_rate {
+                differences.push(FormatDifference::SampleRate {
+                    requested: requirement.value(),
+                    actual: format.sample_rate,
+                });
+            }
+        }
+        if let Some(requirement) = self.data_type {
+            if requirement.value() != format.data_type {
+                differences.push(FormatDifference::DataType {
+                    requested: requirement.value(),
+                    actual: format.data_type,
+                });
+            }
+        }
+        differences
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {Format, SampleFormat, SampleRate, SupportedFormat};
+    use super::{ClosestFormat, FormatDifference, FormatRequest, Requirement};
+
+    fn supported(channels: u16, min: u32, max: u32, data_type: SampleFormat) -> SupportedFormat {
+        SupportedFormat {
+            channels,
+            min_sample_rate: SampleRate(min),
+            max_sample_rate: SampleRate(max),
+            data_type,
+            channel_layout: None,
+        }
+    }
+
+    fn formats() -> Vec<SupportedFormat> {
+        vec![
+            supported(1, 8_000, 48_000, SampleFormat::I16),
+            supported(2, 8_000, 48_000, SampleFormat::I16),
+            supported(2, 44_100, 44_100, SampleFormat::F32),
+            supported(6, 48_000, 96_000, SampleFormat::F32),
+        ]
+    }
+
+    fn format(channels: u16, rate: u32, data_type: SampleFormat) -> Format {
+        Format { channels, sample_rate: SampleRate(rate), data_type, channel_layout: None }
+    }
+
+    #[test]
+    fn empty_request_uses_default_heuristics() {
+        let closest = FormatRequest::default().closest_format(formats()).unwrap();
+        assert_eq!(closest, ClosestFormat {
+            format: format(2, 44_100, SampleFormat::F32),
+            differences: vec![],
+        });
+    }
+
+    #[test]
+    fn exact_match() {
+        let request = FormatRequest {
+            channels: Some(Requirement::Preferred(2)),
+            sample_rate: Some(Requirement::Preferred(SampleRate(48_000))),
+            data_type: Some(Requirement::Preferred(SampleFormat::I16)),
+        };
+        let closest = request.closest_format(formats()).unwrap();
+        assert_eq!(closest.format, format(2, 48_000, SampleFormat::I16));
+        assert!(closest.differences.is_empty());
+    }
+
+    #[test]
+    fn sample_rate_takes_precedence() {
+        let request = FormatRequest {
+            channels: Some(Requirement::Preferred(2)),
+            sample_rate: Some(Requirement::Preferred(SampleRate(96_000))),
+            data_type: None,
+        };
+        let closest = request.closest_format(formats()).unwrap();
+        assert_eq!(closest.format, format(6, 96_000, SampleFormat::F32));
+        assert_eq!(closest.differences, vec![FormatDifference::Channels { requested: 2, actual: 6 }]);
+    }
+
+    #[test]
+    fn required_properties_filter_formats() {
+        let request = FormatRequest {
+            channels: Some(Requirement::Required(2)),
+            sample_rate: Some(Requirement::Preferred(SampleRate(96_000))),
+            data_type: Some(Requirement::Preferred(SampleFormat::F32)),
+        };
+        let closest = request.closest_format(formats()).unwrap();
+        assert_eq!(closest.format, format(2, 48_000, SampleFormat::I16));
+        assert_eq!(closest.differences, vec![
+            FormatDifference::SampleRate { requested: SampleRate(96_000), actual: SampleRate(48_000) },
+            FormatDifference::DataType { requested: SampleFormat::F32, actual: SampleFormat::I16 },
+        ]);
+    }
+
+    #[test]
+    fn unsatisfiable_request() {
+        let request = FormatRequest {
+            channels: Some(Requirement::Required(6)),
+            sample_rate: Some(Requirement::Required(SampleRate(44_100))),
+            data_type: None,
+        };
+        assert_eq!(request.closest_format(formats()), None);
+    }
+}
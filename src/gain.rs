@@ -0,0 +1,628 @@
+//! Software gain applied to the data of streams between the host and the `run` callback.
+//!
+//! Changes of the volume are ramped linearly over a configurable duration to avoid the clicks and
+//! zipper noise of abrupt gain changes. Output streams may also fade in when played and fade out
+//! before they are paused or destroyed.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use {
+    Format,
+    StreamData,
+    StreamDataResult,
+    UnknownTypeInputBuffer,
+    UnknownTypeOutputBuffer,
+};
+use conversion::BufferSample;
+
+/// The duration over which volume changes are ramped by default.
+pub const DEFAULT_VOLUME_RAMP: Duration = Duration::from_millis(20);
+
+// The values of `StreamGain::pending`.
+const NO_ACTION: usize = 0;
+const PAUSE: usize = 1;
+const DESTROY: usize = 2;
+
+// What to do with a stream once its output has faded out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FadeAction {
+    Pause,
+    Destroy,
+}
+
+// The volume, mute and fade state of the streams of an event loop.
+pub struct StreamGains<S> {
+    streams: Mutex<HashMap<S, Arc<StreamGain>>>,
+    ramp_micros: AtomicUsize,
+}
+
+struct StreamGain {
+    channels: usize,
+    sample_rate: u32,
+    // Only output streams are faded.
+    output: bool,
+    // The bits of the `f32` volume.
+    volume: AtomicUsize,
+    muted: AtomicBool,
+    fade_micros: AtomicUsize,
+    playing: AtomicBool,
+    // The `FadeAction` to perform once the output has faded out, if any.
+    pending: AtomicUsize,
+    // Set when the stream is played, to fade in from silence.
+    fade_in: AtomicBool,
+    // Only used by the thread running the event loop.
+    state: Mutex<GainState>,
+}
+
+struct GainState {
+    volume: Ramp,
+    fade: Ramp,
+    // A `Vec` of the sample type of the stream, holding input data with the gain applied.
+    scratch: Box<dyn Any + Send>,
+}
+
+impl<S> StreamGains<S>
+where
+    S: Clone + Eq + Hash,
+{
+    pub fn new() -> Self {
+        let gains = StreamGains {
+            streams: Mutex::new(HashMap::new()),
+            ramp_micros: AtomicUsize::new(0),
+        };
+        gains.set_ramp(DEFAULT_VOLUME_RAMP);
+        gains
+    }
+
+    // Starts tracking a paused input stream at full volume.
+    pub fn add_input(&self, stream: S, format: &Format) {
+        self.add(stream, format, false);
+    }
+
+    // Starts tracking a paused output stream at full volume, without fades.
+    pub fn add_output(&self, stream: S, format: &Format) {
+        self.add(stream, format, true);
+    }
+
+    fn add(&self, stream: S, format: &Format, output: bool) {
+        let gain = StreamGain {
+            channels: (format.channels as usize).max(1),
+            sample_rate: format.sample_rate.0,
+            output,
+            volume: AtomicUsize::new(1.0f32.to_bits() as usize),
+            muted: AtomicBool::new(false),
+            fade_micros: AtomicUsize::new(0),
+            playing: AtomicBool::new(false),
+            pending: AtomicUsize::new(NO_ACTION),
+            fade_in: AtomicBool::new(false),
+            state: Mutex::new(GainState {
+                volume: Ramp::new(1.0),
+                fade: Ramp::new(1.0),
+                scratch: Box::new(()),
+            }),
+        };
+        self.streams.lock().unwrap().insert(stream, Arc::new(gain));
+    }
+
+    pub fn remove(&self, stream: &S) {
+        self.streams.lock().unwrap().remove(stream);
+    }
+
+    pub fn set_volume(&self, stream: &S, volume: f32) {
+        // Also maps NaN to silence.
+        let volume = if volume > 0.0 { volume } else { 0.0 };
+        if let Some(gain) = self.get(stream) {
+            gain.volume.store(volume.to_bits() as usize, Ordering::Relaxed);
+        }
+    }
+
+    pub fn volume(&self, stream: &S) -> Option<f32> {
+        self.get(stream).map(|gain| gain.volume())
+    }
+
+    pub fn set_muted(&self, stream: &S, muted: bool) {
+        if let Some(gain) = self.get(stream) {
+            gain.muted.store(muted, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_muted(&self, stream: &S) -> Option<bool> {
+        self.get(stream).map(|gain| gain.muted.load(Ordering::Relaxed))
+    }
+
+    pub fn set_ramp(&self, ramp: Duration) {
+        self.ramp_micros.store(micros(ramp), Ordering::Relaxed);
+    }
+
+    pub fn ramp(&self) -> Duration {
+        Duration::from_micros(self.ramp_micros.load(Ordering::Relaxed) as u64)
+    }
+
+    pub fn set_fade(&self, stream: &S, fade: Duration) {
+        if let Some(gain) = self.get(stream) {
+            gain.fade_micros.store(micros(fade), Ordering::Relaxed);
+        }
+    }
+
+    pub fn fade(&self, stream: &S) -> Option<Duration> {
+        self.get(stream)
+            .map(|gain| Duration::from_micros(gain.fade_micros.load(Ordering::Relaxed) as u64))
+    }
+
+    // Called before the host plays the stream. Cancels a pending pause and fades in, unless the
+    // stream is already playing.
+    pub fn play(&self, stream: &S) {
+        if let Some(gain) = self.get(stream) {
+            let was_playing = gain.playing.swap(true, Ordering::Relaxed);
+            // While the stream is still fading out it fades back in from where it is.
+            let pending = gain.pending.swap(NO_ACTION, Ordering::Relaxed);
+            if !was_playing && pending == NO_ACTION {
+                gain.fade_in.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // Called if the stream could not be played.
+    pub fn stopped(&self, stream: &S) {
+        if let Some(gain) = self.get(stream) {
+            gain.playing.store(false, Ordering::Relaxed);
+            gain.pending.store(NO_ACTION, Ordering::Relaxed);
+        }
+    }
+
+    // Starts fading out the stream before it is paused or destroyed. Returns `false` if the
+    // action should be performed immediately instead.
+    pub fn fade_out(&self, stream: &S, action: FadeAction) -> bool {
+        let gain = match self.get(stream) {
+            Some(gain) => gain,
+            None => return false,
+        };
+        let fades = gain.output
+            && gain.playing.load(Ordering::Relaxed)
+            && gain.fade_micros.load(Ordering::Relaxed) > 0;
+        if !fades {
+            gain.playing.store(false, Ordering::Relaxed);
+            return false;
+        }
+        let pending = match action {
+            FadeAction::Pause => PAUSE,
+            FadeAction::Destroy => DESTROY,
+        };
+        gain.pending.store(pending, Ordering::Relaxed);
+        true
+    }
+
+    // Whether the stream is fading out before it is destroyed.
+    pub fn is_destroy_pending(&self, stream: &S) -> bool {
+        self.get(stream).map(|gain| gain.pending.load(Ordering::Relaxed)) == Some(DESTROY)
+    }
+
+    // Passes the data of a stream between the host and the callback, applying the gain of the
+    // stream to the output written by the callback or to the input before the callback reads it.
+    //
+    // Returns the action to perform on the stream if its output has finished fading out.
+    pub fn process(
+        &self,
+        stream: S,
+        result: StreamDataResult,
+        callback: &mut dyn FnMut(S, StreamDataResult),
+    ) -> Option<FadeAction> {
+        let data = match result {
+            Ok(data) => data,
+            Err(err) => {
+                // The stream is removed by the host after any error but an interruption.
+                if !err.is_recoverable() {
+                    self.remove(&stream);
+                }
+                callback(stream, Err(err));
+                return None;
+            },
+        };
+        // The gain is cloned out of the map so that the callback may build or destroy streams
+        // without deadlocking.
+        let gain = match self.get(&stream) {
+            Some(gain) => gain,
+            None => {
+                callback(stream, Ok(data));
+                return None;
+            },
+        };
+        let mut state = gain.state.lock().unwrap();
+        let ramp_micros = self.ramp_micros.load(Ordering::Relaxed);
+        state.volume.set_target(gain.target(), frames(ramp_micros, gain.sample_rate));
+        if gain.output {
+            gain.update_fade(&mut state);
+        }
+        match data {
+            StreamData::Output { buffer } => gain.output(&mut state, stream, buffer, callback),
+            StreamData::Input { buffer } => gain.input(&mut state, stream, buffer, callback),
+        }
+        if gain.output && state.fade.is_silent() {
+            gain.take_pending()
+        } else {
+            None
+        }
+    }
+
+    fn get(&self, stream: &S) -> Option<Arc<StreamGain>> {
+        self.streams.lock().unwrap().get(stream).cloned()
+    }
+
+}
+
+fn micros(duration: Duration) -> usize {
+    let micros = duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros());
+    if micros > usize::max_value() as u64 {
+        usize::max_value()
+    } else {
+        micros as usize
+    }
+}
+
+// The number of frames that a duration in microseconds takes.
+fn frames(micros: usize, sample_rate: u32) -> usize {
+    (micros as u64 * u64::from(sample_rate) / 1_000_000) as usize
+}
+
+impl StreamGain {
+    fn volume(&self) -> f32 {
+        f32::from_bits(self.volume.load(Ordering::Relaxed) as u32)
+    }
+
+    // The gain that the stream is ramped towards.
+    fn target(&self) -> f32 {
+        if self.muted.load(Ordering::Relaxed) {
+            0.0
+        } else {
+            self.volume()
+        }
+    }
+
+    fn update_fade(&self, state: &mut GainState) {
+        if self.fade_in.swap(false, Ordering::Relaxed) {
+            state.fade = Ramp::new(0.0);
+        }
+        let target = if self.pending.load(Ordering::Relaxed) == NO_ACTION { 1.0 } else { 0.0 };
+        let fade_frames = frames(self.fade_micros.load(Ordering::Relaxed), self.sample_rate);
+        state.fade.set_target(target, fade_frames);
+    }
+
+    // Takes the action to perform now that the output has faded out.
+    fn take_pending(&self) -> Option<FadeAction> {
+        match self.pending.swap(NO_ACTION, Ordering::Relaxed) {
+            PAUSE => {
+                self.playing.store(false, Ordering::Relaxed);
+                Some(FadeAction::Pause)
+            },
+            DESTROY => Some(FadeAction::Destroy),
+            _ => None,
+        }
+    }
+
+    fn output<S>(
+        &self,
+        state: &mut GainState,
+        stream: S,
+        buffer: UnknownTypeOutputBuffer,
+        callback: &mut dyn FnMut(S, StreamDataResult),
+    ) {
+        match buffer {
+            UnknownTypeOutputBuffer::I8(mut buffer) => self.output_typed(state, stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::U8(mut buffer) => self.output_typed(state, stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::I16(mut buffer) => self.output_typed(state, stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::U16(mut buffer) => self.output_typed(state, stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::I24(mut buffer) => self.output_typed(state, stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::I24In32(mut buffer) => self.output_typed(state, stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::I32(mut buffer) => self.output_typed(state, stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::U32(mut buffer) => self.output_typed(state, stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::F32(mut buffer) => self.output_typed(state, stream, &mut buffer[..], callback),
+            UnknownTypeOutputBuffer::F64(mut buffer) => self.output_typed(state, stream, &mut buffer[..], callback),
+        }
+    }
+
+    fn input<S>(
+        &self,
+        state: &mut GainState,
+        stream: S,
+        buffer: UnknownTypeInputBuffer,
+        callback: &mut dyn FnMut(S, StreamDataResult),
+    ) {
+        match buffer {
+            UnknownTypeInputBuffer::I8(buffer) => self.input_typed(state, stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::U8(buffer) => self.input_typed(state, stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::I16(buffer) => self.input_typed(state, stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::U16(buffer) => self.input_typed(state, stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::I24(buffer) => self.input_typed(state, stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::I24In32(buffer) => self.input_typed(state, stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::I32(buffer) => self.input_typed(state, stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::U32(buffer) => self.input_typed(state, stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::F32(buffer) => self.input_typed(state, stream, &buffer[..], callback),
+            UnknownTypeInputBuffer::F64(buffer) => self.input_typed(state, stream, &buffer[..], callback),
+        }
+    }
+
+    fn output_typed<S, T>(
+        &self,
+        state: &mut GainState,
+        stream: S,
+        samples: &mut [T],
+        callback: &mut dyn FnMut(S, StreamDataResult),
+    )
+    where
+        T: BufferSample,
+    {
+        {
+            let buffer = T::output_buffer(samples);
+            callback(stream, Ok(StreamData::Output { buffer }));
+        }
+        apply_gain(samples, self.channels, &mut state.volume, &mut state.fade);
+    }
+
+    fn input_typed<S, T>(
+        &self,
+        state: &mut GainState,
+        stream: S,
+        samples: &[T],
+        callback: &mut dyn FnMut(S, StreamDataResult),
+    )
+    where
+        T: BufferSample,
+    {
+        if state.volume.is_unity() && state.fade.is_unity() {
+            let buffer = T::input_buffer(samples);
+            return callback(stream, Ok(StreamData::Input { buffer }));
+        }
+        if !state.scratch.is::<Vec<T>>() {
+            state.scratch = Box::new(Vec::<T>::new());
+        }
+        let scratch = state.scratch.downcast_mut::<Vec<T>>().unwrap();
+        scratch.clear();
+        scratch.extend_from_slice(samples);
+        apply_gain(scratch, self.channels, &mut state.volume, &mut state.fade);
+        let buffer = T::input_buffer(scratch);
+        callback(stream, Ok(StreamData::Input { buffer }));
+    }
+}
+
+// A gain that moves linearly towards its target, one step per frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Ramp {
+    current: f32,
+    target: f32,
+    step: f32,
+    remaining: usize,
+}
+
+impl Ramp {
+    fn new(gain: f32) -> Self {
+        Ramp { current: gain, target: gain, step: 0.0, remaining: 0 }
+    }
+
+    // Ramps from the current gain to the given one over the given number of frames.
+    fn set_target(&mut self, target: f32, frames: usize) {
+        if target == self.target {
+            return;
+        }
+        self.target = target;
+        if frames == 0 {
+            self.current = target;
+            self.remaining = 0;
+        } else {
+            self.step = (target - self.current) / frames as f32;
+            self.remaining = frames;
+        }
+    }
+
+    // Whether the gain leaves samples unchanged.
+    fn is_unity(&self) -> bool {
+        self.remaining == 0 && self.current == 1.0
+    }
+
+    // Whether the gain silences samples.
+    fn is_silent(&self) -> bool {
+        self.remaining == 0 && self.current == 0.0
+    }
+
+    // Advances the ramp by one frame, returning the gain of the frame.
+    fn next_gain(&mut self) -> f32 {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            self.current = if self.remaining == 0 {
+                self.target
+            } else {
+                self.current + self.step
+            };
+        }
+        self.current
+    }
+}
+
+// Applies the product of the volume and the fade to each frame of samples.
+fn apply_gain<T>(samples: &mut [T], channels: usize, volume: &mut Ramp, fade: &mut Ramp)
+where
+    T: BufferSample,
+{
+    if volume.is_unity() && fade.is_unity() {
+        return;
+    }
+    for frame in samples.chunks_mut(channels) {
+        let gain = volume.next_gain() * fade.next_gain();
+        for sample in frame {
+            *sample = sample.mul_amp(gain);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+    use {
+        Format, InputBuffer, OutputBuffer, SampleFormat, SampleRate, StreamData,
+        UnknownTypeInputBuffer, UnknownTypeOutputBuffer,
+    };
+    use super::{FadeAction, Ramp, StreamGains};
+
+    fn gains(channels: u16) -> StreamGains<u32> {
+        let gains = StreamGains::new();
+        let format = Format {
+            channels,
+            sample_rate: SampleRate(1_000),
+            data_type: SampleFormat::I16,
+            channel_layout: None,
+        };
+        gains.add_output(0, &format);
+        gains
+    }
+
+    // Processes a buffer of output samples that the callback fills with `value`.
+    fn output(gains: &StreamGains<u32>, samples: &mut [i16], value: i16) -> Option<FadeAction> {
+        let buffer = UnknownTypeOutputBuffer::I16(OutputBuffer { buffer: samples });
+        gains.process(0, Ok(StreamData::Output { buffer }), &mut |_, result| match result {
+            Ok(StreamData::Output { buffer: UnknownTypeOutputBuffer::I16(mut buffer) }) => {
+                for sample in buffer.iter_mut() {
+                    *sample = value;
+                }
+            },
+            _ => panic!("unexpected stream data"),
+        })
+    }
+
+    #[test]
+    fn ramp_is_linear() {
+        let mut ramp = Ramp::new(1.0);
+        ramp.set_target(0.0, 4);
+        let gains: Vec<f32> = (0..6).map(|_| ramp.next_gain()).collect();
+        assert_eq!(gains, vec![0.75, 0.5, 0.25, 0.0, 0.0, 0.0]);
+        // A new target starts from the current gain.
+        ramp.set_target(1.0, 2);
+        assert_eq!(ramp.next_gain(), 0.5);
+        ramp.set_target(0.0, 0);
+        assert_eq!(ramp.next_gain(), 0.0);
+    }
+
+    #[test]
+    fn volume_is_ramped_per_frame() {
+        // 4 ms at 1 kHz take 4 frames of 2 channels.
+        let gains = gains(2);
+        gains.set_ramp(Duration::from_millis(4));
+        let mut samples = [0i16; 12];
+        output(&gains, &mut samples, 1000);
+        assert!(samples.iter().all(|&s| s == 1000));
+        gains.set_volume(&0, 0.5);
+        assert_eq!(gains.volume(&0), Some(0.5));
+        output(&gains, &mut samples, 1000);
+        assert_eq!(samples, [875, 875, 750, 750, 625, 625, 500, 500, 500, 500, 500, 500]);
+    }
+
+    #[test]
+    fn muting_keeps_the_volume() {
+        let gains = gains(1);
+        gains.set_ramp(Duration::from_millis(0));
+        gains.set_volume(&0, 0.25);
+        gains.set_muted(&0, true);
+        assert_eq!(gains.is_muted(&0), Some(true));
+        let mut samples = [0i16; 4];
+        output(&gains, &mut samples, 1000);
+        assert_eq!(samples, [0; 4]);
+        gains.set_muted(&0, false);
+        output(&gains, &mut samples, 1000);
+        assert_eq!(samples, [250; 4]);
+        // Negative volumes are treated as silence.
+        gains.set_volume(&0, -1.0);
+        assert_eq!(gains.volume(&0), Some(0.0));
+    }
+
+    #[test]
+    fn input_gain_of_unsigned_samples() {
+        let gains = StreamGains::new();
+        let format = Format {
+            channels: 1,
+            sample_rate: SampleRate(1_000),
+            data_type: SampleFormat::U8,
+            channel_layout: None,
+        };
+        gains.add_input(0u32, &format);
+        gains.set_ramp(Duration::from_millis(0));
+        gains.set_volume(&0, 0.5);
+        let samples = [0x80u8, 0xc0, 0x40];
+        let mut received = None;
+        let buffer = UnknownTypeInputBuffer::U8(InputBuffer { buffer: &samples });
+        gains.process(0, Ok(StreamData::Input { buffer }), &mut |_, result| match result {
+            Ok(StreamData::Input { buffer: UnknownTypeInputBuffer::U8(buffer) }) => {
+                received = Some(buffer.to_vec());
+            },
+            _ => panic!("unexpected stream data"),
+        });
+        assert_eq!(received, Some(vec![0x80, 0xa0, 0x60]));
+    }
+
+    #[test]
+    fn fades_on_play_and_pause() {
+        let gains = gains(1);
+        gains.set_fade(&0, Duration::from_millis(4));
+        assert_eq!(gains.fade(&0), Some(Duration::from_millis(4)));
+        // Paused streams are paused again immediately.
+        assert!(!gains.fade_out(&0, FadeAction::Pause));
+        gains.play(&0);
+        let mut samples = [0i16; 6];
+        assert_eq!(output(&gains, &mut samples, 1000), None);
+        assert_eq!(samples, [250, 500, 750, 1000, 1000, 1000]);
+        assert!(gains.fade_out(&0, FadeAction::Pause));
+        assert_eq!(output(&gains, &mut samples, 1000), Some(FadeAction::Pause));
+        assert_eq!(samples, [750, 500, 250, 0, 0, 0]);
+        assert!(!gains.fade_out(&0, FadeAction::Pause));
+    }
+
+    #[test]
+    fn playing_a_playing_stream_does_not_fade_in_again() {
+        let gains = gains(1);
+        gains.set_fade(&0, Duration::from_millis(4));
+        gains.play(&0);
+        let mut samples = [0i16; 2];
+        output(&gains, &mut samples, 1000);
+        assert_eq!(samples, [250, 500]);
+        // The fade-in continues instead of starting over from silence.
+        gains.play(&0);
+        output(&gains, &mut samples, 1000);
+        assert_eq!(samples, [750, 1000]);
+        gains.play(&0);
+        output(&gains, &mut samples, 1000);
+        assert_eq!(samples, [1000, 1000]);
+    }
+
+    #[test]
+    fn playing_cancels_a_fade_out() {
+        let gains = gains(1);
+        gains.set_fade(&0, Duration::from_millis(4));
+        gains.play(&0);
+        let mut samples = [0i16; 4];
+        output(&gains, &mut samples, 1000);
+        assert!(gains.fade_out(&0, FadeAction::Destroy));
+        assert!(gains.is_destroy_pending(&0));
+        let mut samples = [0i16; 2];
+        assert_eq!(output(&gains, &mut samples, 1000), None);
+        assert_eq!(samples, [750, 500]);
+        // The fade-in continues from the current gain.
+        gains.play(&0);
+        assert!(!gains.is_destroy_pending(&0));
+        assert_eq!(output(&gains, &mut samples, 1000), None);
+        assert_eq!(samples, [625, 750]);
+        assert!(gains.fade_out(&0, FadeAction::Destroy));
+        let mut samples = [0i16; 8];
+        assert_eq!(output(&gains, &mut samples, 1000), Some(FadeAction::Destroy));
+    }
+
+    #[test]
+    fn unknown_streams_are_ignored() {
+        let gains = gains(1);
+        gains.set_volume(&1, 0.5);
+        assert_eq!(gains.volume(&1), None);
+        gains.remove(&0);
+        assert_eq!(gains.volume(&0), None);
+    }
+}
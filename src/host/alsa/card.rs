@@ -0,0 +1,542 @@
+//! Sound cards and the control elements of their drivers.
+//!
+//! Control elements are the raw settings a driver exposes, such as volumes, switches and routing
+//! choices. The simple controls of a `Mixer` are built on top of the elements of the mixer
+//! interface.
+//!
+//! ```no_run
+//! use cpal::platform::alsa::Card;
+//!
+//! for card in Card::all() {
+//!     let info = card.info().unwrap();
+//!     println!("{}: {}", info.index, info.long_name);
+//!     for element in card.elements().unwrap() {
+//!         println!("  {} ({:?}): {:?}", element.name, element.element_type, element.value);
+//!     }
+//! }
+//! ```
+
+use BackendSpecificError;
+use failure::Fail;
+use super::alsa;
+use super::check_errors;
+use super::libc;
+use super::mixer::{Mixer, MixerError};
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+/// An error that may occur while querying a `Card`.
+#[derive(Debug, Fail)]
+pub enum CardError {
+    /// The card no longer exists. This can happen if it is disconnected while the program is
+    /// running.
+    #[fail(display = "The requested device is no longer available. For example, it has been unplugged.")]
+    DeviceNotAvailable,
+    /// See the `BackendSpecificError` docs for more information about this error variant.
+    #[fail(display = "{}", err)]
+    BackendSpecific {
+        #[fail(cause)]
+        err: BackendSpecificError,
+    }
+}
+
+impl From<BackendSpecificError> for CardError {
+    fn from(err: BackendSpecificError) -> Self {
+        CardError::BackendSpecific { err }
+    }
+}
+
+/// A sound card known to ALSA.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Card {
+    index: u32,
+}
+
+/// An iterator over the sound cards of the system, in order of their index.
+pub struct Cards {
+    next: libc::c_int,
+}
+
+/// The identification of a sound card.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CardInfo {
+    /// The index of the card, as in `hw:0`.
+    pub index: u32,
+    /// The identifier of the card, which can be used instead of its index, e.g. "PCH".
+    pub id: String,
+    /// The name of the kernel driver of the card, e.g. "HDA-Intel".
+    pub driver: String,
+    /// The short name of the card, e.g. "HDA Intel PCH".
+    pub name: String,
+    /// The long name of the card, usually including its bus address and interrupt.
+    pub long_name: String,
+    /// The name of the mixer chip, e.g. "Realtek ALC3246".
+    pub mixer_name: String,
+    /// The space-separated list of the card's components.
+    pub components: String,
+}
+
+/// The interface of the driver a control element belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ElementInterface {
+    /// Global settings of the card.
+    Card,
+    /// A hardware dependent device.
+    HwDep,
+    /// The mixer, on which the simple controls of a `Mixer` are built.
+    Mixer,
+    /// A PCM device, e.g. its channel map or IEC958 settings.
+    Pcm,
+    /// A raw MIDI device.
+    RawMidi,
+    /// A timer.
+    Timer,
+    /// The sequencer.
+    Sequencer,
+}
+
+/// The type of the values of a control element, with their range.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ElementType {
+    /// A switch, such as a mute switch.
+    Boolean,
+    /// An integer between `min` and `max`, in multiples of `step` if it is not zero.
+    Integer { min: i64, max: i64, step: i64 },
+    /// An integer with a 64-bit range.
+    Integer64 { min: i64, max: i64, step: i64 },
+    /// One of the named `items`, such as the source of a capture.
+    Enumerated { items: Vec<String> },
+    /// Raw bytes.
+    Bytes,
+    /// IEC958 (S/PDIF) status bits, which are not decoded.
+    Iec958,
+}
+
+/// The current values of a control element, one per channel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ElementValue {
+    /// The values of a `Boolean` element.
+    Boolean(Vec<bool>),
+    /// The values of an `Integer` or `Integer64` element.
+    Integer(Vec<i64>),
+    /// The indices of the selected items of an `Enumerated` element.
+    Enumerated(Vec<u32>),
+    /// The contents of a `Bytes` element.
+    Bytes(Vec<u8>),
+}
+
+/// A control element of a card, as reported when it was listed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Element {
+    /// The number identifying the element within its card.
+    pub numid: u32,
+    /// The interface the element belongs to.
+    pub interface: ElementInterface,
+    /// The name of the element, e.g. "Master Playback Volume".
+    pub name: String,
+    /// The index distinguishing elements with the same name.
+    pub index: u32,
+    /// The device the element applies to, e.g. for PCM elements.
+    pub device: u32,
+    /// The subdevice the element applies to.
+    pub subdevice: u32,
+    /// The type of the values of the element.
+    pub element_type: ElementType,
+    /// The number of values of the element, usually one per channel.
+    pub channels: u32,
+    /// Whether the values can be read.
+    pub readable: bool,
+    /// Whether the values can be changed.
+    pub writable: bool,
+    /// Whether the element currently has no effect, e.g. because of a routing choice.
+    pub inactive: bool,
+    /// The current values, unless the element is not readable or its type is not decoded.
+    pub value: Option<ElementValue>,
+}
+
+/// An iterator over the control elements of a card.
+///
+/// Elements that disappear while iterating are skipped.
+pub struct Elements {
+    ctl: Ctl,
+    list: ElemList,
+    id: ElemId,
+    info: ElemInfo,
+    value: ElemValue,
+    next: u32,
+    used: u32,
+}
+
+impl Card {
+    /// The card with the given index, as in `DeviceInfo::card_index`. The card may not exist.
+    pub fn new(index: u32) -> Card {
+        Card { index }
+    }
+
+    /// All sound cards of the system.
+    pub fn all() -> Cards {
+        Cards { next: -1 }
+    }
+
+    /// The index of the card.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// The identification of the card.
+    pub fn info(&self) -> Result<CardInfo, CardError> {
+        let ctl = Ctl::open(self.index)?;
+        let info = unsafe { CtlCardInfo::of(&ctl)? };
+        Ok(CardInfo {
+            index: self.index,
+            id: info.id(),
+            driver: info.driver(),
+            name: info.name(),
+            long_name: info.long_name(),
+            mixer_name: info.mixer_name(),
+            components: info.components(),
+        })
+    }
+
+    /// The control elements of the card, with their current values.
+    pub fn elements(&self) -> Result<Elements, CardError> {
+        let ctl = Ctl::open(self.index)?;
+        let list = ElemList::new();
+        unsafe {
+            check(alsa::snd_ctl_elem_list(ctl.0, list.0), "list the control elements")?;
+            let count = alsa::snd_ctl_elem_list_get_count(list.0);
+            let result = alsa::snd_ctl_elem_list_alloc_space(list.0, count);
+            check(result, "list the control elements")?;
+            check(alsa::snd_ctl_elem_list(ctl.0, list.0), "list the control elements")?;
+            let used = alsa::snd_ctl_elem_list_get_used(list.0);
+            Ok(Elements {
+                ctl,
+                list,
+                id: ElemId::new(),
+                info: ElemInfo::new(),
+                value: ElemValue::new(),
+                next: 0,
+                used,
+            })
+        }
+    }
+
+    /// Opens the mixer of the card.
+    pub fn mixer(&self) -> Result<Mixer, MixerError> {
+        Mixer::for_card(self.index)
+    }
+}
+
+impl Iterator for Cards {
+    type Item = Card;
+
+    fn next(&mut self) -> Option<Card> {
+        // An error ends the iteration, like running out of cards.
+        let result = unsafe { alsa::snd_card_next(&mut self.next) };
+        if result < 0 || self.next < 0 {
+            self.next = -1;
+            return None;
+        }
+        Some(Card::new(self.next as u32))
+    }
+}
+
+impl ElementInterface {
+    fn from_alsa(interface: alsa::snd_ctl_elem_iface_t) -> Option<ElementInterface> {
+        match interface {
+            alsa::SND_CTL_ELEM_IFACE_CARD => Some(ElementInterface::Card),
+            alsa::SND_CTL_ELEM_IFACE_HWDEP => Some(ElementInterface::HwDep),
+            alsa::SND_CTL_ELEM_IFACE_MIXER => Some(ElementInterface::Mixer),
+            alsa::SND_CTL_ELEM_IFACE_PCM => Some(ElementInterface::Pcm),
+            alsa::SND_CTL_ELEM_IFACE_RAWMIDI => Some(ElementInterface::RawMidi),
+            alsa::SND_CTL_ELEM_IFACE_TIMER => Some(ElementInterface::Timer),
+            alsa::SND_CTL_ELEM_IFACE_SEQUENCER => Some(ElementInterface::Sequencer),
+            _ => None,
+        }
+    }
+}
+
+impl Iterator for Elements {
+    type Item = Element;
+
+    fn next(&mut self) -> Option<Element> {
+        while self.next < self.used {
+            let index = self.next;
+            self.next += 1;
+            if let Some(element) = unsafe { self.element(index) } {
+                return Some(element);
+            }
+        }
+        None
+    }
+}
+
+impl Elements {
+    // Reads the element at the given index of the list, if it still exists.
+    unsafe fn element(&mut self, index: u32) -> Option<Element> {
+        alsa::snd_ctl_elem_list_get_id(self.list.0, index, self.id.0);
+        alsa::snd_ctl_elem_info_set_id(self.info.0, self.id.0);
+        if alsa::snd_ctl_elem_info(self.ctl.0, self.info.0) < 0 {
+            return None;
+        }
+        let info = self.info.0;
+        let interface = ElementInterface::from_alsa(alsa::snd_ctl_elem_info_get_interface(info))?;
+        let element_type = self.element_type()?;
+        let channels = alsa::snd_ctl_elem_info_get_count(info);
+        let readable = alsa::snd_ctl_elem_info_is_readable(info) != 0;
+        let value = if readable {
+            self.value(&element_type, channels)
+        } else {
+            None
+        };
+        Some(Element {
+            numid: alsa::snd_ctl_elem_info_get_numid(info),
+            interface,
+            name: ctl_string(alsa::snd_ctl_elem_info_get_name(info)),
+            index: alsa::snd_ctl_elem_info_get_index(info),
+            device: alsa::snd_ctl_elem_info_get_device(info),
+            subdevice: alsa::snd_ctl_elem_info_get_subdevice(info),
+            element_type,
+            channels,
+            readable,
+            writable: alsa::snd_ctl_elem_info_is_writable(info) != 0,
+            inactive: alsa::snd_ctl_elem_info_is_inactive(info) != 0,
+            value,
+        })
+    }
+
+    unsafe fn element_type(&mut self) -> Option<ElementType> {
+        let info = self.info.0;
+        let element_type = match alsa::snd_ctl_elem_info_get_type(info) {
+            alsa::SND_CTL_ELEM_TYPE_BOOLEAN => ElementType::Boolean,
+            alsa::SND_CTL_ELEM_TYPE_INTEGER => ElementType::Integer {
+                min: i64::from(alsa::snd_ctl_elem_info_get_min(info)),
+                max: i64::from(alsa::snd_ctl_elem_info_get_max(info)),
+                step: i64::from(alsa::snd_ctl_elem_info_get_step(info)),
+            },
+            alsa::SND_CTL_ELEM_TYPE_INTEGER64 => ElementType::Integer64 {
+                min: alsa::snd_ctl_elem_info_get_min64(info),
+                max: alsa::snd_ctl_elem_info_get_max64(info),
+                step: alsa::snd_ctl_elem_info_get_step64(info),
+            },
+            alsa::SND_CTL_ELEM_TYPE_ENUMERATED => {
+                // The name of each item is queried by selecting it in the info.
+                let count = alsa::snd_ctl_elem_info_get_items(info);
+                let mut items = Vec::with_capacity(count as usize);
+                for item in 0..count {
+                    alsa::snd_ctl_elem_info_set_item(info, item);
+                    if alsa::snd_ctl_elem_info(self.ctl.0, info) < 0 {
+                        return None;
+                    }
+                    items.push(ctl_string(alsa::snd_ctl_elem_info_get_item_name(info)));
+                }
+                ElementType::Enumerated { items }
+            },
+            alsa::SND_CTL_ELEM_TYPE_BYTES => ElementType::Bytes,
+            alsa::SND_CTL_ELEM_TYPE_IEC958 => ElementType::Iec958,
+            _ => return None,
+        };
+        Some(element_type)
+    }
+
+    unsafe fn value(&mut self, element_type: &ElementType, channels: u32) -> Option<ElementValue> {
+        let value = self.value.0;
+        alsa::snd_ctl_elem_value_set_id(value, self.id.0);
+        if alsa::snd_ctl_elem_read(self.ctl.0, value) < 0 {
+            return None;
+        }
+        let element_value = match *element_type {
+            ElementType::Boolean => ElementValue::Boolean(
+                (0..channels)
+                    .map(|i| alsa::snd_ctl_elem_value_get_boolean(value, i) != 0)
+                    .collect(),
+            ),
+            ElementType::Integer { .. } => ElementValue::Integer(
+                (0..channels)
+                    .map(|i| i64::from(alsa::snd_ctl_elem_value_get_integer(value, i)))
+                    .collect(),
+            ),
+            ElementType::Integer64 { .. } => ElementValue::Integer(
+                (0..channels)
+                    .map(|i| alsa::snd_ctl_elem_value_get_integer64(value, i))
+                    .collect(),
+            ),
+            ElementType::Enumerated { .. } => ElementValue::Enumerated(
+                (0..channels)
+                    .map(|i| alsa::snd_ctl_elem_value_get_enumerated(value, i))
+                    .collect(),
+            ),
+            ElementType::Bytes => ElementValue::Bytes(
+                (0..channels)
+                    .map(|i| alsa::snd_ctl_elem_value_get_byte(value, i))
+                    .collect(),
+            ),
+            ElementType::Iec958 => return None,
+        };
+        Some(element_value)
+    }
+}
+
+/// An open control interface of a card.
+struct Ctl(*mut alsa::snd_ctl_t);
+
+impl Ctl {
+    fn open(card_index: u32) -> Result<Ctl, CardError> {
+        let name = CString::new(format!("hw:{}", card_index)).unwrap();
+        let mut ctl = ptr::null_mut();
+        match unsafe { alsa::snd_ctl_open(&mut ctl, name.as_ptr(), 0) } {
+            e if e == -libc::ENOENT || e == -libc::ENODEV => Err(CardError::DeviceNotAvailable),
+            e => {
+                check(e, "open the control interface")?;
+                Ok(Ctl(ctl))
+            },
+        }
+    }
+}
+
+impl Drop for Ctl {
+    fn drop(&mut self) {
+        unsafe {
+            alsa::snd_ctl_close(self.0);
+        }
+    }
+}
+
+// Declares an owning wrapper around a control structure allocated by ALSA.
+macro_rules! ctl_struct {
+    ($(#[$attr:meta])* $name:ident, $ty:ident, $malloc:ident, $free:ident) => {
+        $(#[$attr])*
+        struct $name(*mut alsa::$ty);
+
+        impl $name {
+            fn new() -> $name {
+                let mut ptr = ptr::null_mut();
+                unsafe {
+                    check_errors(alsa::$malloc(&mut ptr))
+                        .expect(concat!("unable to allocate ", stringify!($ty)));
+                }
+                $name(ptr)
+            }
+        }
+
+        impl Drop for $name {
+            fn drop(&mut self) {
+                unsafe {
+                    alsa::$free(self.0);
+                }
+            }
+        }
+    };
+}
+
+ctl_struct!(
+    /// Wrapper around `snd_ctl_card_info_t`.
+    CtlCardInfo, snd_ctl_card_info_t, snd_ctl_card_info_malloc, snd_ctl_card_info_free
+);
+ctl_struct!(
+    /// Wrapper around `snd_ctl_elem_id_t`.
+    ElemId, snd_ctl_elem_id_t, snd_ctl_elem_id_malloc, snd_ctl_elem_id_free
+);
+ctl_struct!(
+    /// Wrapper around `snd_ctl_elem_info_t`.
+    ElemInfo, snd_ctl_elem_info_t, snd_ctl_elem_info_malloc, snd_ctl_elem_info_free
+);
+ctl_struct!(
+    /// Wrapper around `snd_ctl_elem_value_t`.
+    ElemValue, snd_ctl_elem_value_t, snd_ctl_elem_value_malloc, snd_ctl_elem_value_free
+);
+
+/// Wrapper around `snd_ctl_elem_list_t`, including the space allocated for its entries.
+struct ElemList(*mut alsa::snd_ctl_elem_list_t);
+
+impl ElemList {
+    fn new() -> ElemList {
+        let mut list = ptr::null_mut();
+        unsafe {
+            check_errors(alsa::snd_ctl_elem_list_malloc(&mut list))
+                .expect("unable to allocate snd_ctl_elem_list_t");
+        }
+        ElemList(list)
+    }
+}
+
+impl Drop for ElemList {
+    fn drop(&mut self) {
+        unsafe {
+            alsa::snd_ctl_elem_list_free_space(self.0);
+            alsa::snd_ctl_elem_list_free(self.0);
+        }
+    }
+}
+
+impl CtlCardInfo {
+    // Fills the info of the card of an open control interface.
+    unsafe fn of(ctl: &Ctl) -> Result<CtlCardInfo, CardError> {
+        let info = CtlCardInfo::new();
+        check(alsa::snd_ctl_card_info(ctl.0, info.0), "retrieve the card info")?;
+        Ok(info)
+    }
+
+    fn id(&self) -> String {
+        unsafe { ctl_string(alsa::snd_ctl_card_info_get_id(self.0)) }
+    }
+
+    fn driver(&self) -> String {
+        unsafe { ctl_string(alsa::snd_ctl_card_info_get_driver(self.0)) }
+    }
+
+    fn name(&self) -> String {
+        unsafe { ctl_string(alsa::snd_ctl_card_info_get_name(self.0)) }
+    }
+
+    fn long_name(&self) -> String {
+        unsafe { ctl_string(alsa::snd_ctl_card_info_get_longname(self.0)) }
+    }
+
+    fn mixer_name(&self) -> String {
+        unsafe { ctl_string(alsa::snd_ctl_card_info_get_mixername(self.0)) }
+    }
+
+    fn components(&self) -> String {
+        unsafe { ctl_string(alsa::snd_ctl_card_info_get_components(self.0)) }
+    }
+}
+
+fn check(result: libc::c_int, what: &str) -> Result<(), CardError> {
+    check_errors(result).map_err(|err| {
+        let description = format!("failed to {}: {}", what, err);
+        BackendSpecificError { description }.into()
+    })
+}
+
+// Copies a string owned by an ALSA control structure.
+unsafe fn ctl_string(ptr: *const libc::c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Card, CardError};
+
+    #[test]
+    fn cards_are_listed_in_order() {
+        let indices: Vec<u32> = Card::all().map(|card| card.index()).collect();
+        assert!(indices.windows(2).all(|w| w[0] < w[1]));
+        for card in Card::all() {
+            assert_eq!(card.info().unwrap().index, card.index());
+        }
+    }
+
+    #[test]
+    fn missing_card() {
+        let card = Card::new(999);
+        match card.info() {
+            Err(CardError::DeviceNotAvailable) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(card.elements().is_err());
+    }
+}
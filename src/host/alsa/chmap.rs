@@ -0,0 +1,148 @@
+//! Conversions between ALSA channel maps and `ChannelLayout`s.
+
+use {ChannelLayout, ChannelPosition};
+use super::alsa;
+use super::check_errors;
+use super::libc;
+use std::slice;
+
+// Flags that ALSA may set on top of a position.
+const SND_CHMAP_POSITION_MASK: libc::c_uint = 0xffff;
+
+// The positions of ALSA in the order of their `SND_CHMAP_*` values, starting at `SND_CHMAP_MONO`.
+const POSITIONS: [ChannelPosition; 35] = [
+    ChannelPosition::Mono,
+    ChannelPosition::FrontLeft,
+    ChannelPosition::FrontRight,
+    ChannelPosition::RearLeft,
+    ChannelPosition::RearRight,
+    ChannelPosition::FrontCenter,
+    ChannelPosition::LowFrequency,
+    ChannelPosition::SideLeft,
+    ChannelPosition::SideRight,
+    ChannelPosition::RearCenter,
+    ChannelPosition::FrontLeftOfCenter,
+    ChannelPosition::FrontRightOfCenter,
+    ChannelPosition::RearLeftOfCenter,
+    ChannelPosition::RearRightOfCenter,
+    ChannelPosition::FrontLeftWide,
+    ChannelPosition::FrontRightWide,
+    ChannelPosition::FrontLeftHigh,
+    ChannelPosition::FrontCenterHigh,
+    ChannelPosition::FrontRightHigh,
+    ChannelPosition::TopCenter,
+    ChannelPosition::TopFrontLeft,
+    ChannelPosition::TopFrontRight,
+    ChannelPosition::TopFrontCenter,
+    ChannelPosition::TopRearLeft,
+    ChannelPosition::TopRearRight,
+    ChannelPosition::TopRearCenter,
+    ChannelPosition::TopFrontLeftOfCenter,
+    ChannelPosition::TopFrontRightOfCenter,
+    ChannelPosition::TopSideLeft,
+    ChannelPosition::TopSideRight,
+    ChannelPosition::LowFrequencyLeft,
+    ChannelPosition::LowFrequencyRight,
+    ChannelPosition::BottomCenter,
+    ChannelPosition::BottomLeftOfCenter,
+    ChannelPosition::BottomRightOfCenter,
+];
+
+fn position_from_alsa(pos: libc::c_uint) -> ChannelPosition {
+    match pos & SND_CHMAP_POSITION_MASK {
+        alsa::SND_CHMAP_NA => ChannelPosition::NotApplicable,
+        pos @ alsa::SND_CHMAP_MONO..=alsa::SND_CHMAP_LAST => {
+            POSITIONS[(pos - alsa::SND_CHMAP_MONO) as usize]
+        },
+        _ => ChannelPosition::Unknown,
+    }
+}
+
+// ALSA has no notion of discrete channels, so these are requested as unknown positions.
+fn position_to_alsa(position: ChannelPosition) -> libc::c_uint {
+    match position {
+        ChannelPosition::Unknown | ChannelPosition::Discrete(_) => alsa::SND_CHMAP_UNKNOWN,
+        ChannelPosition::NotApplicable => alsa::SND_CHMAP_NA,
+        position => {
+            let index = POSITIONS.iter().position(|&p| p == position)
+                .expect("all named positions are listed in `POSITIONS`");
+            alsa::SND_CHMAP_MONO + index as libc::c_uint
+        },
+    }
+}
+
+unsafe fn layout_from_chmap(map: *const alsa::snd_pcm_chmap_t) -> ChannelLayout {
+    let positions = slice::from_raw_parts((*map).pos.as_ptr(), (*map).channels as usize);
+    ChannelLayout::new(positions.iter().map(|&pos| position_from_alsa(pos)).collect())
+}
+
+/// The channel maps offered by the PCM, without duplicates.
+///
+/// Returns an empty list if the PCM does not support channel maps.
+pub unsafe fn query_channel_layouts(pcm_handle: *mut alsa::snd_pcm_t) -> Vec<ChannelLayout> {
+    let maps = alsa::snd_pcm_query_chmaps(pcm_handle);
+    if maps.is_null() {
+        return Vec::new();
+    }
+    let mut layouts = Vec::new();
+    let mut map = maps;
+    while !(*map).is_null() {
+        let layout = layout_from_chmap(&(**map).map);
+        if !layouts.contains(&layout) {
+            layouts.push(layout);
+        }
+        map = map.offset(1);
+    }
+    alsa::snd_pcm_free_chmaps(maps);
+    layouts
+}
+
+/// The channel map currently used by the PCM, if it reports one.
+pub unsafe fn get_channel_layout(pcm_handle: *mut alsa::snd_pcm_t) -> Option<ChannelLayout> {
+    let map = alsa::snd_pcm_get_chmap(pcm_handle);
+    if map.is_null() {
+        return None;
+    }
+    let layout = layout_from_chmap(map);
+    libc::free(map as *mut _);
+    Some(layout)
+}
+
+/// Requests the given channel map. Must be called after the hardware parameters are set.
+pub unsafe fn set_channel_layout(
+    pcm_handle: *mut alsa::snd_pcm_t,
+    layout: &ChannelLayout,
+) -> Result<(), String> {
+    // `snd_pcm_chmap_t` is the channel count followed by the position of each channel.
+    let mut map = Vec::with_capacity(layout.positions().len() + 1);
+    map.push(layout.channels() as libc::c_uint);
+    map.extend(layout.positions().iter().map(|&position| position_to_alsa(position)));
+    let map = map.as_ptr() as *const alsa::snd_pcm_chmap_t;
+    check_errors(alsa::snd_pcm_set_chmap(pcm_handle, map))
+}
+
+#[cfg(test)]
+mod test {
+    use ChannelPosition;
+    use super::{POSITIONS, alsa, position_from_alsa, position_to_alsa};
+
+    #[test]
+    fn named_positions_round_trip() {
+        for &position in POSITIONS.iter() {
+            assert_eq!(position_from_alsa(position_to_alsa(position)), position);
+        }
+        assert_eq!(position_from_alsa(alsa::SND_CHMAP_FC), ChannelPosition::FrontCenter);
+        assert_eq!(position_from_alsa(alsa::SND_CHMAP_LFE), ChannelPosition::LowFrequency);
+        assert_eq!(position_from_alsa(alsa::SND_CHMAP_BRC), ChannelPosition::BottomRightOfCenter);
+    }
+
+    #[test]
+    fn special_positions() {
+        assert_eq!(position_from_alsa(alsa::SND_CHMAP_UNKNOWN), ChannelPosition::Unknown);
+        assert_eq!(position_from_alsa(alsa::SND_CHMAP_NA), ChannelPosition::NotApplicable);
+        assert_eq!(position_from_alsa(alsa::SND_CHMAP_LAST + 1), ChannelPosition::Unknown);
+        assert_eq!(position_to_alsa(ChannelPosition::Discrete(4)), alsa::SND_CHMAP_UNKNOWN);
+        // The phase inverse flag does not change the position.
+        assert_eq!(position_from_alsa(alsa::SND_CHMAP_FL | 0x10000), ChannelPosition::FrontLeft);
+    }
+}
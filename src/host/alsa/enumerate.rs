@@ -1,11 +1,15 @@
-use {BackendSpecificError, DevicesError};
+use {BackendSpecificError, DeviceDirections, DevicesError};
 use super::Device;
 use super::alsa;
 use super::check_errors;
-use std::ffi::CString;
+use super::libc;
+use std::ffi::CStr;
 use std::ptr;
 
 /// ALSA implementation for `Devices`.
+///
+/// Devices are listed from the PCM name hints alone. They are not opened until their formats are
+/// requested, so a device that is busy is still listed.
 pub struct Devices {
     // we keep the original list so that we can pass it to the free function
     global_list: *const *const u8,
@@ -60,38 +64,13 @@ impl Iterator for Devices {
                     return None;
                 }
 
-                let name = {
-                    let n_ptr = alsa::snd_device_name_get_hint(*self.next_str as *const _,
-                                                               b"NAME\0".as_ptr() as *const _);
-                    if !n_ptr.is_null() {
-                        let bytes = CString::from_raw(n_ptr).into_bytes();
-                        let string = String::from_utf8(bytes).unwrap();
-                        Some(string)
-                    } else {
-                        None
-                    }
-                };
-
-                let io = {
-                    let n_ptr = alsa::snd_device_name_get_hint(*self.next_str as *const _,
-                                                               b"IOID\0".as_ptr() as *const _);
-                    if !n_ptr.is_null() {
-                        let bytes = CString::from_raw(n_ptr).into_bytes();
-                        let string = String::from_utf8(bytes).unwrap();
-                        Some(string)
-                    } else {
-                        None
-                    }
-                };
+                let hint = *self.next_str as *const _;
+                let name = get_hint(hint, b"NAME\0");
+                let description = get_hint(hint, b"DESC\0");
+                let io = get_hint(hint, b"IOID\0");
 
                 self.next_str = self.next_str.offset(1);
 
-                if let Some(io) = io {
-                    if io != "Output" {
-                        continue;
-                    }
-                }
-
                 let name = match name {
                     Some(name) => {
                         // Ignoring the `null` device.
@@ -103,47 +82,177 @@ impl Iterator for Devices {
                     _ => continue,
                 };
 
-                // trying to open the PCM device to see if it can be opened
-                let name_zeroed = CString::new(&name[..]).unwrap();
-
-                // See if the device has an available output stream.
-                let mut playback_handle = ptr::null_mut();
-                let has_available_output = alsa::snd_pcm_open(
-                    &mut playback_handle,
-                    name_zeroed.as_ptr() as *const _,
-                    alsa::SND_PCM_STREAM_PLAYBACK,
-                    alsa::SND_PCM_NONBLOCK,
-                ) == 0;
-                if has_available_output {
-                    alsa::snd_pcm_close(playback_handle);
-                }
-
-                // See if the device has an available input stream.
-                let mut capture_handle = ptr::null_mut();
-                let has_available_input = alsa::snd_pcm_open(
-                    &mut capture_handle,
-                    name_zeroed.as_ptr() as *const _,
-                    alsa::SND_PCM_STREAM_CAPTURE,
-                    alsa::SND_PCM_NONBLOCK,
-                ) == 0;
-                if has_available_input {
-                    alsa::snd_pcm_close(capture_handle);
-                }
-
-                if has_available_output || has_available_input {
-                    return Some(Device(name));
-                }
+                return Some(device_from_hint(name, description, io.as_ref().map(|io| &io[..])));
             }
         }
     }
 }
 
+// Creates the device of a PCM from its hints, without opening it.
+fn device_from_hint(name: String, description: Option<String>, io: Option<&str>) -> Device {
+    let directions = directions_from_ioid(io);
+    // Descriptions are split across lines, e.g. "HDA Intel PCH, ALC892 Analog\n
+    // Front speakers".
+    let description = description.map(|desc| desc.replace('\n', ", "));
+    Device::new(name, description, directions)
+}
+
 #[inline]
 pub fn default_input_device() -> Option<Device> {
-    Some(Device("default".to_owned()))
+    Some(Device::default_device())
 }
 
 #[inline]
 pub fn default_output_device() -> Option<Device> {
-    Some(Device("default".to_owned()))
+    Some(Device::default_device())
+}
+
+// Reads the value with the given `id` (e.g. `b"NAME\0"`) from a device name hint.
+unsafe fn get_hint(hint: *const libc::c_void, id: &[u8]) -> Option<String> {
+    let ptr = alsa::snd_device_name_get_hint(hint, id.as_ptr() as *const _);
+    if ptr.is_null() {
+        return None;
+    }
+    let string = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+    libc::free(ptr as *mut _);
+    Some(string)
+}
+
+// The `IOID` hint is absent for devices that support both directions.
+fn directions_from_ioid(io: Option<&str>) -> DeviceDirections {
+    match io {
+        Some("Input") => DeviceDirections { input: true, output: false },
+        Some("Output") => DeviceDirections { input: false, output: true },
+        _ => DeviceDirections { input: true, output: true },
+    }
+}
+
+/// The location of a PCM on the sound hardware, as given by the arguments of its name.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PcmLocation {
+    /// The `CARD` argument, either the index or the id of a card.
+    pub card: Option<String>,
+    /// The `DEV` argument.
+    pub device: Option<u32>,
+    /// The `SUBDEV` argument.
+    pub subdevice: Option<u32>,
+}
+
+// Extracts the card, device and subdevice arguments from a PCM name.
+//
+// PCM names have the form `type:ARG,ARG,...` where each argument is either given as `KEY=VALUE`
+// or positionally, e.g. `hw:CARD=PCH,DEV=3`, `sysdefault:CARD=PCH` or `hw:0,3,1`. The positional
+// arguments of all PCM types that ALSA defines by default are ordered `CARD`, `DEV`, `SUBDEV`.
+pub fn parse_pcm_location(name: &str) -> PcmLocation {
+    let mut location = PcmLocation::default();
+    let args = match name.find(':') {
+        Some(pos) => &name[pos + 1..],
+        None => return location,
+    };
+    for (position, arg) in args.split(',').enumerate() {
+        let (key, value) = match arg.find('=') {
+            Some(pos) => (&arg[..pos], &arg[pos + 1..]),
+            None => match position {
+                0 => ("CARD", arg),
+                1 => ("DEV", arg),
+                2 => ("SUBDEV", arg),
+                _ => continue,
+            },
+        };
+        let value = value.trim_matches('"');
+        match key {
+            "CARD" if !value.is_empty() => location.card = Some(value.to_string()),
+            "DEV" => location.device = value.parse().ok(),
+            "SUBDEV" => location.subdevice = value.parse().ok(),
+            _ => (),
+        }
+    }
+    location
+}
+
+#[cfg(test)]
+mod test {
+    use DeviceDirections;
+    use traits::DeviceTrait;
+    use super::{PcmLocation, device_from_hint, directions_from_ioid, parse_pcm_location};
+
+    #[test]
+    fn directions_follow_ioid() {
+        let input = DeviceDirections { input: true, output: false };
+        let output = DeviceDirections { input: false, output: true };
+        let both = DeviceDirections { input: true, output: true };
+        assert_eq!(directions_from_ioid(Some("Input")), input);
+        assert_eq!(directions_from_ioid(Some("Output")), output);
+        assert_eq!(directions_from_ioid(None), both);
+    }
+
+    // The `null` PCM is defined by the default ALSA configuration and can always be opened in
+    // both directions, so only the hints decide in which directions it is listed.
+    #[test]
+    fn hinted_directions_of_null_pcm() {
+        for &(io, input, output) in &[
+            (Some("Input"), true, false),
+            (Some("Output"), false, true),
+            (None, true, true),
+        ] {
+            let device = device_from_hint("null".to_owned(), None, io);
+            assert_eq!(device.supports_input(), input);
+            assert_eq!(device.supports_output(), output);
+            {
+                // The PCM is not opened by enumeration.
+                let cache = device.supported_formats_cache.lock().unwrap();
+                assert!(cache.input.is_none() && cache.output.is_none());
+            }
+            if input {
+                assert!(device.supported_input_formats().unwrap().count() > 0);
+            }
+            if output {
+                assert!(device.supported_output_formats().unwrap().count() > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_pcm_is_listed_without_opening() {
+        let description = Some("No such\nPCM".to_owned());
+        let device = device_from_hint("cpal_no_such_pcm".to_owned(), description, None);
+        assert!(device.supports_input() && device.supports_output());
+        assert_eq!(device.info().unwrap().description, "No such, PCM");
+        assert!(device.supported_input_formats().is_err());
+        assert!(device.supported_output_formats().is_err());
+    }
+
+    #[test]
+    fn pcm_location_without_arguments() {
+        assert_eq!(parse_pcm_location("default"), PcmLocation::default());
+        assert_eq!(parse_pcm_location("pulse"), PcmLocation::default());
+    }
+
+    #[test]
+    fn pcm_location_with_named_arguments() {
+        assert_eq!(
+            parse_pcm_location("hw:CARD=PCH,DEV=3"),
+            PcmLocation { card: Some("PCH".to_string()), device: Some(3), subdevice: None },
+        );
+        assert_eq!(
+            parse_pcm_location("sysdefault:CARD=PCH"),
+            PcmLocation { card: Some("PCH".to_string()), device: None, subdevice: None },
+        );
+        assert_eq!(
+            parse_pcm_location("plughw:DEV=1,CARD=\"USB\""),
+            PcmLocation { card: Some("USB".to_string()), device: Some(1), subdevice: None },
+        );
+    }
+
+    #[test]
+    fn pcm_location_with_positional_arguments() {
+        assert_eq!(
+            parse_pcm_location("hw:1,0,2"),
+            PcmLocation { card: Some("1".to_string()), device: Some(0), subdevice: Some(2) },
+        );
+        assert_eq!(
+            parse_pcm_location("front:0"),
+            PcmLocation { card: Some("0".to_string()), device: None, subdevice: None },
+        );
+    }
 }
@@ -0,0 +1,544 @@
+//! Hardware volume and mute controls of ALSA sound cards.
+//!
+//! A `Mixer` gives access to the simple controls of a card's mixer, such as "Master" or
+//! "Capture". Changes apply to the card itself, so they affect every program using it.
+//!
+//! ```no_run
+//! use cpal::platform::alsa::Mixer;
+//! use cpal::traits::HostTrait;
+//!
+//! let device = cpal::default_host().default_output_device().expect("no output device");
+//! let mixer = Mixer::for_device(&device).unwrap();
+//! if let Some(control) = mixer.playback_control() {
+//!     control.set_normalized_volume(0.5).unwrap();
+//!     control.set_muted(false).unwrap();
+//! }
+//! ```
+
+use {BackendSpecificError, DeviceInfoError};
+use failure::Fail;
+use super::alsa;
+use super::check_errors;
+use super::libc;
+use traits::DeviceTrait;
+use std::ffi::{CStr, CString};
+use std::marker::PhantomData;
+use std::ptr;
+
+// The minimum of a dB range that mutes the control, in hundredths of a dB.
+const SND_CTL_TLV_DB_GAIN_MUTE: libc::c_long = -9_999_999;
+
+// dB ranges up to this size, in hundredths of a dB, are mapped to normalized volumes linearly.
+const MAX_LINEAR_DB_SCALE: libc::c_long = 2_400;
+
+// The names of the controls preferred as the main control of each direction, in order.
+const PLAYBACK_CONTROLS: [&str; 4] = ["Master", "PCM", "Speaker", "Headphone"];
+const CAPTURE_CONTROLS: [&str; 3] = ["Capture", "Mic", "Internal Mic"];
+
+/// The direction of the signal a mixer control applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlDirection {
+    /// The control applies to output.
+    Playback,
+    /// The control applies to input.
+    Capture,
+}
+
+/// An error that may occur while using a `Mixer`.
+#[derive(Debug, Fail)]
+pub enum MixerError {
+    /// The card no longer exists. This can happen if it is disconnected while the program is
+    /// running.
+    #[fail(display = "The requested device is no longer available. For example, it has been unplugged.")]
+    DeviceNotAvailable,
+    /// The control has no volume, dB scale or mute switch for its direction.
+    #[fail(display = "The mixer control does not support the requested operation.")]
+    NotSupported,
+    /// See the `BackendSpecificError` docs for more information about this error variant.
+    #[fail(display = "{}", err)]
+    BackendSpecific {
+        #[fail(cause)]
+        err: BackendSpecificError,
+    }
+}
+
+impl From<BackendSpecificError> for MixerError {
+    fn from(err: BackendSpecificError) -> Self {
+        MixerError::BackendSpecific { err }
+    }
+}
+
+/// The mixer of an ALSA sound card.
+pub struct Mixer {
+    handle: *mut alsa::snd_mixer_t,
+}
+
+impl Mixer {
+    /// Opens the mixer of the card with the given index, as in `DeviceInfo::card_index`.
+    pub fn for_card(card_index: u32) -> Result<Self, MixerError> {
+        Mixer::open(&format!("hw:{}", card_index))
+    }
+
+    /// Opens the mixer of the default ALSA control device, usually that of the default card.
+    pub fn open_default() -> Result<Self, MixerError> {
+        Mixer::open("default")
+    }
+
+    /// Opens the mixer of the card a device belongs to. The default mixer is opened for devices
+    /// that do not belong to a single card, such as `default`.
+    pub fn for_device<D>(device: &D) -> Result<Self, MixerError>
+    where
+        D: DeviceTrait,
+    {
+        let info = device.info().map_err(|err| match err {
+            DeviceInfoError::DeviceNotAvailable => MixerError::DeviceNotAvailable,
+            DeviceInfoError::BackendSpecific { err } => err.into(),
+        })?;
+        match info.card_index {
+            Some(card_index) => Mixer::for_card(card_index),
+            None => Mixer::open_default(),
+        }
+    }
+
+    fn open(name: &str) -> Result<Self, MixerError> {
+        let name = CString::new(name).expect("mixer names do not contain nul bytes");
+        unsafe {
+            let mut handle = ptr::null_mut();
+            check(alsa::snd_mixer_open(&mut handle, 0), "snd_mixer_open")?;
+            // Closes the handle if any of the following steps fail.
+            let mixer = Mixer { handle };
+            match alsa::snd_mixer_attach(handle, name.as_ptr()) {
+                e if e == -libc::ENOENT || e == -libc::ENODEV => {
+                    return Err(MixerError::DeviceNotAvailable);
+                },
+                e => check(e, "snd_mixer_attach")?,
+            }
+            let result = alsa::snd_mixer_selem_register(handle, ptr::null_mut(), ptr::null_mut());
+            check(result, "snd_mixer_selem_register")?;
+            check(alsa::snd_mixer_load(handle), "snd_mixer_load")?;
+            Ok(mixer)
+        }
+    }
+
+    /// Updates the values reported by the controls with changes made by other programs.
+    pub fn refresh(&self) -> Result<(), MixerError> {
+        let result = unsafe { alsa::snd_mixer_handle_events(self.handle) };
+        check(result, "snd_mixer_handle_events")
+    }
+
+    /// The main control of the given direction: the first of the conventional controls, such as
+    /// "Master" for playback or "Capture" for capture, that has a volume. Otherwise the first
+    /// control with a volume.
+    pub fn playback_control(&self) -> Option<Control<'_>> {
+        self.main_control(ControlDirection::Playback, &PLAYBACK_CONTROLS)
+    }
+
+    /// The main capture control, chosen like the `playback_control`.
+    pub fn capture_control(&self) -> Option<Control<'_>> {
+        self.main_control(ControlDirection::Capture, &CAPTURE_CONTROLS)
+    }
+
+    /// The control with the given name and index, if it applies to the given direction.
+    pub fn control(&self, name: &str, index: u32, direction: ControlDirection) -> Option<Control<'_>> {
+        self.controls(direction).find(|c| c.name() == name && c.index() == index)
+    }
+
+    /// The controls that have a volume or a mute switch for the given direction.
+    pub fn controls(&self, direction: ControlDirection) -> Controls<'_> {
+        Controls {
+            next: unsafe { alsa::snd_mixer_first_elem(self.handle) },
+            direction,
+            mixer: PhantomData,
+        }
+    }
+
+    fn main_control(&self, direction: ControlDirection, names: &[&str]) -> Option<Control<'_>> {
+        let with_volume = || self.controls(direction).filter(Control::has_volume);
+        names.iter()
+            .filter_map(|&name| with_volume().find(|c| c.name() == name && c.index() == 0))
+            .next()
+            .or_else(|| with_volume().next())
+    }
+}
+
+impl Drop for Mixer {
+    fn drop(&mut self) {
+        unsafe {
+            alsa::snd_mixer_close(self.handle);
+        }
+    }
+}
+
+/// An iterator over the controls of a `Mixer`.
+pub struct Controls<'a> {
+    next: *mut alsa::snd_mixer_elem_t,
+    direction: ControlDirection,
+    mixer: PhantomData<&'a Mixer>,
+}
+
+impl<'a> Iterator for Controls<'a> {
+    type Item = Control<'a>;
+
+    fn next(&mut self) -> Option<Control<'a>> {
+        while !self.next.is_null() {
+            let control = Control {
+                elem: self.next,
+                direction: self.direction,
+                mixer: PhantomData,
+            };
+            self.next = unsafe { alsa::snd_mixer_elem_next(self.next) };
+            if control.has_volume() || control.has_switch() {
+                return Some(control);
+            }
+        }
+        None
+    }
+}
+
+// Selects the playback or capture variant of an ALSA simple element function.
+macro_rules! directed {
+    ($control:expr, $playback:ident, $capture:ident) => {
+        match $control.direction {
+            ControlDirection::Playback => alsa::$playback,
+            ControlDirection::Capture => alsa::$capture,
+        }
+    };
+}
+
+/// A simple control of a `Mixer` for one direction, such as "Master" for playback.
+///
+/// Volumes are read from the first channel of the control and set on all its channels.
+#[derive(Clone, Copy)]
+pub struct Control<'a> {
+    elem: *mut alsa::snd_mixer_elem_t,
+    direction: ControlDirection,
+    mixer: PhantomData<&'a Mixer>,
+}
+
+impl<'a> Control<'a> {
+    /// The name of the control, such as "Master".
+    pub fn name(&self) -> String {
+        unsafe {
+            let name = alsa::snd_mixer_selem_get_name(self.elem);
+            CStr::from_ptr(name).to_string_lossy().into_owned()
+        }
+    }
+
+    /// The index distinguishing controls with the same name.
+    pub fn index(&self) -> u32 {
+        unsafe { alsa::snd_mixer_selem_get_index(self.elem) as u32 }
+    }
+
+    /// The direction the control applies to.
+    pub fn direction(&self) -> ControlDirection {
+        self.direction
+    }
+
+    /// Whether the control has a volume.
+    pub fn has_volume(&self) -> bool {
+        let has_volume = directed!(
+            self,
+            snd_mixer_selem_has_playback_volume,
+            snd_mixer_selem_has_capture_volume
+        );
+        unsafe { has_volume(self.elem) != 0 }
+    }
+
+    /// Whether the control has a mute switch.
+    pub fn has_switch(&self) -> bool {
+        let has_switch = directed!(
+            self,
+            snd_mixer_selem_has_playback_switch,
+            snd_mixer_selem_has_capture_switch
+        );
+        unsafe { has_switch(self.elem) != 0 }
+    }
+
+    /// The range of raw volume values, from silent to loudest.
+    pub fn volume_range(&self) -> Result<(i64, i64), MixerError> {
+        self.require_volume()?;
+        let get_range = directed!(
+            self,
+            snd_mixer_selem_get_playback_volume_range,
+            snd_mixer_selem_get_capture_volume_range
+        );
+        let (min, max) = self.range(get_range, "volume range")?;
+        Ok((i64::from(min), i64::from(max)))
+    }
+
+    /// The range of the volume in dB, if the control has a dB scale.
+    ///
+    /// The minimum is negative infinity for controls whose lowest volume mutes the signal.
+    pub fn db_range(&self) -> Result<(f32, f32), MixerError> {
+        let (min, max) = self.raw_db_range()?;
+        Ok((db_from_raw(min), db_from_raw(max)))
+    }
+
+    /// The raw volume.
+    pub fn volume(&self) -> Result<i64, MixerError> {
+        self.require_volume()?;
+        let get_volume = directed!(
+            self,
+            snd_mixer_selem_get_playback_volume,
+            snd_mixer_selem_get_capture_volume
+        );
+        Ok(i64::from(self.value(get_volume, "volume")?))
+    }
+
+    /// Sets the raw volume, which is limited to the `volume_range`.
+    pub fn set_volume(&self, volume: i64) -> Result<(), MixerError> {
+        let (min, max) = self.volume_range()?;
+        let volume = volume.max(min).min(max);
+        let set_volume = directed!(
+            self,
+            snd_mixer_selem_set_playback_volume_all,
+            snd_mixer_selem_set_capture_volume_all
+        );
+        let result = unsafe { set_volume(self.elem, volume as libc::c_long) };
+        check(result, "snd_mixer_selem_set_volume_all")
+    }
+
+    /// The volume in dB.
+    pub fn volume_db(&self) -> Result<f32, MixerError> {
+        self.raw_db_range()?;
+        Ok(db_from_raw(self.raw_db()?))
+    }
+
+    /// Sets the volume in dB, rounding up to the next step of the control.
+    pub fn set_volume_db(&self, db: f32) -> Result<(), MixerError> {
+        let (min, max) = self.raw_db_range()?;
+        let db = ((db as f64 * 100.0).round() as libc::c_long).max(min).min(max);
+        self.set_raw_db(db)
+    }
+
+    /// The volume between 0.0 and 1.0, as shown by `alsamixer`.
+    ///
+    /// Controls with a large dB range are mapped so that equal steps sound roughly equally loud,
+    /// others are mapped linearly from their raw volume.
+    pub fn normalized_volume(&self) -> Result<f32, MixerError> {
+        if let Some((min, max)) = self.mapped_db_range() {
+            return Ok(normalized_from_db(self.raw_db()?, min, max) as f32);
+        }
+        let (min, max) = self.volume_range()?;
+        if max <= min {
+            return Ok(0.0);
+        }
+        Ok(((self.volume()? - min) as f64 / (max - min) as f64) as f32)
+    }
+
+    /// Sets the volume between 0.0 and 1.0, as shown by `alsamixer`.
+    pub fn set_normalized_volume(&self, volume: f32) -> Result<(), MixerError> {
+        let volume = if volume.is_nan() || volume < 0.0 {
+            0.0
+        } else if volume > 1.0 {
+            1.0
+        } else {
+            f64::from(volume)
+        };
+        if let Some((min, max)) = self.mapped_db_range() {
+            let db = db_from_normalized(volume, min, max);
+            return self.set_raw_db(db.max(min).min(max));
+        }
+        let (min, max) = self.volume_range()?;
+        self.set_volume(min + (volume * (max - min) as f64).round() as i64)
+    }
+
+    /// Whether the control is muted.
+    pub fn is_muted(&self) -> Result<bool, MixerError> {
+        self.require_switch()?;
+        let get_switch = directed!(
+            self,
+            snd_mixer_selem_get_playback_switch,
+            snd_mixer_selem_get_capture_switch
+        );
+        let mut value = 0;
+        let result = unsafe { get_switch(self.elem, alsa::SND_MIXER_SCHN_FRONT_LEFT, &mut value) };
+        check(result, "snd_mixer_selem_get_switch")?;
+        // The switch is on while the signal passes.
+        Ok(value == 0)
+    }
+
+    /// Mutes or unmutes the control.
+    pub fn set_muted(&self, muted: bool) -> Result<(), MixerError> {
+        self.require_switch()?;
+        let set_switch = directed!(
+            self,
+            snd_mixer_selem_set_playback_switch_all,
+            snd_mixer_selem_set_capture_switch_all
+        );
+        let result = unsafe { set_switch(self.elem, if muted { 0 } else { 1 }) };
+        check(result, "snd_mixer_selem_set_switch_all")
+    }
+
+    fn require_volume(&self) -> Result<(), MixerError> {
+        if self.has_volume() {
+            Ok(())
+        } else {
+            Err(MixerError::NotSupported)
+        }
+    }
+
+    fn require_switch(&self) -> Result<(), MixerError> {
+        if self.has_switch() {
+            Ok(())
+        } else {
+            Err(MixerError::NotSupported)
+        }
+    }
+
+    fn range(
+        &self,
+        get_range: unsafe extern "C" fn(
+            *mut alsa::snd_mixer_elem_t,
+            *mut libc::c_long,
+            *mut libc::c_long,
+        ) -> libc::c_int,
+        what: &str,
+    ) -> Result<(libc::c_long, libc::c_long), MixerError> {
+        let (mut min, mut max) = (0, 0);
+        let result = unsafe { get_range(self.elem, &mut min, &mut max) };
+        check(result, what)?;
+        Ok((min, max))
+    }
+
+    fn value(
+        &self,
+        get_value: unsafe extern "C" fn(
+            *mut alsa::snd_mixer_elem_t,
+            alsa::snd_mixer_selem_channel_id_t,
+            *mut libc::c_long,
+        ) -> libc::c_int,
+        what: &str,
+    ) -> Result<libc::c_long, MixerError> {
+        let mut value = 0;
+        let result = unsafe { get_value(self.elem, alsa::SND_MIXER_SCHN_FRONT_LEFT, &mut value) };
+        check(result, what)?;
+        Ok(value)
+    }
+
+    // The dB range in hundredths of a dB, failing for controls without a dB scale.
+    fn raw_db_range(&self) -> Result<(libc::c_long, libc::c_long), MixerError> {
+        self.require_volume()?;
+        let get_range = directed!(
+            self,
+            snd_mixer_selem_get_playback_dB_range,
+            snd_mixer_selem_get_capture_dB_range
+        );
+        match self.range(get_range, "dB range") {
+            Ok((min, max)) if min < max => Ok((min, max)),
+            _ => Err(MixerError::NotSupported),
+        }
+    }
+
+    // The dB range if the normalized volume is mapped from it, like `alsamixer` does.
+    fn mapped_db_range(&self) -> Option<(libc::c_long, libc::c_long)> {
+        match self.raw_db_range() {
+            Ok((min, max)) if max - min > MAX_LINEAR_DB_SCALE => Some((min, max)),
+            _ => None,
+        }
+    }
+
+    fn raw_db(&self) -> Result<libc::c_long, MixerError> {
+        let get_db = directed!(
+            self,
+            snd_mixer_selem_get_playback_dB,
+            snd_mixer_selem_get_capture_dB
+        );
+        self.value(get_db, "dB value")
+    }
+
+    fn set_raw_db(&self, db: libc::c_long) -> Result<(), MixerError> {
+        let set_db = directed!(
+            self,
+            snd_mixer_selem_set_playback_dB_all,
+            snd_mixer_selem_set_capture_dB_all
+        );
+        // Round up, so that small increases of the volume are not lost.
+        let result = unsafe { set_db(self.elem, db, 1) };
+        check(result, "snd_mixer_selem_set_dB_all")
+    }
+}
+
+fn check(result: libc::c_int, what: &str) -> Result<(), MixerError> {
+    check_errors(result).map_err(|err| {
+        let description = format!("failed to get or set the mixer {}: {}", what, err);
+        BackendSpecificError { description }.into()
+    })
+}
+
+// Converts hundredths of a dB to dB.
+fn db_from_raw(db: libc::c_long) -> f32 {
+    if db <= SND_CTL_TLV_DB_GAIN_MUTE {
+        f32::NEG_INFINITY
+    } else {
+        db as f32 / 100.0
+    }
+}
+
+// The normalized volume of a dB value, following the mapping of `alsamixer`.
+fn normalized_from_db(db: libc::c_long, min: libc::c_long, max: libc::c_long) -> f64 {
+    let normalized = 10f64.powf((db - max) as f64 / 6000.0);
+    if min == SND_CTL_TLV_DB_GAIN_MUTE {
+        return normalized;
+    }
+    let min_normalized = 10f64.powf((min - max) as f64 / 6000.0);
+    (normalized - min_normalized) / (1.0 - min_normalized)
+}
+
+// The dB value of a normalized volume, the inverse of `normalized_from_db`.
+fn db_from_normalized(normalized: f64, min: libc::c_long, max: libc::c_long) -> libc::c_long {
+    let normalized = if min == SND_CTL_TLV_DB_GAIN_MUTE {
+        normalized
+    } else {
+        let min_normalized = 10f64.powf((min - max) as f64 / 6000.0);
+        normalized * (1.0 - min_normalized) + min_normalized
+    };
+    if normalized <= 0.0 {
+        return min;
+    }
+    (6000.0 * normalized.log10()).round() as libc::c_long + max
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        Mixer,
+        SND_CTL_TLV_DB_GAIN_MUTE,
+        db_from_normalized,
+        db_from_raw,
+        normalized_from_db,
+    };
+
+    #[test]
+    fn normalized_volume_mapping() {
+        // A typical range from -65.25 dB to 0 dB.
+        let (min, max) = (-6525, 0);
+        assert_eq!(normalized_from_db(min, min, max), 0.0);
+        assert_eq!(normalized_from_db(max, min, max), 1.0);
+        assert_eq!(db_from_normalized(0.0, min, max), min);
+        assert_eq!(db_from_normalized(1.0, min, max), max);
+        // Halving the normalized volume lowers it by 18 dB, less the offset of the minimum.
+        let half = db_from_normalized(0.5, min, max);
+        assert!(half > -1700 && half < -1500, "{}", half);
+        for db in (min..=max).step_by(25) {
+            let normalized = normalized_from_db(db, min, max);
+            assert!(normalized >= 0.0 && normalized <= 1.0);
+            assert_eq!(db_from_normalized(normalized, min, max), db);
+        }
+    }
+
+    #[test]
+    fn muting_minimum() {
+        let (min, max) = (SND_CTL_TLV_DB_GAIN_MUTE, 600);
+        assert_eq!(normalized_from_db(min, min, max), 0.0);
+        assert_eq!(normalized_from_db(max, min, max), 1.0);
+        assert_eq!(db_from_normalized(0.0, min, max), min);
+        assert_eq!(db_from_raw(min), f32::NEG_INFINITY);
+        assert_eq!(db_from_raw(-1050), -10.5);
+    }
+
+    #[test]
+    fn missing_card() {
+        assert!(Mixer::for_card(999).is_err());
+    }
+}
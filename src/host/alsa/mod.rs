@@ -3,13 +3,25 @@ extern crate libc;
 
 pub use self::enumerate::{Devices, default_input_device, default_output_device};
 
+use self::card::{Card, CardError};
+use self::chmap::{get_channel_layout, query_channel_layouts, set_channel_layout};
+use self::enumerate::parse_pcm_location;
+
 use ChannelCount;
+use ChannelLayout;
+use BackendErrorCode;
 use BackendSpecificError;
 use BuildStreamError;
 use DefaultFormatError;
+use DeviceId;
+use DeviceDirections;
+use DeviceIdError;
+use DeviceInfo;
+use DeviceInfoError;
 use DeviceNameError;
 use DevicesError;
 use Format;
+use FormatPreference;
 use PauseStreamError;
 use PlayStreamError;
 use SampleFormat;
@@ -23,16 +35,21 @@ use UnknownTypeInputBuffer;
 use UnknownTypeOutputBuffer;
 use traits::{DeviceTrait, EventLoopTrait, HostTrait, StreamIdTrait};
 
-use std::{cmp, ffi, ptr};
-use std::sync::Mutex;
+use std::{cmp, ffi, ptr, thread};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::sync::mpsc::{channel, Sender, Receiver};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 use std::vec::IntoIter as VecIntoIter;
 
 pub type SupportedInputFormats = VecIntoIter<SupportedFormat>;
 pub type SupportedOutputFormats = VecIntoIter<SupportedFormat>;
 
+pub mod card;
+mod chmap;
 mod enumerate;
+pub mod mixer;
 
 /// The default linux and freebsd host type.
 #[derive(Debug)]
@@ -79,6 +96,14 @@ impl DeviceTrait for Device {
         Device::name(self)
     }
 
+    fn id(&self) -> Result<DeviceId, DeviceIdError> {
+        Device::id(self)
+    }
+
+    fn info(&self) -> Result<DeviceInfo, DeviceInfoError> {
+        Device::info(self)
+    }
+
     fn supported_input_formats(&self) -> Result<Self::SupportedInputFormats, SupportedFormatsError> {
         Device::supported_input_formats(self)
     }
@@ -94,8 +119,34 @@ impl DeviceTrait for Device {
     fn default_output_format(&self) -> Result<Format, DefaultFormatError> {
         Device::default_output_format(self)
     }
+
+    fn default_input_format_with(
+        &self,
+        preference: &FormatPreference,
+    ) -> Result<Format, DefaultFormatError> {
+        Device::default_input_format_with(self, preference)
+    }
+
+    fn default_output_format_with(
+        &self,
+        preference: &FormatPreference,
+    ) -> Result<Format, DefaultFormatError> {
+        Device::default_output_format_with(self, preference)
+    }
+
+    fn supports_input(&self) -> bool {
+        self.directions.input
+    }
+
+    fn supports_output(&self) -> bool {
+        self.directions.output
+    }
 }
 
+// `destroy_stream` may be called from within the `run` callback, the stream is removed before
+// the run loop polls it again.
+pub(crate) const CAN_DESTROY_STREAMS_IN_CALLBACK: bool = true;
+
 impl EventLoopTrait for EventLoop {
     type Device = Device;
     type StreamId = StreamId;
@@ -128,6 +179,10 @@ impl EventLoopTrait for EventLoop {
         EventLoop::destroy_stream(self, stream)
     }
 
+    fn stream_channel_layout(&self, stream: Self::StreamId) -> Option<ChannelLayout> {
+        EventLoop::stream_channel_layout(self, stream)
+    }
+
     fn run<F>(&self, callback: F) -> !
     where
         F: FnMut(Self::StreamId, StreamDataResult) + Send,
@@ -179,22 +234,143 @@ impl Drop for Trigger {
 }
 
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Device(String);
+#[derive(Clone, Debug)]
+pub struct Device {
+    // The name of the PCM, used to open it.
+    name: String,
+
+    // The `DESC` hint of the PCM, if the device was found by enumeration.
+    description: Option<String>,
+
+    // The stream directions announced by the `IOID` hint of the PCM.
+    directions: DeviceDirections,
+
+    // The supported formats of each direction, probed the first time they are requested. Shared
+    // between clones of the device.
+    supported_formats_cache: Arc<Mutex<SupportedFormatsCache>>,
+}
+
+#[derive(Debug, Default)]
+struct SupportedFormatsCache {
+    input: Option<Vec<SupportedFormat>>,
+    output: Option<Vec<SupportedFormat>>,
+}
+
+impl PartialEq for Device {
+    #[inline]
+    fn eq(&self, other: &Device) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Device {
+}
 
 impl Device {
+    // The `default` PCM, which does not have any hints.
+    fn default_device() -> Device {
+        let directions = DeviceDirections { input: true, output: true };
+        Device::new("default".to_owned(), None, directions)
+    }
+
+    fn new(name: String, description: Option<String>, directions: DeviceDirections) -> Device {
+        Device {
+            name,
+            description,
+            directions,
+            supported_formats_cache: Arc::new(Mutex::new(SupportedFormatsCache::default())),
+        }
+    }
+
     #[inline]
     fn name(&self) -> Result<String, DeviceNameError> {
-        Ok(self.0.clone())
+        Ok(self.name.clone())
+    }
+
+    // The PCM name is what ALSA uses to open the device, so it is also its identifier.
+    #[inline]
+    fn id(&self) -> Result<DeviceId, DeviceIdError> {
+        Ok(DeviceId::new(crate::HostId::Alsa, self.name.clone()))
+    }
+
+    fn info(&self) -> Result<DeviceInfo, DeviceInfoError> {
+        let location = parse_pcm_location(&self.name);
+
+        // `snd_card_get_index` accepts both card indices and card ids.
+        let card_index = match location.card {
+            Some(card) => {
+                let card = match ffi::CString::new(card) {
+                    Ok(card) => card,
+                    Err(err) => {
+                        let description = format!("invalid card name: {}", err);
+                        let err = BackendSpecificError { description };
+                        return Err(err.into());
+                    },
+                };
+                match unsafe { alsa::snd_card_get_index(card.as_ptr()) } {
+                    index if index >= 0 => Some(index as u32),
+                    _ => return Err(DeviceInfoError::DeviceNotAvailable),
+                }
+            },
+            None => None,
+        };
+
+        let (card_name, driver) = match card_index {
+            Some(index) => {
+                let card_info = Card::new(index).info().map_err(|err| match err {
+                    CardError::DeviceNotAvailable => DeviceInfoError::DeviceNotAvailable,
+                    CardError::BackendSpecific { err } => err.into(),
+                })?;
+                (Some(card_info.name), Some(card_info.driver))
+            },
+            None => (None, None),
+        };
+
+        let description = self.description.clone()
+            .or_else(|| card_name.clone())
+            .unwrap_or_else(|| self.name.clone());
+
+        Ok(DeviceInfo {
+            description,
+            card_name,
+            card_index,
+            driver,
+            device_index: location.device,
+            subdevice_index: location.subdevice,
+            directions: self.directions,
+            is_default: self.name == "default",
+        })
     }
 
+    // Opening a PCM can be slow and may fail while another application uses it, so the formats
+    // are only probed on request. Successful results are cached, errors such as a busy device are
+    // not.
     unsafe fn supported_formats(
         &self,
         stream_t: alsa::snd_pcm_stream_t,
     ) -> Result<VecIntoIter<SupportedFormat>, SupportedFormatsError>
+    {
+        let mut cache = self.supported_formats_cache.lock().unwrap();
+        let cached = if stream_t == alsa::SND_PCM_STREAM_CAPTURE {
+            &mut cache.input
+        } else {
+            &mut cache.output
+        };
+        if let Some(ref formats) = *cached {
+            return Ok(formats.clone().into_iter());
+        }
+        let formats = self.probe_supported_formats(stream_t)?;
+        *cached = Some(formats.clone());
+        Ok(formats.into_iter())
+    }
+
+    unsafe fn probe_supported_formats(
+        &self,
+        stream_t: alsa::snd_pcm_stream_t,
+    ) -> Result<Vec<SupportedFormat>, SupportedFormatsError>
     {
         let mut handle = ptr::null_mut();
-        let device_name = match ffi::CString::new(&self.0[..]) {
+        let device_name = match ffi::CString::new(&self.name[..]) {
             Ok(name) => name,
             Err(err) => {
                 let description = format!("failed to retrieve device name: {}", err);
@@ -209,8 +385,10 @@ impl Device {
             stream_t,
             alsa::SND_PCM_NONBLOCK,
         ) {
-            -2 |
-            -16 /* determined empirically */ => return Err(SupportedFormatsError::DeviceNotAvailable),
+            -2 => return Err(SupportedFormatsError::DeviceNotAvailable),
+            e @ -16 /* EBUSY */ => {
+                return Err(SupportedFormatsError::DeviceBusy { err: error_code(e) });
+            },
             -22 => return Err(SupportedFormatsError::InvalidArgument),
             e => if let Err(description) = check_errors(e) {
                 let err = BackendSpecificError { description };
@@ -227,54 +405,33 @@ impl Device {
             Ok(_) => (),
         };
 
-        // TODO: check endianess
-        const FORMATS: [(SampleFormat, alsa::snd_pcm_format_t); 3] =
-            [
-                //SND_PCM_FORMAT_S8,
-                //SND_PCM_FORMAT_U8,
-                (SampleFormat::I16, alsa::SND_PCM_FORMAT_S16_LE),
-                //SND_PCM_FORMAT_S16_BE,
-                (SampleFormat::U16, alsa::SND_PCM_FORMAT_U16_LE),
-                //SND_PCM_FORMAT_U16_BE,
-            /*SND_PCM_FORMAT_S24_LE,
-            SND_PCM_FORMAT_S24_BE,
-            SND_PCM_FORMAT_U24_LE,
-            SND_PCM_FORMAT_U24_BE,
-            SND_PCM_FORMAT_S32_LE,
-            SND_PCM_FORMAT_S32_BE,
-            SND_PCM_FORMAT_U32_LE,
-            SND_PCM_FORMAT_U32_BE,*/
-                (SampleFormat::F32, alsa::SND_PCM_FORMAT_FLOAT_LE) /*SND_PCM_FORMAT_FLOAT_BE,
-            SND_PCM_FORMAT_FLOAT64_LE,
-            SND_PCM_FORMAT_FLOAT64_BE,
-            SND_PCM_FORMAT_IEC958_SUBFRAME_LE,
-            SND_PCM_FORMAT_IEC958_SUBFRAME_BE,
-            SND_PCM_FORMAT_MU_LAW,
-            SND_PCM_FORMAT_A_LAW,
-            SND_PCM_FORMAT_IMA_ADPCM,
-            SND_PCM_FORMAT_MPEG,
-            SND_PCM_FORMAT_GSM,
-            SND_PCM_FORMAT_SPECIAL,
-            SND_PCM_FORMAT_S24_3LE,
-            SND_PCM_FORMAT_S24_3BE,
-            SND_PCM_FORMAT_U24_3LE,
-            SND_PCM_FORMAT_U24_3BE,
-            SND_PCM_FORMAT_S20_3LE,
-            SND_PCM_FORMAT_S20_3BE,
-            SND_PCM_FORMAT_U20_3LE,
-            SND_PCM_FORMAT_U20_3BE,
-            SND_PCM_FORMAT_S18_3LE,
-            SND_PCM_FORMAT_S18_3BE,
-            SND_PCM_FORMAT_U18_3LE,
-            SND_PCM_FORMAT_U18_3BE,*/,
-            ];
-
+        // Formats without a `SampleFormat`, such as 20-bit or compressed formats, are not probed.
+        const FORMATS: [SampleFormat; 10] = [
+            SampleFormat::I8,
+            SampleFormat::U8,
+            SampleFormat::I16,
+            SampleFormat::U16,
+            SampleFormat::I24,
+            SampleFormat::I24In32,
+            SampleFormat::I32,
+            SampleFormat::U32,
+            SampleFormat::F32,
+            SampleFormat::F64,
+        ];
+
+        // Formats supported only in the opposite byte order are reported in native byte order,
+        // as streams swap the bytes of their samples.
         let mut supported_formats = Vec::new();
-        for &(sample_format, alsa_format) in FORMATS.iter() {
-            if alsa::snd_pcm_hw_params_test_format(handle,
-                                                   hw_params.0,
-                                                   alsa_format) == 0
-            {
+        for &sample_format in FORMATS.iter() {
+            let native = alsa_format(sample_format);
+            let supported = alsa::snd_pcm_hw_params_test_format(handle, hw_params.0, native) == 0
+                || match swapped_alsa_format(sample_format) {
+                    Some(swapped) => {
+                        alsa::snd_pcm_hw_params_test_format(handle, hw_params.0, swapped) == 0
+                    },
+                    None => false,
+                };
+            if supported {
                 supported_formats.push(sample_format);
             }
         }
@@ -372,16 +529,34 @@ impl Device {
             })
             .collect::<Vec<_>>();
 
+        // Each channel count is listed once per channel map offered for it, or once without a
+        // layout if the PCM does not offer any.
+        let channel_layouts = query_channel_layouts(handle);
+        let supported_channels = supported_channels.into_iter()
+            .flat_map(|channels| {
+                let mut layouts: Vec<_> = channel_layouts.iter()
+                    .filter(|layout| layout.channels() == channels)
+                    .cloned()
+                    .map(Some)
+                    .collect();
+                if layouts.is_empty() {
+                    layouts.push(None);
+                }
+                layouts.into_iter().map(move |layout| (channels, layout))
+            })
+            .collect::<Vec<_>>();
+
         let mut output = Vec::with_capacity(supported_formats.len() * supported_channels.len() *
                                                 sample_rates.len());
         for &data_type in supported_formats.iter() {
-            for channels in supported_channels.iter() {
+            for &(channels, ref channel_layout) in supported_channels.iter() {
                 for &(min_rate, max_rate) in sample_rates.iter() {
                     output.push(SupportedFormat {
-                                    channels: channels.clone(),
+                                    channels,
                                     min_sample_rate: SampleRate(min_rate as u32),
                                     max_sample_rate: SampleRate(max_rate as u32),
                                     data_type: data_type,
+                                    channel_layout: channel_layout.clone(),
                                 });
                 }
             }
@@ -389,7 +564,7 @@ impl Device {
 
         // TODO: RAII
         alsa::snd_pcm_close(handle);
-        Ok(output.into_iter())
+        Ok(output)
     }
 
     fn supported_input_formats(&self) -> Result<SupportedInputFormats, SupportedFormatsError> {
@@ -404,18 +579,22 @@ impl Device {
         }
     }
 
-    // ALSA does not offer default stream formats, so instead we compare all supported formats by
-    // the `SupportedFormat::cmp_default_heuristics` order and select the greatest.
+    // ALSA does not offer default stream formats, so instead we select the supported format
+    // ranked highest by the given preference.
     fn default_format(
         &self,
         stream_t: alsa::snd_pcm_stream_t,
+        preference: &FormatPreference,
     ) -> Result<Format, DefaultFormatError>
     {
-        let mut formats: Vec<_> = unsafe {
+        let formats = unsafe {
             match self.supported_formats(stream_t) {
                 Err(SupportedFormatsError::DeviceNotAvailable) => {
                     return Err(DefaultFormatError::DeviceNotAvailable);
                 },
+                Err(SupportedFormatsError::DeviceBusy { err }) => {
+                    return Err(DefaultFormatError::DeviceBusy { err });
+                },
                 Err(SupportedFormatsError::InvalidArgument) => {
                     // this happens sometimes when querying for input and output capabilities but
                     // the device supports only one
@@ -424,33 +603,33 @@ impl Device {
                 Err(SupportedFormatsError::BackendSpecific { err }) => {
                     return Err(err.into());
                 }
-                Ok(fmts) => fmts.collect(),
+                Ok(fmts) => fmts,
             }
         };
 
-        formats.sort_by(|a, b| a.cmp_default_heuristics(b));
-
-        match formats.into_iter().last() {
-            Some(f) => {
-                let min_r = f.min_sample_rate;
-                let max_r = f.max_sample_rate;
-                let mut format = f.with_max_sample_rate();
-                const HZ_44100: SampleRate = SampleRate(44_100);
-                if min_r <= HZ_44100 && HZ_44100 <= max_r {
-                    format.sample_rate = HZ_44100;
-                }
-                Ok(format)
-            },
-            None => Err(DefaultFormatError::StreamTypeNotSupported)
-        }
+        preference.best_format(formats).ok_or(DefaultFormatError::StreamTypeNotSupported)
     }
 
     fn default_input_format(&self) -> Result<Format, DefaultFormatError> {
-        self.default_format(alsa::SND_PCM_STREAM_CAPTURE)
+        self.default_format(alsa::SND_PCM_STREAM_CAPTURE, &FormatPreference::default())
     }
 
     fn default_output_format(&self) -> Result<Format, DefaultFormatError> {
-        self.default_format(alsa::SND_PCM_STREAM_PLAYBACK)
+        self.default_format(alsa::SND_PCM_STREAM_PLAYBACK, &FormatPreference::default())
+    }
+
+    fn default_input_format_with(
+        &self,
+        preference: &FormatPreference,
+    ) -> Result<Format, DefaultFormatError> {
+        self.default_format(alsa::SND_PCM_STREAM_CAPTURE, preference)
+    }
+
+    fn default_output_format_with(
+        &self,
+        preference: &FormatPreference,
+    ) -> Result<Format, DefaultFormatError> {
+        self.default_format(alsa::SND_PCM_STREAM_PLAYBACK, preference)
     }
 }
 
@@ -468,6 +647,9 @@ pub struct EventLoop {
 
     // Commands processed by the `run()` method that is currently running.
     commands: Sender<Command>,
+
+    // The channel map in effect for each stream whose PCM reports one.
+    channel_layouts: Arc<Mutex<HashMap<StreamId, ChannelLayout>>>,
 }
 
 unsafe impl Send for EventLoop {
@@ -476,6 +658,10 @@ unsafe impl Send for EventLoop {
 unsafe impl Sync for EventLoop {
 }
 
+// How often and at which interval `snd_pcm_resume` is retried while a driver is waking up.
+const RESUME_ATTEMPTS: u32 = 100;
+const RESUME_INTERVAL: Duration = Duration::from_millis(10);
+
 enum Command {
     NewStream(StreamInner),
     PlayStream(StreamId),
@@ -506,6 +692,10 @@ struct StreamInner {
     // Format of the samples.
     sample_format: SampleFormat,
 
+    // Whether the device uses the opposite byte order, in which case the bytes of each sample are
+    // swapped between the device and the user's buffer.
+    swap_bytes: bool,
+
     // Number of channels, ie. number of samples per frame.
     num_channels: u16,
 
@@ -515,7 +705,8 @@ struct StreamInner {
     // Minimum number of samples to put in the buffer.
     period_len: usize,
 
-    // Whether or not the hardware supports pausing the stream.
+    // Whether or not the hardware supports pausing the stream. Otherwise the stream is stopped
+    // while paused.
     can_pause: bool,
 
     // Whether or not the sample stream is currently paused.
@@ -561,6 +752,7 @@ impl EventLoop {
             pending_command_trigger: pending_command_trigger,
             run_context,
             commands: tx,
+            channel_layouts: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -612,8 +804,27 @@ impl EventLoop {
                 // Iterate over each individual stream/descriptor.
                 let mut i_stream = 0;
                 let mut i_descriptor = 1;
-                while (i_descriptor as usize) < run_context.descriptors.len() {
+                'streams: while (i_descriptor as usize) < run_context.descriptors.len() {
                     let stream = &mut run_context.streams[i_stream];
+
+                    // Paused streams have no descriptors.
+                    if stream.is_paused {
+                        i_stream += 1;
+                        continue;
+                    }
+
+                    // A PCM is suspended together with the system and has to be resumed before it
+                    // can be used again. The stream keeps running, so the user is only notified.
+                    if alsa::snd_pcm_state(stream.channel) == alsa::SND_PCM_STATE_SUSPENDED {
+                        match resume_suspended_pcm(stream) {
+                            Ok(()) => callback(stream.id, Err(StreamError::Interrupted)),
+                            Err(err) => streams_to_remove.push((stream.id, err.into())),
+                        }
+                        i_descriptor += stream.num_descriptors as isize;
+                        i_stream += 1;
+                        continue;
+                    }
+
                     let stream_descriptor_ptr = run_context.descriptors.as_mut_ptr().offset(i_descriptor);
 
                     // Only go on if this event was a pollout or pollin event.
@@ -662,23 +873,61 @@ impl EventLoop {
                                 stream.buffer.as_mut_ptr() as *mut _,
                                 available_frames as alsa::snd_pcm_uframes_t,
                             );
-                            if let Err(err) = check_errors(result as _) {
+                            if result as i32 == -libc::EPIPE {
+                                // Buffer overrun, reading continues once the stream is polled again.
+                                match recover_from_xrun(stream.channel, result as i32) {
+                                    Ok(err) => callback(stream.id, Err(StreamError::Xrun { err })),
+                                    Err(err) => streams_to_remove.push((stream.id, err.into())),
+                                }
+                                i_descriptor += stream.num_descriptors as isize;
+                                i_stream += 1;
+                                continue;
+                            } else if result as i32 == -libc::ESTRPIPE {
+                                // Suspended since the last poll, resumed in the next iteration.
+                                continue;
+                            } else if let Err(err) = check_errors(result as _) {
                                 let description = format!("`snd_pcm_readi` failed: {}", err);
                                 let err = BackendSpecificError { description };
                                 streams_to_remove.push((stream.id, err.into()));
+                                i_descriptor += stream.num_descriptors as isize;
+                                i_stream += 1;
                                 continue;
                             }
+                            if stream.swap_bytes {
+                                swap_sample_bytes(&mut stream.buffer, stream.sample_format.sample_size());
+                            }
 
                             let input_buffer = match stream.sample_format {
+                                SampleFormat::I8 => UnknownTypeInputBuffer::I8(::InputBuffer {
+                                    buffer: cast_input_buffer(&mut stream.buffer),
+                                }),
+                                SampleFormat::U8 => UnknownTypeInputBuffer::U8(::InputBuffer {
+                                    buffer: cast_input_buffer(&mut stream.buffer),
+                                }),
                                 SampleFormat::I16 => UnknownTypeInputBuffer::I16(::InputBuffer {
                                     buffer: cast_input_buffer(&mut stream.buffer),
                                 }),
                                 SampleFormat::U16 => UnknownTypeInputBuffer::U16(::InputBuffer {
                                     buffer: cast_input_buffer(&mut stream.buffer),
                                 }),
+                                SampleFormat::I24 => UnknownTypeInputBuffer::I24(::InputBuffer {
+                                    buffer: cast_input_buffer(&mut stream.buffer),
+                                }),
+                                SampleFormat::I24In32 => UnknownTypeInputBuffer::I24In32(::InputBuffer {
+                                    buffer: cast_input_buffer(&mut stream.buffer),
+                                }),
+                                SampleFormat::I32 => UnknownTypeInputBuffer::I32(::InputBuffer {
+                                    buffer: cast_input_buffer(&mut stream.buffer),
+                                }),
+                                SampleFormat::U32 => UnknownTypeInputBuffer::U32(::InputBuffer {
+                                    buffer: cast_input_buffer(&mut stream.buffer),
+                                }),
                                 SampleFormat::F32 => UnknownTypeInputBuffer::F32(::InputBuffer {
                                     buffer: cast_input_buffer(&mut stream.buffer),
                                 }),
+                                SampleFormat::F64 => UnknownTypeInputBuffer::F64(::InputBuffer {
+                                    buffer: cast_input_buffer(&mut stream.buffer),
+                                }),
                             };
                             let stream_data = StreamData::Input {
                                 buffer: input_buffer,
@@ -689,15 +938,36 @@ impl EventLoop {
                             {
                                 // We're now sure that we're ready to write data.
                                 let output_buffer = match stream.sample_format {
+                                    SampleFormat::I8 => UnknownTypeOutputBuffer::I8(::OutputBuffer {
+                                        buffer: cast_output_buffer(&mut stream.buffer),
+                                    }),
+                                    SampleFormat::U8 => UnknownTypeOutputBuffer::U8(::OutputBuffer {
+                                        buffer: cast_output_buffer(&mut stream.buffer),
+                                    }),
                                     SampleFormat::I16 => UnknownTypeOutputBuffer::I16(::OutputBuffer {
                                         buffer: cast_output_buffer(&mut stream.buffer),
                                     }),
                                     SampleFormat::U16 => UnknownTypeOutputBuffer::U16(::OutputBuffer {
                                         buffer: cast_output_buffer(&mut stream.buffer),
                                     }),
+                                    SampleFormat::I24 => UnknownTypeOutputBuffer::I24(::OutputBuffer {
+                                        buffer: cast_output_buffer(&mut stream.buffer),
+                                    }),
+                                    SampleFormat::I24In32 => UnknownTypeOutputBuffer::I24In32(::OutputBuffer {
+                                        buffer: cast_output_buffer(&mut stream.buffer),
+                                    }),
+                                    SampleFormat::I32 => UnknownTypeOutputBuffer::I32(::OutputBuffer {
+                                        buffer: cast_output_buffer(&mut stream.buffer),
+                                    }),
+                                    SampleFormat::U32 => UnknownTypeOutputBuffer::U32(::OutputBuffer {
+                                        buffer: cast_output_buffer(&mut stream.buffer),
+                                    }),
                                     SampleFormat::F32 => UnknownTypeOutputBuffer::F32(::OutputBuffer {
                                         buffer: cast_output_buffer(&mut stream.buffer),
                                     }),
+                                    SampleFormat::F64 => UnknownTypeOutputBuffer::F64(::OutputBuffer {
+                                        buffer: cast_output_buffer(&mut stream.buffer),
+                                    }),
                                 };
 
                                 let stream_data = StreamData::Output {
@@ -705,6 +975,10 @@ impl EventLoop {
                                 };
                                 callback(stream.id, Ok(stream_data));
                             }
+                            if stream.swap_bytes {
+                                swap_sample_bytes(&mut stream.buffer, stream.sample_format.sample_size());
+                            }
+                            let mut recovered = false;
                             loop {
                                 let result = alsa::snd_pcm_writei(
                                     stream.channel,
@@ -713,14 +987,37 @@ impl EventLoop {
                                 );
 
                                 if result as i32 == -libc::EPIPE {
-                                    // buffer underrun
-                                    // TODO: Notify the user of this.
-                                    alsa::snd_pcm_recover(stream.channel, result as i32, 0);
+                                    // Buffer underrun, write the data again once recovered. If the
+                                    // buffer runs empty again at once, the data is dropped.
+                                    let recovery = if recovered {
+                                        None
+                                    } else {
+                                        Some(recover_from_xrun(stream.channel, result as i32))
+                                    };
+                                    match recovery {
+                                        Some(Ok(err)) => {
+                                            recovered = true;
+                                            callback(stream.id, Err(StreamError::Xrun { err }));
+                                            continue;
+                                        },
+                                        Some(Err(err)) => {
+                                            streams_to_remove.push((stream.id, err.into()));
+                                        },
+                                        None => (),
+                                    }
+                                    i_descriptor += stream.num_descriptors as isize;
+                                    i_stream += 1;
+                                    continue 'streams;
+                                } else if result as i32 == -libc::ESTRPIPE {
+                                    // Suspended since the last poll, resumed in the next iteration.
+                                    break;
                                 } else if let Err(err) = check_errors(result as _) {
                                     let description = format!("`snd_pcm_writei` failed: {}", err);
                                     let err = BackendSpecificError { description };
                                     streams_to_remove.push((stream.id, err.into()));
-                                    continue;
+                                    i_descriptor += stream.num_descriptors as isize;
+                                    i_stream += 1;
+                                    continue 'streams;
                                 } else if result as usize != available_frames {
                                     let description = format!(
                                         "unexpected number of frames written: expected {}, \
@@ -730,7 +1027,9 @@ impl EventLoop {
                                     );
                                     let err = BackendSpecificError { description };
                                     streams_to_remove.push((stream.id, err.into()));
-                                    continue;
+                                    i_descriptor += stream.num_descriptors as isize;
+                                    i_stream += 1;
+                                    continue 'streams;
                                 } else {
                                     break;
                                 }
@@ -742,6 +1041,7 @@ impl EventLoop {
                 // Remove any streams that have errored and notify the user.
                 for (stream_id, err) in streams_to_remove {
                     run_context.streams.retain(|s| s.id != stream_id);
+                    self.channel_layouts.lock().unwrap().remove(&stream_id);
                     callback(stream_id, Err(err.into()));
                 }
             }
@@ -757,7 +1057,7 @@ impl EventLoop {
     ) -> Result<StreamId, BuildStreamError>
     {
         unsafe {
-            let name = ffi::CString::new(device.0.clone()).expect("unable to clone device");
+            let name = ffi::CString::new(device.name.clone()).expect("unable to clone device");
 
             let mut capture_handle = ptr::null_mut();
             match alsa::snd_pcm_open(
@@ -766,18 +1066,16 @@ impl EventLoop {
                 alsa::SND_PCM_STREAM_CAPTURE,
                 alsa::SND_PCM_NONBLOCK,
             ) {
-                -16 /* determined empirically */ => return Err(BuildStreamError::DeviceNotAvailable),
-                -22 => return Err(BuildStreamError::InvalidArgument),
-                e => if let Err(description) = check_errors(e) {
-                    let err = BackendSpecificError { description };
-                    return Err(err.into());
-                }
+                e if e < 0 => return Err(open_stream_error(e)),
+                _ => (),
             }
             let hw_params = HwParams::alloc();
 
-            set_hw_params_from_format(capture_handle, &hw_params, format)
+            let swap_bytes = set_hw_params_from_format(capture_handle, &hw_params, format)
                 .map_err(|description| BackendSpecificError { description })?;
 
+            let channel_layout = apply_channel_layout(capture_handle, format)?;
+
             let can_pause = alsa::snd_pcm_hw_params_can_pause(hw_params.0) == 1;
 
             let (buffer_len, period_len) = set_sw_params_from_format(capture_handle, format)
@@ -808,6 +1106,7 @@ impl EventLoop {
                 id: new_stream_id.clone(),
                 channel: capture_handle,
                 sample_format: format.data_type,
+                swap_bytes,
                 num_descriptors: num_descriptors,
                 num_channels: format.channels as u16,
                 buffer_len: buffer_len,
@@ -818,6 +1117,10 @@ impl EventLoop {
                 buffer: vec![],
             };
 
+            if let Some(channel_layout) = channel_layout {
+                self.channel_layouts.lock().unwrap().insert(new_stream_id, channel_layout);
+            }
+
             if let Err(desc) = check_errors(alsa::snd_pcm_start(capture_handle)) {
                 let description = format!("could not start capture stream: {}", desc);
                 let err = BackendSpecificError { description };
@@ -836,7 +1139,7 @@ impl EventLoop {
     ) -> Result<StreamId, BuildStreamError>
     {
         unsafe {
-            let name = ffi::CString::new(device.0.clone()).expect("unable to clone device");
+            let name = ffi::CString::new(device.name.clone()).expect("unable to clone device");
 
             let mut playback_handle = ptr::null_mut();
             match alsa::snd_pcm_open(
@@ -845,18 +1148,16 @@ impl EventLoop {
                 alsa::SND_PCM_STREAM_PLAYBACK,
                 alsa::SND_PCM_NONBLOCK,
             ) {
-                -16 /* determined empirically */ => return Err(BuildStreamError::DeviceNotAvailable),
-                -22 => return Err(BuildStreamError::InvalidArgument),
-                e => if let Err(description) = check_errors(e) {
-                    let err = BackendSpecificError { description };
-                    return Err(err.into())
-                }
+                e if e < 0 => return Err(open_stream_error(e)),
+                _ => (),
             }
             let hw_params = HwParams::alloc();
 
-            set_hw_params_from_format(playback_handle, &hw_params, format)
+            let swap_bytes = set_hw_params_from_format(playback_handle, &hw_params, format)
                 .map_err(|description| BackendSpecificError { description })?;
 
+            let channel_layout = apply_channel_layout(playback_handle, format)?;
+
             let can_pause = alsa::snd_pcm_hw_params_can_pause(hw_params.0) == 1;
 
             let (buffer_len, period_len) = set_sw_params_from_format(playback_handle, format)
@@ -887,6 +1188,7 @@ impl EventLoop {
                 id: new_stream_id.clone(),
                 channel: playback_handle,
                 sample_format: format.data_type,
+                swap_bytes,
                 num_descriptors: num_descriptors,
                 num_channels: format.channels as u16,
                 buffer_len: buffer_len,
@@ -897,6 +1199,10 @@ impl EventLoop {
                 buffer: vec![],
             };
 
+            if let Some(channel_layout) = channel_layout {
+                self.channel_layouts.lock().unwrap().insert(new_stream_id, channel_layout);
+            }
+
             self.push_command(Command::NewStream(stream_inner));
             Ok(new_stream_id)
         }
@@ -911,9 +1217,15 @@ impl EventLoop {
 
     #[inline]
     fn destroy_stream(&self, stream_id: StreamId) {
+        self.channel_layouts.lock().unwrap().remove(&stream_id);
         self.push_command(Command::DestroyStream(stream_id));
     }
 
+    #[inline]
+    fn stream_channel_layout(&self, stream_id: StreamId) -> Option<ChannelLayout> {
+        self.channel_layouts.lock().unwrap().get(&stream_id).cloned()
+    }
+
     #[inline]
     fn play_stream(&self, stream_id: StreamId) -> Result<(), PlayStreamError> {
         self.push_command(Command::PlayStream(stream_id));
@@ -935,23 +1247,13 @@ fn process_commands(run_context: &mut RunContext) {
                 run_context.streams.retain(|s| s.id != stream_id);
             },
             Command::PlayStream(stream_id) => {
-                if let Some(stream) = run_context.streams.iter_mut()
-                    .find(|stream| stream.can_pause && stream.id == stream_id)
-                {
-                    unsafe {
-                        alsa::snd_pcm_pause(stream.channel, 0);
-                    }
-                    stream.is_paused = false;
+                if let Some(stream) = run_context.streams.iter_mut().find(|s| s.id == stream_id) {
+                    resume_pcm(stream);
                 }
             },
             Command::PauseStream(stream_id) => {
-                if let Some(stream) = run_context.streams.iter_mut()
-                    .find(|stream| stream.can_pause && stream.id == stream_id)
-                {
-                    unsafe {
-                        alsa::snd_pcm_pause(stream.channel, 1);
-                    }
-                    stream.is_paused = true;
+                if let Some(stream) = run_context.streams.iter_mut().find(|s| s.id == stream_id) {
+                    pause_pcm(stream);
                 }
             },
             Command::NewStream(stream_inner) => {
@@ -961,6 +1263,85 @@ fn process_commands(run_context: &mut RunContext) {
     }
 }
 
+// Pauses the PCM of a stream in hardware if it supports pausing. Otherwise the PCM is stopped,
+// dropping the pending frames, and prepared again when the stream is resumed.
+fn pause_pcm(stream: &mut StreamInner) {
+    if stream.is_paused {
+        return;
+    }
+    unsafe {
+        // Pausing also fails if the PCM has not been started yet.
+        let paused = stream.can_pause && alsa::snd_pcm_pause(stream.channel, 1) == 0;
+        if !paused {
+            alsa::snd_pcm_drop(stream.channel);
+        }
+    }
+    stream.is_paused = true;
+}
+
+fn resume_pcm(stream: &mut StreamInner) {
+    if !stream.is_paused {
+        return;
+    }
+    unsafe {
+        match alsa::snd_pcm_state(stream.channel) {
+            alsa::SND_PCM_STATE_PAUSED => {
+                alsa::snd_pcm_pause(stream.channel, 0);
+            },
+            alsa::SND_PCM_STATE_SETUP => {
+                alsa::snd_pcm_prepare(stream.channel);
+                start_prepared_pcm(stream.channel);
+            },
+            _ => (),
+        }
+    }
+    stream.is_paused = false;
+}
+
+// Resumes a PCM that was suspended together with the system, or prepares it again if the
+// hardware cannot resume.
+fn resume_suspended_pcm(stream: &StreamInner) -> Result<(), BackendSpecificError> {
+    unsafe {
+        // The driver may still be waking up.
+        let mut result = alsa::snd_pcm_resume(stream.channel);
+        let mut attempts = 1;
+        while result == -libc::EAGAIN && attempts < RESUME_ATTEMPTS {
+            thread::sleep(RESUME_INTERVAL);
+            result = alsa::snd_pcm_resume(stream.channel);
+            attempts += 1;
+        }
+        if result < 0 {
+            if let Err(desc) = check_errors(alsa::snd_pcm_prepare(stream.channel)) {
+                let description = format!("could not resume stream after a suspend: {}", desc);
+                return Err(BackendSpecificError { description });
+            }
+            start_prepared_pcm(stream.channel);
+        }
+    }
+    Ok(())
+}
+
+// Recovers a PCM from a buffer underrun or overrun, returning the error code to report.
+unsafe fn recover_from_xrun(
+    channel: *mut alsa::snd_pcm_t,
+    err: libc::c_int,
+) -> Result<BackendErrorCode, BackendSpecificError>
+{
+    if let Err(desc) = check_errors(alsa::snd_pcm_recover(channel, err, 0)) {
+        let description = format!("could not recover from a buffer underrun or overrun: {}", desc);
+        return Err(BackendSpecificError { description });
+    }
+    start_prepared_pcm(channel);
+    Ok(error_code(err))
+}
+
+// Playback starts with the first write, capture has to be started explicitly.
+unsafe fn start_prepared_pcm(channel: *mut alsa::snd_pcm_t) {
+    if alsa::snd_pcm_stream(channel) == alsa::SND_PCM_STREAM_CAPTURE {
+        alsa::snd_pcm_start(channel);
+    }
+}
+
 // Resets the descriptors so that only `pending_command_trigger.read_fd()` is contained.
 fn reset_descriptors_with_pending_command_trigger(
     descriptors: &mut Vec<libc::pollfd>,
@@ -976,8 +1357,10 @@ fn reset_descriptors_with_pending_command_trigger(
 
 // Appends the `poll` descriptors for each stream onto the `RunContext`'s descriptor slice, ready
 // for a call to `libc::poll`.
+//
+// Paused streams are skipped, so that they neither wake up the loop nor call the callback.
 fn append_stream_poll_descriptors(run_context: &mut RunContext) {
-    for stream in run_context.streams.iter() {
+    for stream in run_context.streams.iter().filter(|s| !s.is_paused) {
         run_context.descriptors.reserve(stream.num_descriptors);
         let len = run_context.descriptors.len();
         let filled = unsafe {
@@ -1058,9 +1441,11 @@ fn get_available_samples(stream: &StreamInner) -> Result<usize, BackendSpecificE
         alsa::snd_pcm_avail_update(stream.channel)
     };
     if available == -32 {
-        // buffer underrun
-        // TODO: Notify the user some how.
+        // Buffer underrun or overrun, reported once the following read or write fails.
         Ok(stream.buffer_len)
+    } else if available as libc::c_int == -libc::ESTRPIPE {
+        // Suspended since the last poll, resumed in the next iteration of the loop.
+        Ok(0)
     } else if let Err(desc) = check_errors(available as libc::c_int) {
         let description = format!("failed to get available samples: {}", desc);
         let err = BackendSpecificError { description };
@@ -1070,37 +1455,96 @@ fn get_available_samples(stream: &StreamInner) -> Result<usize, BackendSpecificE
     }
 }
 
-unsafe fn set_hw_params_from_format(
+// Requests the channel map of the format, if any, and returns the channel map in effect.
+unsafe fn apply_channel_layout(
     pcm_handle: *mut alsa::snd_pcm_t,
-    hw_params: &HwParams,
     format: &Format,
-) -> Result<(), String> {
-    if let Err(e) = check_errors(alsa::snd_pcm_hw_params_any(pcm_handle, hw_params.0)) {
-        return Err(format!("errors on pcm handle: {}", e));
+) -> Result<Option<ChannelLayout>, BuildStreamError> {
+    if let Some(ref layout) = format.channel_layout {
+        if layout.channels() != format.channels {
+            return Err(BuildStreamError::FormatNotSupported);
+        }
+        if set_channel_layout(pcm_handle, layout).is_err() {
+            return Err(BuildStreamError::FormatNotSupported);
+        }
     }
-    if let Err(e) = check_errors(alsa::snd_pcm_hw_params_set_access(pcm_handle,
-                                                    hw_params.0,
-                                                    alsa::SND_PCM_ACCESS_RW_INTERLEAVED)) {
-        return Err(format!("handle not acessible: {}", e));
+    Ok(get_channel_layout(pcm_handle))
+}
+
+// The ALSA format of the given sample format in native byte order.
+fn alsa_format(sample_format: SampleFormat) -> alsa::snd_pcm_format_t {
+    alsa_format_in_byte_order(sample_format, cfg!(target_endian = "big"))
+}
+
+// The ALSA format of the given sample format in the opposite of the native byte order, or `None`
+// for single byte formats.
+fn swapped_alsa_format(sample_format: SampleFormat) -> Option<alsa::snd_pcm_format_t> {
+    if sample_format.sample_size() == 1 {
+        return None;
     }
+    Some(alsa_format_in_byte_order(sample_format, !cfg!(target_endian = "big")))
+}
 
-    let data_type = if cfg!(target_endian = "big") {
-        match format.data_type {
+fn alsa_format_in_byte_order(
+    sample_format: SampleFormat,
+    big_endian: bool,
+) -> alsa::snd_pcm_format_t {
+    if big_endian {
+        match sample_format {
+            SampleFormat::I8 => alsa::SND_PCM_FORMAT_S8,
+            SampleFormat::U8 => alsa::SND_PCM_FORMAT_U8,
             SampleFormat::I16 => alsa::SND_PCM_FORMAT_S16_BE,
             SampleFormat::U16 => alsa::SND_PCM_FORMAT_U16_BE,
+            SampleFormat::I24 => alsa::SND_PCM_FORMAT_S24_3BE,
+            SampleFormat::I24In32 => alsa::SND_PCM_FORMAT_S24_BE,
+            SampleFormat::I32 => alsa::SND_PCM_FORMAT_S32_BE,
+            SampleFormat::U32 => alsa::SND_PCM_FORMAT_U32_BE,
             SampleFormat::F32 => alsa::SND_PCM_FORMAT_FLOAT_BE,
+            SampleFormat::F64 => alsa::SND_PCM_FORMAT_FLOAT64_BE,
         }
     } else {
-        match format.data_type {
+        match sample_format {
+            SampleFormat::I8 => alsa::SND_PCM_FORMAT_S8,
+            SampleFormat::U8 => alsa::SND_PCM_FORMAT_U8,
             SampleFormat::I16 => alsa::SND_PCM_FORMAT_S16_LE,
             SampleFormat::U16 => alsa::SND_PCM_FORMAT_U16_LE,
+            SampleFormat::I24 => alsa::SND_PCM_FORMAT_S24_3LE,
+            SampleFormat::I24In32 => alsa::SND_PCM_FORMAT_S24_LE,
+            SampleFormat::I32 => alsa::SND_PCM_FORMAT_S32_LE,
+            SampleFormat::U32 => alsa::SND_PCM_FORMAT_U32_LE,
             SampleFormat::F32 => alsa::SND_PCM_FORMAT_FLOAT_LE,
+            SampleFormat::F64 => alsa::SND_PCM_FORMAT_FLOAT64_LE,
         }
-    };
+    }
+}
+
+// Returns whether the device uses the opposite of the native byte order, which is only chosen
+// when the native one is not supported.
+unsafe fn set_hw_params_from_format(
+    pcm_handle: *mut alsa::snd_pcm_t,
+    hw_params: &HwParams,
+    format: &Format,
+) -> Result<bool, String> {
+    if let Err(e) = check_errors(alsa::snd_pcm_hw_params_any(pcm_handle, hw_params.0)) {
+        return Err(format!("errors on pcm handle: {}", e));
+    }
+    if let Err(e) = check_errors(alsa::snd_pcm_hw_params_set_access(pcm_handle,
+                                                    hw_params.0,
+                                                    alsa::SND_PCM_ACCESS_RW_INTERLEAVED)) {
+        return Err(format!("handle not acessible: {}", e));
+    }
 
+    let native = alsa_format(format.data_type);
+    let (device_format, swap_bytes) = match swapped_alsa_format(format.data_type) {
+        Some(swapped) if alsa::snd_pcm_hw_params_test_format(pcm_handle, hw_params.0, native) != 0
+            && alsa::snd_pcm_hw_params_test_format(pcm_handle, hw_params.0, swapped) == 0 => {
+            (swapped, true)
+        },
+        _ => (native, false),
+    };
     if let Err(e) = check_errors(alsa::snd_pcm_hw_params_set_format(pcm_handle,
                                                     hw_params.0,
-                                                    data_type)) {
+                                                    device_format)) {
         return Err(format!("format could not be set: {}", e));
     }
     if let Err(e) = check_errors(alsa::snd_pcm_hw_params_set_rate(pcm_handle,
@@ -1130,7 +1574,7 @@ unsafe fn set_hw_params_from_format(
         return Err(format!("hardware params could not be set: {}", e));
     }
 
-    Ok(())
+    Ok(swap_bytes)
 }
 
 unsafe fn set_sw_params_from_format(
@@ -1220,6 +1664,33 @@ fn check_errors(err: libc::c_int) -> Result<(), String> {
     Ok(())
 }
 
+// Preserves a negative return value of an ALSA function together with its description.
+fn error_code(err: libc::c_int) -> BackendErrorCode {
+    let description = check_errors(err).err().unwrap_or_default();
+    BackendErrorCode { code: err, description }
+}
+
+// Classifies the error returned when opening the PCM of a new stream.
+fn open_stream_error(err: libc::c_int) -> BuildStreamError {
+    match -err {
+        libc::EBUSY => BuildStreamError::DeviceBusy { err: error_code(err) },
+        libc::EACCES | libc::EPERM => BuildStreamError::PermissionDenied { err: error_code(err) },
+        libc::ENOENT | libc::ENODEV | libc::ENXIO => BuildStreamError::DeviceNotAvailable,
+        libc::EINVAL => BuildStreamError::InvalidArgument,
+        _ => {
+            let description = error_code(err).description;
+            BackendSpecificError { description }.into()
+        },
+    }
+}
+
+/// Reverse the bytes of each sample of `sample_size` bytes in the buffer.
+fn swap_sample_bytes(buffer: &mut [u8], sample_size: usize) {
+    for sample in buffer.chunks_mut(sample_size) {
+        sample.reverse();
+    }
+}
+
 /// Cast a byte slice into a (immutable) slice of desired type.
 /// Safety: it's up to the caller to ensure that the input slice has valid bit representations.
 unsafe fn cast_input_buffer<T>(v: &[u8]) -> &[T] {
@@ -1233,3 +1704,66 @@ unsafe fn cast_output_buffer<T>(v: &mut [u8]) -> &mut [T] {
     debug_assert!(v.len() % std::mem::size_of::<T>() == 0);
     std::slice::from_raw_parts_mut(v.as_mut_ptr() as *mut T, v.len() / std::mem::size_of::<T>())
 }
+
+#[cfg(test)]
+mod test {
+    use BuildStreamError;
+    use ChannelLayout;
+    use DeviceDirections;
+    use SupportedFormatsError;
+    use super::{Device, EventLoop, swap_sample_bytes};
+
+    fn device(name: &str) -> Device {
+        let directions = DeviceDirections { input: true, output: true };
+        Device::new(name.to_owned(), None, directions)
+    }
+
+    // The `null` PCM is defined by the default ALSA configuration and can always be opened.
+    #[test]
+    fn supported_formats_are_cached() {
+        let device = device("null");
+        assert!(device.supported_formats_cache.lock().unwrap().output.is_none());
+        let formats: Vec<_> = device.supported_output_formats().unwrap().collect();
+        assert!(!formats.is_empty());
+        {
+            let cache = device.supported_formats_cache.lock().unwrap();
+            assert_eq!(cache.output.as_ref(), Some(&formats));
+            assert!(cache.input.is_none());
+        }
+        let clone = device.clone();
+        let cached: Vec<_> = clone.supported_output_formats().unwrap().collect();
+        assert_eq!(cached, formats);
+    }
+
+    #[test]
+    fn failed_probes_are_not_cached() {
+        let device = device("cpal_no_such_pcm");
+        match device.supported_output_formats() {
+            Err(SupportedFormatsError::DeviceNotAvailable) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(device.supported_formats_cache.lock().unwrap().output.is_none());
+    }
+
+    #[test]
+    fn sample_bytes_are_swapped() {
+        let mut buffer = [1u8, 2, 3, 4, 5, 6];
+        swap_sample_bytes(&mut buffer, 2);
+        assert_eq!(buffer, [2, 1, 4, 3, 6, 5]);
+        swap_sample_bytes(&mut buffer, 3);
+        assert_eq!(buffer, [4, 1, 2, 5, 6, 3]);
+        swap_sample_bytes(&mut buffer, 1);
+        assert_eq!(buffer, [4, 1, 2, 5, 6, 3]);
+    }
+
+    #[test]
+    fn channel_layout_must_match_channel_count() {
+        let device = device("null");
+        let mut format = device.default_output_format().unwrap();
+        format.channel_layout = Some(ChannelLayout::discrete(format.channels + 1));
+        match EventLoop::new().build_output_stream(&device, &format) {
+            Err(BuildStreamError::FormatNotSupported) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
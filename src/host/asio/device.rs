@@ -6,6 +6,11 @@ use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 use BackendSpecificError;
 use DefaultFormatError;
+use DeviceDirections;
+use DeviceId;
+use DeviceIdError;
+use DeviceInfo;
+use DeviceInfoError;
 use DeviceNameError;
 use DevicesError;
 use Format;
@@ -47,6 +52,33 @@ impl Device {
         Ok(self.driver.name().to_string())
     }
 
+    /// ASIO drivers are loaded by name, so the driver name doubles as the device identifier.
+    pub fn id(&self) -> Result<DeviceId, DeviceIdError> {
+        Ok(DeviceId::new(crate::HostId::Asio, self.driver.name().to_string()))
+    }
+
+    /// Describes the device. ASIO has no concept of a default device.
+    pub fn info(&self) -> Result<DeviceInfo, DeviceInfoError> {
+        let name = self.driver.name().to_string();
+        let channels = self.driver.channels().map_err(|e| {
+            let description = format!("{}", e);
+            BackendSpecificError { description }
+        })?;
+        Ok(DeviceInfo {
+            description: name.clone(),
+            card_name: None,
+            card_index: None,
+            driver: Some(name),
+            device_index: None,
+            subdevice_index: None,
+            directions: DeviceDirections {
+                input: channels.ins > 0,
+                output: channels.outs > 0,
+            },
+            is_default: false,
+        })
+    }
+
     /// Gets the supported input formats.
     /// TODO currently only supports the default.
     /// Need to find all possible formats.
@@ -115,6 +147,7 @@ impl Device {
             channels,
             sample_rate,
             data_type,
+            channel_layout: None,
         })
     }
 
@@ -129,6 +162,7 @@ impl Device {
             channels,
             sample_rate,
             data_type,
+            channel_layout: None,
         })
     }
 }
@@ -3,6 +3,10 @@ extern crate asio_sys as sys;
 use {
     BuildStreamError,
     DefaultFormatError,
+    DeviceId,
+    DeviceIdError,
+    DeviceInfo,
+    DeviceInfoError,
     DeviceNameError,
     DevicesError,
     Format,
@@ -76,6 +80,14 @@ impl DeviceTrait for Device {
         Device::name(self)
     }
 
+    fn id(&self) -> Result<DeviceId, DeviceIdError> {
+        Device::id(self)
+    }
+
+    fn info(&self) -> Result<DeviceInfo, DeviceInfoError> {
+        Device::info(self)
+    }
+
     fn supported_input_formats(&self) -> Result<Self::SupportedInputFormats, SupportedFormatsError> {
         Device::supported_input_formats(self)
     }
@@ -93,6 +105,9 @@ impl DeviceTrait for Device {
     }
 }
 
+// `destroy_stream` may be called from within the `run` callback, it only forgets the stream.
+pub(crate) const CAN_DESTROY_STREAMS_IN_CALLBACK: bool = true;
+
 impl EventLoopTrait for EventLoop {
     type Device = Device;
     type StreamId = StreamId;
@@ -699,6 +699,7 @@ fn check_format(
         channels,
         sample_rate,
         data_type,
+        ..
     } = format;
     // Try and set the sample rate to what the user selected.
     let sample_rate = sample_rate.0.into();
@@ -714,7 +715,14 @@ fn check_format(
     // unsigned formats are not supported by asio
     match data_type {
         SampleFormat::I16 | SampleFormat::F32 => (),
-        SampleFormat::U16 => return Err(BuildStreamError::FormatNotSupported),
+        SampleFormat::I8 |
+        SampleFormat::U8 |
+        SampleFormat::U16 |
+        SampleFormat::I24 |
+        SampleFormat::I24In32 |
+        SampleFormat::I32 |
+        SampleFormat::U32 |
+        SampleFormat::F64 => return Err(BuildStreamError::FormatNotSupported),
     }
     if *channels > num_asio_channels {
         return Err(BuildStreamError::FormatNotSupported);
@@ -5,6 +5,11 @@ use ChannelCount;
 use BackendSpecificError;
 use BuildStreamError;
 use DefaultFormatError;
+use DeviceDirections;
+use DeviceId;
+use DeviceIdError;
+use DeviceInfo;
+use DeviceInfoError;
 use DeviceNameError;
 use DevicesError;
 use Format;
@@ -48,6 +53,7 @@ use self::coreaudio::sys::{
     AudioValueRange,
     kAudioDevicePropertyAvailableNominalSampleRates,
     kAudioDevicePropertyDeviceNameCFString,
+    kAudioDevicePropertyDeviceUID,
     kAudioDevicePropertyNominalSampleRate,
     kAudioObjectPropertyScopeInput,
     kAudioObjectPropertyScopeGlobal,
@@ -56,6 +62,7 @@ use self::coreaudio::sys::{
     kAudioDevicePropertyStreamFormat,
     kAudioFormatFlagIsFloat,
     kAudioFormatFlagIsPacked,
+    kAudioFormatFlagIsSignedInteger,
     kAudioFormatLinearPCM,
     kAudioObjectPropertyElementMaster,
     kAudioObjectPropertyScopeOutput,
@@ -65,9 +72,13 @@ use self::coreaudio::sys::{
     kCFStringEncodingUTF8,
     OSStatus,
 };
+use self::core_foundation_sys::base::{CFRelease, CFTypeRef};
 use self::core_foundation_sys::string::{
     CFStringRef,
+    CFStringGetCString,
     CFStringGetCStringPtr,
+    CFStringGetLength,
+    CFStringGetMaximumSizeForEncoding,
 };
 
 mod enumerate;
@@ -119,6 +130,14 @@ impl DeviceTrait for Device {
         Device::name(self)
     }
 
+    fn id(&self) -> Result<DeviceId, DeviceIdError> {
+        Device::id(self)
+    }
+
+    fn info(&self) -> Result<DeviceInfo, DeviceInfoError> {
+        Device::info(self)
+    }
+
     fn supported_input_formats(&self) -> Result<Self::SupportedInputFormats, SupportedFormatsError> {
         Device::supported_input_formats(self)
     }
@@ -136,6 +155,10 @@ impl DeviceTrait for Device {
     }
 }
 
+// The `run` callback is called from the render callback of the stream's `AudioUnit`, which
+// `destroy_stream` disposes of.
+pub(crate) const CAN_DESTROY_STREAMS_IN_CALLBACK: bool = false;
+
 impl EventLoopTrait for EventLoop {
     type Device = Device;
     type StreamId = StreamId;
@@ -214,6 +237,69 @@ impl Device {
         Ok(c_str.to_string_lossy().into_owned())
     }
 
+    // The `AudioDeviceID` is only valid for the lifetime of the process, so we use the device's UID
+    // which persists across restarts instead.
+    fn id(&self) -> Result<DeviceId, DeviceIdError> {
+        let property_address = AudioObjectPropertyAddress {
+            mSelector: kAudioDevicePropertyDeviceUID,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMaster,
+        };
+        let device_uid: CFStringRef = null();
+        let data_size = mem::size_of::<CFStringRef>();
+        let uid = unsafe {
+            let status = AudioObjectGetPropertyData(
+                self.audio_device_id,
+                &property_address as *const _,
+                0,
+                null(),
+                &data_size as *const _ as *mut _,
+                &device_uid as *const _ as *mut _,
+            );
+            check_os_status(status)?;
+
+            // The caller owns the UID string.
+            let uid = cf_string_to_string(device_uid);
+            CFRelease(device_uid as CFTypeRef);
+            uid
+        };
+        match uid {
+            Some(uid) => Ok(DeviceId::new(crate::HostId::CoreAudio, uid)),
+            None => {
+                let description = "failed to convert the device UID to UTF-8".to_string();
+                Err(BackendSpecificError { description }.into())
+            },
+        }
+    }
+
+    fn info(&self) -> Result<DeviceInfo, DeviceInfoError> {
+        let description = match self.name() {
+            Ok(name) => name,
+            Err(DeviceNameError::BackendSpecific { err }) => return Err(err.into()),
+        };
+        let has_formats = |scope| {
+            self.supported_formats(scope)
+                .map(|mut formats| formats.next().is_some())
+                .unwrap_or(false)
+        };
+        let directions = DeviceDirections {
+            input: has_formats(kAudioObjectPropertyScopeInput),
+            output: has_formats(kAudioObjectPropertyScopeOutput),
+        };
+        let is_default = default_input_device().map_or(false, |d| d == *self)
+            || default_output_device().map_or(false, |d| d == *self);
+        Ok(DeviceInfo {
+            description,
+            card_name: None,
+            card_index: None,
+            driver: None,
+            device_index: None,
+            subdevice_index: None,
+            directions,
+            is_default,
+        })
+    }
+
     // Logic re-used between `supported_input_formats` and `supported_output_formats`.
     fn supported_formats(
         &self,
@@ -310,6 +396,7 @@ impl Device {
                     min_sample_rate: SampleRate(range.mMinimum as _),
                     max_sample_rate: SampleRate(range.mMaximum as _),
                     data_type: sample_format,
+                    channel_layout: None,
                 };
                 fmts.push(fmt);
             }
@@ -397,6 +484,7 @@ impl Device {
                 sample_rate: SampleRate(asbd.mSampleRate as _),
                 channels: asbd.mChannelsPerFrame as _,
                 data_type: sample_format,
+                channel_layout: None,
             };
             Ok(format)
         }
@@ -469,19 +557,42 @@ impl From<coreaudio::Error> for BuildStreamError {
     }
 }
 
+// Copies a `CFString` as UTF-8, falling back to `CFStringGetCString` if the string has no
+// internal C string that `CFStringGetCStringPtr` can return.
+unsafe fn cf_string_to_string(string: CFStringRef) -> Option<String> {
+    let c_string: *const c_char = CFStringGetCStringPtr(string, kCFStringEncodingUTF8);
+    if c_string != null() {
+        return Some(CStr::from_ptr(c_string).to_string_lossy().into_owned());
+    }
+    let length = CFStringGetLength(string);
+    let size = CFStringGetMaximumSizeForEncoding(length, kCFStringEncodingUTF8) + 1;
+    let mut buffer = vec![0 as c_char; size as usize];
+    if CFStringGetCString(string, buffer.as_mut_ptr(), size, kCFStringEncodingUTF8) == 0 {
+        return None;
+    }
+    Some(CStr::from_ptr(buffer.as_ptr()).to_string_lossy().into_owned())
+}
+
 // Create a coreaudio AudioStreamBasicDescription from a CPAL Format.
 fn asbd_from_format(format: &Format) -> AudioStreamBasicDescription {
     let n_channels = format.channels as usize;
     let sample_rate = format.sample_rate.0;
     let bytes_per_channel = format.data_type.sample_size();
-    let bits_per_channel = bytes_per_channel * 8;
     let bytes_per_frame = n_channels * bytes_per_channel;
     let frames_per_packet = 1;
     let bytes_per_packet = frames_per_packet * bytes_per_frame;
     let sample_format = format.data_type;
-    let format_flags = match sample_format {
-        SampleFormat::F32 => (kAudioFormatFlagIsFloat | kAudioFormatFlagIsPacked) as u32,
-        _ => kAudioFormatFlagIsPacked as u32,
+    let (bits_per_channel, format_flags) = match sample_format {
+        SampleFormat::F32 | SampleFormat::F64 => {
+            (bytes_per_channel * 8, (kAudioFormatFlagIsFloat | kAudioFormatFlagIsPacked) as u32)
+        },
+        SampleFormat::I24 => {
+            (24, (kAudioFormatFlagIsSignedInteger | kAudioFormatFlagIsPacked) as u32)
+        },
+        // Without `kAudioFormatFlagIsPacked` the 24 bits are aligned to the low end of the 4 bytes
+        // of each sample, as in ALSA's `S24_LE`.
+        SampleFormat::I24In32 => (24, kAudioFormatFlagIsSignedInteger as u32),
+        _ => (bytes_per_channel * 8, kAudioFormatFlagIsPacked as u32),
     };
     let asbd = AudioStreamBasicDescription {
         mBitsPerChannel: bits_per_channel as _,
@@ -808,8 +919,15 @@ impl EventLoop {
 
             match sample_format {
                 SampleFormat::F32 => try_callback!(F32, f32),
+                SampleFormat::F64 => try_callback!(F64, f64),
+                SampleFormat::I8 => try_callback!(I8, i8),
+                SampleFormat::U8 => try_callback!(U8, u8),
                 SampleFormat::I16 => try_callback!(I16, i16),
                 SampleFormat::U16 => try_callback!(U16, u16),
+                SampleFormat::I24 => try_callback!(I24, ::I24),
+                SampleFormat::I24In32 => try_callback!(I24In32, ::I24In32),
+                SampleFormat::I32 => try_callback!(I32, i32),
+                SampleFormat::U32 => try_callback!(U32, u32),
             }
 
             Ok(())
@@ -864,14 +982,14 @@ impl EventLoop {
 
             // A small macro to simplify handling the callback for different sample types.
             macro_rules! try_callback {
-                ($SampleFormat:ident, $SampleType:ty, $equilibrium:expr) => {{
+                ($SampleFormat:ident, $SampleType:ty) => {{
                     let data_len = (data_byte_size as usize / bytes_per_channel) as usize;
                     let data_slice = slice::from_raw_parts_mut(data as *mut $SampleType, data_len);
                     let callback = match *user_callback {
                         UserCallback::Active(ref mut cb) => cb,
                         UserCallback::Inactive => {
                             for sample in data_slice.iter_mut() {
-                                *sample = $equilibrium;
+                                *sample = <$SampleType as ::Sample>::EQUILIBRIUM;
                             }
                             return Ok(());
                         }
@@ -883,9 +1001,16 @@ impl EventLoop {
             }
 
             match sample_format {
-                SampleFormat::F32 => try_callback!(F32, f32, 0.0),
-                SampleFormat::I16 => try_callback!(I16, i16, 0),
-                SampleFormat::U16 => try_callback!(U16, u16, ::std::u16::MAX / 2),
+                SampleFormat::F32 => try_callback!(F32, f32),
+                SampleFormat::F64 => try_callback!(F64, f64),
+                SampleFormat::I8 => try_callback!(I8, i8),
+                SampleFormat::U8 => try_callback!(U8, u8),
+                SampleFormat::I16 => try_callback!(I16, i16),
+                SampleFormat::U16 => try_callback!(U16, u16),
+                SampleFormat::I24 => try_callback!(I24, ::I24),
+                SampleFormat::I24In32 => try_callback!(I24In32, ::I24In32),
+                SampleFormat::I32 => try_callback!(I32, i32),
+                SampleFormat::U32 => try_callback!(U32, u32),
             }
 
             Ok(())
@@ -10,6 +10,10 @@ use stdweb::web::set_timeout;
 
 use BuildStreamError;
 use DefaultFormatError;
+use DeviceId;
+use DeviceIdError;
+use DeviceInfo;
+use DeviceInfoError;
 use DeviceNameError;
 use DevicesError;
 use Format;
@@ -67,6 +71,14 @@ impl DeviceTrait for Device {
         Device::name(self)
     }
 
+    fn id(&self) -> Result<DeviceId, DeviceIdError> {
+        Device::id(self)
+    }
+
+    fn info(&self) -> Result<DeviceInfo, DeviceInfoError> {
+        Device::info(self)
+    }
+
     fn supported_input_formats(&self) -> Result<Self::SupportedInputFormats, SupportedFormatsError> {
         Device::supported_input_formats(self)
     }
@@ -84,6 +96,10 @@ impl DeviceTrait for Device {
     }
 }
 
+// `destroy_stream` may be called from within the `run` callback, which works on a copy of the
+// streams.
+pub(crate) const CAN_DESTROY_STREAMS_IN_CALLBACK: bool = true;
+
 impl EventLoopTrait for EventLoop {
     type Device = Device;
     type StreamId = StreamId;
@@ -136,7 +152,15 @@ impl StreamIdTrait for StreamId {}
 // be underflows
 
 pub struct EventLoop {
-    streams: Mutex<Vec<Option<Reference>>>,
+    streams: Mutex<Vec<Option<Stream>>>,
+}
+
+#[derive(Clone)]
+struct Stream {
+    // The `AudioContext` of the stream.
+    context: Reference,
+    // Paused streams are skipped by the `run` callback.
+    playing: bool,
 }
 
 impl EventLoop {
@@ -169,8 +193,8 @@ impl EventLoop {
                 let streams = user_data.0.streams.lock().unwrap().clone();
                 for (stream_id, stream) in streams.iter().enumerate() {
                     let stream = match stream.as_ref() {
-                        Some(v) => v,
-                        None => continue,
+                        Some(v) if v.playing => &v.context,
+                        _ => continue,
                     };
 
                     let mut temporary_buffer = vec![0.0; 44100 * 2 / 3];
@@ -235,7 +259,8 @@ impl EventLoop {
 
     #[inline]
     fn build_output_stream(&self, _: &Device, _format: &Format) -> Result<StreamId, BuildStreamError> {
-        let stream = js!(return new AudioContext()).into_reference().unwrap();
+        let context = js!(return new AudioContext()).into_reference().unwrap();
+        let stream = Stream { context, playing: true };
 
         let mut streams = self.streams.lock().unwrap();
         let stream_id = if let Some(pos) = streams.iter().position(|v| v.is_none()) {
@@ -257,23 +282,27 @@ impl EventLoop {
 
     #[inline]
     fn play_stream(&self, stream_id: StreamId) -> Result<(), PlayStreamError> {
-        let streams = self.streams.lock().unwrap();
+        let mut streams = self.streams.lock().unwrap();
         let stream = streams
-            .get(stream_id.0)
-            .and_then(|v| v.as_ref())
+            .get_mut(stream_id.0)
+            .and_then(|v| v.as_mut())
             .expect("invalid stream ID");
-        js!(@{stream}.resume());
+        stream.playing = true;
+        let context = &stream.context;
+        js!(@{context}.resume());
         Ok(())
     }
 
     #[inline]
     fn pause_stream(&self, stream_id: StreamId) -> Result<(), PauseStreamError> {
-        let streams = self.streams.lock().unwrap();
+        let mut streams = self.streams.lock().unwrap();
         let stream = streams
-            .get(stream_id.0)
-            .and_then(|v| v.as_ref())
+            .get_mut(stream_id.0)
+            .and_then(|v| v.as_mut())
             .expect("invalid stream ID");
-        js!(@{stream}.suspend());
+        stream.playing = false;
+        let context = &stream.context;
+        js!(@{context}.suspend());
         Ok(())
     }
 }
@@ -345,6 +374,25 @@ impl Device {
         Ok("Default Device".to_owned())
     }
 
+    #[inline]
+    fn id(&self) -> Result<DeviceId, DeviceIdError> {
+        Ok(DeviceId::new(::HostId::Emscripten, "default".to_owned()))
+    }
+
+    #[inline]
+    fn info(&self) -> Result<DeviceInfo, DeviceInfoError> {
+        Ok(DeviceInfo {
+            description: "Default Device".to_owned(),
+            card_name: None,
+            card_index: None,
+            driver: None,
+            device_index: None,
+            subdevice_index: None,
+            directions: ::DeviceDirections { input: false, output: true },
+            is_default: true,
+        })
+    }
+
     #[inline]
     fn supported_input_formats(&self) -> Result<SupportedInputFormats, SupportedFormatsError> {
         unimplemented!();
@@ -364,6 +412,7 @@ impl Device {
                     min_sample_rate: ::SampleRate(44100),
                     max_sample_rate: ::SampleRate(44100),
                     data_type: ::SampleFormat::F32,
+                    channel_layout: None,
                 },
             ].into_iter(),
         )
@@ -380,6 +429,7 @@ impl Device {
                 channels: 2,
                 sample_rate: ::SampleRate(44100),
                 data_type: ::SampleFormat::F32,
+                channel_layout: None,
             },
         )
     }
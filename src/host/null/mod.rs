@@ -1,7 +1,12 @@
 #![allow(dead_code)]
 
+use BackendSpecificError;
 use BuildStreamError;
 use DefaultFormatError;
+use DeviceId;
+use DeviceIdError;
+use DeviceInfo;
+use DeviceInfoError;
 use DevicesError;
 use DeviceNameError;
 use Format;
@@ -55,6 +60,18 @@ impl DeviceTrait for Device {
         Ok("null".to_owned())
     }
 
+    #[inline]
+    fn id(&self) -> Result<DeviceId, DeviceIdError> {
+        // There is no `HostId` for the null host, so its device cannot be looked up again.
+        let description = "the null device has no identifier".to_owned();
+        Err(BackendSpecificError { description }.into())
+    }
+
+    #[inline]
+    fn info(&self) -> Result<DeviceInfo, DeviceInfoError> {
+        Err(DeviceInfoError::DeviceNotAvailable)
+    }
+
     #[inline]
     fn supported_input_formats(&self) -> Result<SupportedInputFormats, SupportedFormatsError> {
         unimplemented!()
@@ -76,6 +93,9 @@ impl DeviceTrait for Device {
     }
 }
 
+// The null host has no streams to destroy.
+pub(crate) const CAN_DESTROY_STREAMS_IN_CALLBACK: bool = true;
+
 impl EventLoopTrait for EventLoop {
     type Device = Device;
     type StreamId = StreamId;
@@ -11,6 +11,11 @@ use std::sync::{Arc, Mutex, MutexGuard};
 
 use BackendSpecificError;
 use DefaultFormatError;
+use DeviceDirections;
+use DeviceId;
+use DeviceIdError;
+use DeviceInfo;
+use DeviceInfoError;
 use DeviceNameError;
 use DevicesError;
 use Format;
@@ -288,6 +293,7 @@ unsafe fn format_from_waveformatex_ptr(
         channels: (*waveformatex_ptr).nChannels as _,
         sample_rate: SampleRate((*waveformatex_ptr).nSamplesPerSec),
         data_type: data_type,
+        channel_layout: None,
     };
     Some(format)
 }
@@ -348,6 +354,64 @@ impl Device {
         }
     }
 
+    pub fn id(&self) -> Result<DeviceId, DeviceIdError> {
+        unsafe {
+            // The endpoint ID string is persistent across restarts, cf.
+            // https://docs.microsoft.com/en-us/windows/desktop/coreaudio/endpoint-id-strings
+            let mut id_ptr: LPWSTR = ptr::null_mut();
+            if let Err(err) = check_result((*self.device).GetId(&mut id_ptr)) {
+                let description = format!("failed to retrieve device id: {}", err);
+                let err = BackendSpecificError { description };
+                return Err(err.into());
+            }
+
+            // Find the length of the id.
+            let mut len = 0;
+            while *id_ptr.offset(len) != 0 {
+                len += 1;
+            }
+
+            // Create the utf16 slice and covert it into a string.
+            let id_slice = slice::from_raw_parts(id_ptr, len as usize);
+            let id_os_string: OsString = OsStringExt::from_wide(id_slice);
+            let id_string = match id_os_string.into_string() {
+                Ok(string) => string,
+                Err(os_string) => os_string.to_string_lossy().into(),
+            };
+
+            // Clean up the id.
+            CoTaskMemFree(id_ptr as *mut c_void);
+
+            Ok(DeviceId::new(crate::HostId::Wasapi, id_string))
+        }
+    }
+
+    pub fn info(&self) -> Result<DeviceInfo, DeviceInfoError> {
+        let description = match self.name() {
+            Ok(name) => name,
+            Err(DeviceNameError::BackendSpecific { err }) => return Err(err.into()),
+        };
+        // WASAPI endpoints are either render or capture endpoints, never both.
+        let data_flow = self.data_flow();
+        let directions = DeviceDirections {
+            input: data_flow == eCapture,
+            output: data_flow == eRender,
+        };
+        let is_default = default_device(data_flow)
+            .map(|default| default == *self)
+            .unwrap_or(false);
+        Ok(DeviceInfo {
+            description,
+            card_name: None,
+            card_index: None,
+            driver: None,
+            device_index: None,
+            subdevice_index: None,
+            directions,
+            is_default,
+        })
+    }
+
     #[inline]
     fn from_immdevice(device: *mut IMMDevice) -> Self {
         Device {
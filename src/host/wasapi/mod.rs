@@ -3,6 +3,10 @@ extern crate winapi;
 use BackendSpecificError;
 use BuildStreamError;
 use DefaultFormatError;
+use DeviceId;
+use DeviceIdError;
+use DeviceInfo;
+use DeviceInfoError;
 use DeviceNameError;
 use DevicesError;
 use Format;
@@ -65,6 +69,14 @@ impl DeviceTrait for Device {
         Device::name(self)
     }
 
+    fn id(&self) -> Result<DeviceId, DeviceIdError> {
+        Device::id(self)
+    }
+
+    fn info(&self) -> Result<DeviceInfo, DeviceInfoError> {
+        Device::info(self)
+    }
+
     fn supported_input_formats(&self) -> Result<Self::SupportedInputFormats, SupportedFormatsError> {
         Device::supported_input_formats(self)
     }
@@ -82,6 +94,10 @@ impl DeviceTrait for Device {
     }
 }
 
+// `destroy_stream` may be called from within the `run` callback, the stream is removed before
+// the run loop waits on it again.
+pub(crate) const CAN_DESTROY_STREAMS_IN_CALLBACK: bool = true;
+
 impl EventLoopTrait for EventLoop {
     type Device = Device;
     type StreamId = StreamId;
@@ -5,7 +5,12 @@ use super::winapi::shared::basetsd::UINT32;
 use super::winapi::shared::ksmedia;
 use super::winapi::shared::minwindef::{BYTE, DWORD, FALSE, WORD};
 use super::winapi::shared::mmreg;
-use super::winapi::um::audioclient::{self, AUDCLNT_E_DEVICE_INVALIDATED, AUDCLNT_S_BUFFER_EMPTY};
+use super::winapi::um::audioclient::{
+    self,
+    AUDCLNT_E_DEVICE_INVALIDATED,
+    AUDCLNT_E_DEVICE_IN_USE,
+    AUDCLNT_S_BUFFER_EMPTY,
+};
 use super::winapi::um::audiosessiontypes::{AUDCLNT_SHAREMODE_SHARED, AUDCLNT_STREAMFLAGS_EVENTCALLBACK};
 use super::winapi::um::handleapi;
 use super::winapi::um::synchapi;
@@ -20,6 +25,7 @@ use std::sync::mpsc::{channel, Sender, Receiver};
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 
+use BackendErrorCode;
 use BackendSpecificError;
 use BuildStreamError;
 use Format;
@@ -166,6 +172,14 @@ impl EventLoop {
                         (*audio_client).Release();
                         return Err(BuildStreamError::DeviceNotAvailable);
                     },
+                    Err(ref e) if e.raw_os_error() == Some(AUDCLNT_E_DEVICE_IN_USE) => {
+                        (*audio_client).Release();
+                        let err = BackendErrorCode {
+                            code: AUDCLNT_E_DEVICE_IN_USE,
+                            description: format!("{}", e),
+                        };
+                        return Err(BuildStreamError::DeviceBusy { err });
+                    },
                     Err(e) => {
                         (*audio_client).Release();
                         let description = format!("{}", e);
@@ -325,6 +339,14 @@ impl EventLoop {
                         (*audio_client).Release();
                         return Err(BuildStreamError::DeviceNotAvailable);
                     },
+                    Err(ref e) if e.raw_os_error() == Some(AUDCLNT_E_DEVICE_IN_USE) => {
+                        (*audio_client).Release();
+                        let err = BackendErrorCode {
+                            code: AUDCLNT_E_DEVICE_IN_USE,
+                            description: format!("{}", e),
+                        };
+                        return Err(BuildStreamError::DeviceBusy { err });
+                    },
                     Err(e) => {
                         (*audio_client).Release();
                         let description = format!("{}", e);
@@ -563,8 +585,15 @@ impl EventLoop {
 
                             match stream.sample_format {
                                 SampleFormat::F32 => capture_callback!(f32, F32),
+                                SampleFormat::F64 => capture_callback!(f64, F64),
+                                SampleFormat::I8 => capture_callback!(i8, I8),
+                                SampleFormat::U8 => capture_callback!(u8, U8),
                                 SampleFormat::I16 => capture_callback!(i16, I16),
                                 SampleFormat::U16 => capture_callback!(u16, U16),
+                                SampleFormat::I24 => capture_callback!(::I24, I24),
+                                SampleFormat::I24In32 => capture_callback!(::I24In32, I24In32),
+                                SampleFormat::I32 => capture_callback!(i32, I32),
+                                SampleFormat::U32 => capture_callback!(u32, U32),
                             }
                         }
                     },
@@ -616,8 +645,15 @@ impl EventLoop {
 
                         match stream.sample_format {
                             SampleFormat::F32 => render_callback!(f32, F32),
+                            SampleFormat::F64 => render_callback!(f64, F64),
+                            SampleFormat::I8 => render_callback!(i8, I8),
+                            SampleFormat::U8 => render_callback!(u8, U8),
                             SampleFormat::I16 => render_callback!(i16, I16),
                             SampleFormat::U16 => render_callback!(u16, U16),
+                            SampleFormat::I24 => render_callback!(::I24, I24),
+                            SampleFormat::I24In32 => render_callback!(::I24In32, I24In32),
+                            SampleFormat::I32 => render_callback!(i32, I32),
+                            SampleFormat::U32 => render_callback!(u32, U32),
                         }
                     },
                 }
@@ -696,7 +732,10 @@ fn format_to_waveformatextensible(format: &Format) -> Option<mmreg::WAVEFORMATEX
     let format_tag = match format.data_type {
         SampleFormat::I16 => mmreg::WAVE_FORMAT_PCM,
         SampleFormat::F32 => mmreg::WAVE_FORMAT_EXTENSIBLE,
-        SampleFormat::U16 => return None,
+        SampleFormat::I8 | SampleFormat::U8 | SampleFormat::U16 | SampleFormat::I24 |
+        SampleFormat::I24In32 | SampleFormat::I32 | SampleFormat::U32 | SampleFormat::F64 => {
+            return None
+        },
     };
     let channels = format.channels as WORD;
     let sample_rate = format.sample_rate.0 as DWORD;
@@ -711,7 +750,10 @@ fn format_to_waveformatextensible(format: &Format) -> Option<mmreg::WAVEFORMATEX
             let ex_size = mem::size_of::<mmreg::WAVEFORMATEX>();
             (extensible_size - ex_size) as WORD
         },
-        SampleFormat::U16 => return None,
+        SampleFormat::I8 | SampleFormat::U8 | SampleFormat::U16 | SampleFormat::I24 |
+        SampleFormat::I24In32 | SampleFormat::I32 | SampleFormat::U32 | SampleFormat::F64 => {
+            return None
+        },
     };
     let waveformatex = mmreg::WAVEFORMATEX {
         wFormatTag: format_tag,
@@ -731,7 +773,10 @@ fn format_to_waveformatextensible(format: &Format) -> Option<mmreg::WAVEFORMATEX
     let sub_format = match format.data_type {
         SampleFormat::I16 => ksmedia::KSDATAFORMAT_SUBTYPE_PCM,
         SampleFormat::F32 => ksmedia::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT,
-        SampleFormat::U16 => return None,
+        SampleFormat::I8 | SampleFormat::U8 | SampleFormat::U16 | SampleFormat::I24 |
+        SampleFormat::I24In32 | SampleFormat::I32 | SampleFormat::U32 | SampleFormat::F64 => {
+            return None
+        },
     };
     let waveformatextensible = mmreg::WAVEFORMATEXTENSIBLE {
         Format: waveformatex,
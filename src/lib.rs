@@ -97,8 +97,8 @@
 //! While `run()` is running, the audio device of the user will from time to time call the callback
 //! that you passed to this function. The callback gets passed the stream ID and an instance of type
 //! `StreamData` that represents the data that must be read from or written to. The inner
-//! `UnknownTypeOutputBuffer` can be one of `I16`, `U16` or `F32` depending on the format that was
-//! passed to `build_output_stream`.
+//! `UnknownTypeOutputBuffer` has one variant per `SampleFormat`, e.g. `I16`, `U16` or `F32`,
+//! depending on the format that was passed to `build_output_stream`.
 //!
 //! In this example, we simply fill the given output buffer with zeroes.
 //!
@@ -141,6 +141,11 @@
 #![recursion_limit = "512"]
 
 extern crate failure;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
 #[cfg(target_os = "windows")]
 #[macro_use]
 extern crate lazy_static;
@@ -153,14 +158,27 @@ pub use platform::{
     ALL_HOSTS, Device, Devices, EventLoop, Host, HostId, SupportedInputFormats,
     SupportedOutputFormats, StreamId, available_hosts, default_host, host_from_id,
 };
-pub use samples_formats::{Sample, SampleFormat};
+pub use channel_layout::{ChannelLayout, ChannelPosition};
+pub use format_preference::FormatPreference;
+pub use format_request::{ClosestFormat, FormatDifference, FormatRequest, Requirement};
+pub use samples_formats::{I24, I24In32, Sample, SampleFormat, convert_in_place, convert_slice};
 
 use failure::Fail;
 use std::ops::{Deref, DerefMut};
 
+mod callback_guard;
+mod channel_layout;
+pub mod channel_mixer;
+pub mod conversion;
+pub mod dither;
+mod format_preference;
+mod format_request;
+mod gain;
 mod host;
 pub mod platform;
+pub mod resampler;
 mod samples_formats;
+mod simd;
 pub mod traits;
 
 /// A host's device iterator yielding only *input* devices.
@@ -173,19 +191,36 @@ pub type OutputDevices<I> = std::iter::Filter<I, fn(&<I as Iterator>::Item) -> b
 pub type ChannelCount = u16;
 
 /// The number of samples processed per second for a single channel of audio.
+///
+/// With the `serde` feature enabled this is serialized as a plain integer, e.g. `44100`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SampleRate(pub u32);
 
 /// The format of an input or output audio stream.
+///
+/// With the `serde` feature enabled this is serialized as a struct with the fields `channels`,
+/// `sample_rate` and `data_type`, e.g. `{"channels":2,"sample_rate":44100,"data_type":"F32"}` in
+/// JSON, plus `channel_layout` if a layout is specified.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Format {
     pub channels: ChannelCount,
     pub sample_rate: SampleRate,
     pub data_type: SampleFormat,
+    /// The meaning of each channel, if known. When building a stream this requests the given
+    /// channel map from the device, in which case its length must be equal to `channels`.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub channel_layout: Option<ChannelLayout>,
 }
 
 /// Describes a range of supported stream formats.
+///
+/// With the `serde` feature enabled this is serialized as a struct with the fields `channels`,
+/// `min_sample_rate`, `max_sample_rate` and `data_type`, plus `channel_layout` if a layout is
+/// known.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SupportedFormat {
     pub channels: ChannelCount,
     /// Minimum value for the samples rate of the supported formats.
@@ -194,6 +229,60 @@ pub struct SupportedFormat {
     pub max_sample_rate: SampleRate,
     /// Type of data expected by the device.
     pub data_type: SampleFormat,
+    /// The channel map offered by the device for this number of channels, if known.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub channel_layout: Option<ChannelLayout>,
+}
+
+/// An opaque identifier for a `Device` that is unique within the system and stable across
+/// enumerations.
+///
+/// Unlike the device name, a `DeviceId` may be persisted (e.g. as part of a user's settings) and
+/// later used to look up the same device via `HostTrait::device_by_id`, even after a restart of
+/// the application.
+///
+/// With the `serde` feature enabled this is serialized as a struct with the fields `host_id` and
+/// `id`, e.g. `{"host_id":"Alsa","id":"hw:CARD=PCH,DEV=0"}` in JSON. The content of `id` is
+/// specific to the host and should not be relied upon.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeviceId {
+    host_id: HostId,
+    id: String,
+}
+
+/// The stream directions supported by a `Device`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeviceDirections {
+    /// Whether or not input streams may be built on the device.
+    pub input: bool,
+    /// Whether or not output streams may be built on the device.
+    pub output: bool,
+}
+
+/// Metadata describing a `Device`, e.g. for display within a device picker.
+///
+/// Fields that are unknown to the host, or that have no meaning for it, are `None`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeviceInfo {
+    /// A human-readable description of the device.
+    pub description: String,
+    /// The name of the sound card to which the device belongs.
+    pub card_name: Option<String>,
+    /// The index of the sound card to which the device belongs.
+    pub card_index: Option<u32>,
+    /// The name of the driver that handles the device.
+    pub driver: Option<String>,
+    /// The index of the device on its sound card.
+    pub device_index: Option<u32>,
+    /// The index of the subdevice of the device.
+    pub subdevice_index: Option<u32>,
+    /// The stream directions supported by the device.
+    pub directions: DeviceDirections,
+    /// Whether or not the device is the default input or output device of the host.
+    pub is_default: bool,
 }
 
 /// Stream data passed to the `EventLoop::run` callback.
@@ -208,6 +297,8 @@ pub enum StreamData<'a> {
 
 /// Stream data passed to the `EventLoop::run` callback, or an error in the case that the device
 /// was invalidated or some backend-specific error occurred.
+///
+/// The stream is destroyed after an error, unless `StreamError::is_recoverable` returns `true`.
 pub type StreamDataResult<'a> = Result<StreamData<'a>, StreamError>;
 
 /// Represents a buffer containing audio data that may be read.
@@ -242,24 +333,52 @@ where
 ///
 /// Since the type of data is only known at runtime, you have to read the right buffer.
 pub enum UnknownTypeInputBuffer<'a> {
+    /// Samples whose format is `u8`.
+    U8(InputBuffer<'a, u8>),
+    /// Samples whose format is `i8`.
+    I8(InputBuffer<'a, i8>),
     /// Samples whose format is `u16`.
     U16(InputBuffer<'a, u16>),
     /// Samples whose format is `i16`.
     I16(InputBuffer<'a, i16>),
+    /// Samples whose format is `I24`.
+    I24(InputBuffer<'a, I24>),
+    /// Samples whose format is `I24In32`.
+    I24In32(InputBuffer<'a, I24In32>),
+    /// Samples whose format is `u32`.
+    U32(InputBuffer<'a, u32>),
+    /// Samples whose format is `i32`.
+    I32(InputBuffer<'a, i32>),
     /// Samples whose format is `f32`.
     F32(InputBuffer<'a, f32>),
+    /// Samples whose format is `f64`.
+    F64(InputBuffer<'a, f64>),
 }
 
 /// This is the struct that is provided to you by cpal when you want to write samples to a buffer.
 ///
 /// Since the type of data is only known at runtime, you have to fill the right buffer.
 pub enum UnknownTypeOutputBuffer<'a> {
+    /// Samples whose format is `u8`.
+    U8(OutputBuffer<'a, u8>),
+    /// Samples whose format is `i8`.
+    I8(OutputBuffer<'a, i8>),
     /// Samples whose format is `u16`.
     U16(OutputBuffer<'a, u16>),
     /// Samples whose format is `i16`.
     I16(OutputBuffer<'a, i16>),
+    /// Samples whose format is `I24`.
+    I24(OutputBuffer<'a, I24>),
+    /// Samples whose format is `I24In32`.
+    I24In32(OutputBuffer<'a, I24In32>),
+    /// Samples whose format is `u32`.
+    U32(OutputBuffer<'a, u32>),
+    /// Samples whose format is `i32`.
+    I32(OutputBuffer<'a, i32>),
     /// Samples whose format is `f32`.
     F32(OutputBuffer<'a, f32>),
+    /// Samples whose format is `f64`.
+    F64(OutputBuffer<'a, f64>),
 }
 
 /// The requested host, although supported on this platform, is unavailable.
@@ -285,6 +404,20 @@ pub struct BackendSpecificError {
     pub description: String
 }
 
+/// The raw error code returned by the backend API, preserved as the cause of the error variants
+/// that were derived from it.
+///
+/// The meaning of `code` depends on the host. ALSA returns negated `errno` values and WASAPI
+/// returns `HRESULT`s. The other hosts do not report error codes yet.
+#[derive(Clone, Debug, Fail)]
+#[fail(display = "{} (error code {})", description, code)]
+pub struct BackendErrorCode {
+    /// The code as returned by the backend.
+    pub code: i32,
+    /// The description of the code given by the backend.
+    pub description: String,
+}
+
 /// An error that might occur while attempting to enumerate the available devices on a system.
 #[derive(Debug, Fail)]
 pub enum DevicesError {
@@ -307,6 +440,32 @@ pub enum DeviceNameError {
     }
 }
 
+/// An error that may occur while attempting to retrieve a device identifier.
+#[derive(Debug, Fail)]
+pub enum DeviceIdError {
+    /// See the `BackendSpecificError` docs for more information about this error variant.
+    #[fail(display = "{}", err)]
+    BackendSpecific {
+        #[fail(cause)]
+        err: BackendSpecificError,
+    }
+}
+
+/// An error that may occur while attempting to retrieve the `DeviceInfo` of a device.
+#[derive(Debug, Fail)]
+pub enum DeviceInfoError {
+    /// The device no longer exists. This can happen if the device is disconnected while the
+    /// program is running.
+    #[fail(display = "The requested device is no longer available. For example, it has been unplugged.")]
+    DeviceNotAvailable,
+    /// See the `BackendSpecificError` docs for more information about this error variant.
+    #[fail(display = "{}", err)]
+    BackendSpecific {
+        #[fail(cause)]
+        err: BackendSpecificError,
+    }
+}
+
 /// Error that can happen when enumerating the list of supported formats.
 #[derive(Debug, Fail)]
 pub enum SupportedFormatsError {
@@ -314,6 +473,13 @@ pub enum SupportedFormatsError {
     /// program is running.
     #[fail(display = "The requested device is no longer available. For example, it has been unplugged.")]
     DeviceNotAvailable,
+    /// The device is currently in use, for example by another application holding exclusive
+    /// access to it. The device may become available again later.
+    #[fail(display = "The requested device is busy. For example, another application is using it.")]
+    DeviceBusy {
+        #[fail(cause)]
+        err: BackendErrorCode,
+    },
     /// We called something the C-Layer did not understand
     #[fail(display = "Invalid argument passed to the backend. For example, this happens when trying to read capture capabilities when the device does not support it.")]
     InvalidArgument,
@@ -325,6 +491,31 @@ pub enum SupportedFormatsError {
     }
 }
 
+/// May occur when looking for the supported format closest to a `FormatRequest`.
+#[derive(Debug, Fail)]
+pub enum ClosestFormatError {
+    /// The device no longer exists. This can happen if the device is disconnected while the
+    /// program is running.
+    #[fail(display = "The requested device is no longer available. For example, it has been unplugged.")]
+    DeviceNotAvailable,
+    /// The device is currently in use, for example by another application holding exclusive
+    /// access to it. The device may become available again later.
+    #[fail(display = "The requested device is busy. For example, another application is using it.")]
+    DeviceBusy {
+        #[fail(cause)]
+        err: BackendErrorCode,
+    },
+    /// None of the supported formats meets the required properties of the request.
+    #[fail(display = "No supported stream format meets the required properties of the request.")]
+    NoMatchingFormat,
+    /// See the `BackendSpecificError` docs for more information about this error variant.
+    #[fail(display = "{}", err)]
+    BackendSpecific {
+        #[fail(cause)]
+        err: BackendSpecificError,
+    }
+}
+
 /// May occur when attempting to request the default input or output stream format from a `Device`.
 #[derive(Debug, Fail)]
 pub enum DefaultFormatError {
@@ -332,6 +523,13 @@ pub enum DefaultFormatError {
     /// program is running.
     #[fail(display = "The requested device is no longer available. For example, it has been unplugged.")]
     DeviceNotAvailable,
+    /// The device is currently in use, for example by another application holding exclusive
+    /// access to it. The device may become available again later.
+    #[fail(display = "The requested device is busy. For example, another application is using it.")]
+    DeviceBusy {
+        #[fail(cause)]
+        err: BackendErrorCode,
+    },
     /// Returned if e.g. the default input format was requested on an output-only audio device.
     #[fail(display = "The requested stream type is not supported by the device.")]
     StreamTypeNotSupported,
@@ -353,6 +551,19 @@ pub enum BuildStreamError {
     /// The required format is not supported.
     #[fail(display = "The requested stream format is not supported by the device.")]
     FormatNotSupported,
+    /// The device is currently in use, for example by another application holding exclusive
+    /// access to it. Building the stream may succeed later.
+    #[fail(display = "The requested device is busy. For example, another application is using it.")]
+    DeviceBusy {
+        #[fail(cause)]
+        err: BackendErrorCode,
+    },
+    /// The process lacks the permissions to open the device.
+    #[fail(display = "Permission to access the requested device was denied.")]
+    PermissionDenied {
+        #[fail(cause)]
+        err: BackendErrorCode,
+    },
     /// We called something the C-Layer did not understand
     ///
     /// On ALSA device functions called with a feature they do not support will yield this. E.g.
@@ -413,6 +624,24 @@ pub enum StreamError {
     /// program is running.
     #[fail(display = "The requested device is no longer available. For example, it has been unplugged.")]
     DeviceNotAvailable,
+    /// The stream was interrupted, for example because the system was suspended, and has been
+    /// resumed since. Some data was lost, but unlike other errors this does not end the stream.
+    #[fail(display = "The stream was interrupted and has been resumed.")]
+    Interrupted,
+    /// The buffer of the stream ran empty during playback or full during capture because the
+    /// `run` callback was not called in time. Some data was lost, but the stream keeps running.
+    #[fail(display = "A buffer underrun or overrun occurred and some data was lost.")]
+    Xrun {
+        #[fail(cause)]
+        err: BackendErrorCode,
+    },
+    /// The `run` callback panicked while processing the data of the stream. The stream has been
+    /// destroyed, while the other streams of the event loop keep running.
+    #[fail(display = "The `run` callback panicked: {}", message)]
+    CallbackPanicked {
+        /// The message passed to `panic!`.
+        message: String,
+    },
     /// See the `BackendSpecificError` docs for more information about this error variant.
     #[fail(display = "{}", err)]
     BackendSpecific {
@@ -421,6 +650,36 @@ pub enum StreamError {
     }
 }
 
+impl BuildStreamError {
+    /// Whether building the stream again may succeed without any change to the request, such as
+    /// once another application has released the device.
+    pub fn is_recoverable(&self) -> bool {
+        match *self {
+            BuildStreamError::DeviceBusy { .. } => true,
+            BuildStreamError::DeviceNotAvailable |
+            BuildStreamError::FormatNotSupported |
+            BuildStreamError::PermissionDenied { .. } |
+            BuildStreamError::InvalidArgument |
+            BuildStreamError::StreamIdOverflow |
+            BuildStreamError::BackendSpecific { .. } => false,
+        }
+    }
+}
+
+impl StreamError {
+    /// Whether the stream keeps running after this error.
+    ///
+    /// A stream is destroyed after any error that is not recoverable.
+    pub fn is_recoverable(&self) -> bool {
+        match *self {
+            StreamError::Interrupted | StreamError::Xrun { .. } => true,
+            StreamError::DeviceNotAvailable |
+            StreamError::CallbackPanicked { .. } |
+            StreamError::BackendSpecific { .. } => false,
+        }
+    }
+}
+
 impl SupportedFormat {
     /// Turns this `SupportedFormat` into a `Format` corresponding to the maximum samples rate.
     #[inline]
@@ -429,6 +688,7 @@ impl SupportedFormat {
             channels: self.channels,
             sample_rate: self.max_sample_rate,
             data_type: self.data_type,
+            channel_layout: self.channel_layout,
         }
     }
 
@@ -456,51 +716,23 @@ impl SupportedFormat {
     ///
     /// - 44100 (cd quality)
     /// - Max sample rate
+    ///
+    /// This is the order of `FormatPreference::default()`.
     pub fn cmp_default_heuristics(&self, other: &Self) -> std::cmp::Ordering {
-        use std::cmp::Ordering::Equal;
-        use SampleFormat::{F32, I16, U16};
-
-        let cmp_stereo = (self.channels == 2).cmp(&(other.channels == 2));
-        if cmp_stereo != Equal {
-            return cmp_stereo;
-        }
-
-        let cmp_mono = (self.channels == 1).cmp(&(other.channels == 1));
-        if cmp_mono != Equal {
-            return cmp_mono;
-        }
-
-        let cmp_channels = self.channels.cmp(&other.channels);
-        if cmp_channels != Equal {
-            return cmp_channels;
-        }
-
-        let cmp_f32 = (self.data_type == F32).cmp(&(other.data_type == F32));
-        if cmp_f32 != Equal {
-            return cmp_f32;
-        }
-
-        let cmp_i16 = (self.data_type == I16).cmp(&(other.data_type == I16));
-        if cmp_i16 != Equal {
-            return cmp_i16;
-        }
-
-        let cmp_u16 = (self.data_type == U16).cmp(&(other.data_type == U16));
-        if cmp_u16 != Equal {
-            return cmp_u16;
-        }
+        format_preference::cmp_default_formats(self, other)
+    }
+}
 
-        const HZ_44100: SampleRate = SampleRate(44_100);
-        let r44100_in_self = self.min_sample_rate <= HZ_44100
-            && HZ_44100 <= self.max_sample_rate;
-        let r44100_in_other = other.min_sample_rate <= HZ_44100
-            && HZ_44100 <= other.max_sample_rate;
-        let cmp_r44100 = r44100_in_self.cmp(&r44100_in_other);
-        if cmp_r44100 != Equal {
-            return cmp_r44100;
-        }
+impl DeviceId {
+    #[inline]
+    pub(crate) fn new(host_id: HostId, id: String) -> Self {
+        DeviceId { host_id, id }
+    }
 
-        self.max_sample_rate.cmp(&other.max_sample_rate)
+    /// The identifier of the host to which the device belongs.
+    #[inline]
+    pub fn host_id(&self) -> HostId {
+        self.host_id
     }
 }
 
@@ -540,9 +772,16 @@ impl<'a> UnknownTypeInputBuffer<'a> {
     #[inline]
     pub fn len(&self) -> usize {
         match self {
+            &UnknownTypeInputBuffer::U8(ref buf) => buf.len(),
+            &UnknownTypeInputBuffer::I8(ref buf) => buf.len(),
             &UnknownTypeInputBuffer::U16(ref buf) => buf.len(),
             &UnknownTypeInputBuffer::I16(ref buf) => buf.len(),
+            &UnknownTypeInputBuffer::I24(ref buf) => buf.len(),
+            &UnknownTypeInputBuffer::I24In32(ref buf) => buf.len(),
+            &UnknownTypeInputBuffer::U32(ref buf) => buf.len(),
+            &UnknownTypeInputBuffer::I32(ref buf) => buf.len(),
             &UnknownTypeInputBuffer::F32(ref buf) => buf.len(),
+            &UnknownTypeInputBuffer::F64(ref buf) => buf.len(),
         }
     }
 }
@@ -552,9 +791,16 @@ impl<'a> UnknownTypeOutputBuffer<'a> {
     #[inline]
     pub fn len(&self) -> usize {
         match self {
+            &UnknownTypeOutputBuffer::U8(ref buf) => buf.len(),
+            &UnknownTypeOutputBuffer::I8(ref buf) => buf.len(),
             &UnknownTypeOutputBuffer::U16(ref buf) => buf.len(),
             &UnknownTypeOutputBuffer::I16(ref buf) => buf.len(),
+            &UnknownTypeOutputBuffer::I24(ref buf) => buf.len(),
+            &UnknownTypeOutputBuffer::I24In32(ref buf) => buf.len(),
+            &UnknownTypeOutputBuffer::U32(ref buf) => buf.len(),
+            &UnknownTypeOutputBuffer::I32(ref buf) => buf.len(),
             &UnknownTypeOutputBuffer::F32(ref buf) => buf.len(),
+            &UnknownTypeOutputBuffer::F64(ref buf) => buf.len(),
         }
     }
 }
@@ -567,6 +813,7 @@ impl From<Format> for SupportedFormat {
             min_sample_rate: format.sample_rate,
             max_sample_rate: format.sample_rate,
             data_type: format.data_type,
+            channel_layout: format.channel_layout,
         }
     }
 }
@@ -583,12 +830,42 @@ impl From<BackendSpecificError> for DeviceNameError {
     }
 }
 
+impl From<BackendSpecificError> for DeviceIdError {
+    fn from(err: BackendSpecificError) -> Self {
+        DeviceIdError::BackendSpecific { err }
+    }
+}
+
+impl From<BackendSpecificError> for DeviceInfoError {
+    fn from(err: BackendSpecificError) -> Self {
+        DeviceInfoError::BackendSpecific { err }
+    }
+}
+
 impl From<BackendSpecificError> for SupportedFormatsError {
     fn from(err: BackendSpecificError) -> Self {
         SupportedFormatsError::BackendSpecific { err }
     }
 }
 
+impl From<BackendSpecificError> for ClosestFormatError {
+    fn from(err: BackendSpecificError) -> Self {
+        ClosestFormatError::BackendSpecific { err }
+    }
+}
+
+impl From<SupportedFormatsError> for ClosestFormatError {
+    fn from(err: SupportedFormatsError) -> Self {
+        match err {
+            SupportedFormatsError::DeviceNotAvailable => ClosestFormatError::DeviceNotAvailable,
+            SupportedFormatsError::DeviceBusy { err } => ClosestFormatError::DeviceBusy { err },
+            // The device does not support streams of the requested direction at all.
+            SupportedFormatsError::InvalidArgument => ClosestFormatError::NoMatchingFormat,
+            SupportedFormatsError::BackendSpecific { err } => err.into(),
+        }
+    }
+}
+
 impl From<BackendSpecificError> for DefaultFormatError {
     fn from(err: BackendSpecificError) -> Self {
         DefaultFormatError::BackendSpecific { err }
@@ -639,3 +916,108 @@ const COMMON_SAMPLE_RATES: &'static [SampleRate] = &[
     SampleRate(176400),
     SampleRate(192000),
 ];
+
+#[cfg(all(test, feature = "serde"))]
+mod serde_test {
+    use serde_json;
+    use super::{
+        ALL_HOSTS, ChannelLayout, ChannelPosition, DeviceId, Format, HostId, SampleFormat,
+        SampleRate, SupportedFormat,
+    };
+
+    #[test]
+    fn sample_rate_representation() {
+        assert_eq!(serde_json::to_string(&SampleRate(44_100)).unwrap(), "44100");
+        let rate: SampleRate = serde_json::from_str("48000").unwrap();
+        assert_eq!(rate, SampleRate(48_000));
+    }
+
+    #[test]
+    fn sample_format_representation() {
+        for &(format, json) in &[
+            (SampleFormat::I8, "\"I8\""),
+            (SampleFormat::I16, "\"I16\""),
+            (SampleFormat::U16, "\"U16\""),
+            (SampleFormat::I24In32, "\"I24In32\""),
+            (SampleFormat::U32, "\"U32\""),
+            (SampleFormat::F32, "\"F32\""),
+            (SampleFormat::F64, "\"F64\""),
+        ] {
+            assert_eq!(serde_json::to_string(&format).unwrap(), json);
+            assert_eq!(serde_json::from_str::<SampleFormat>(json).unwrap(), format);
+        }
+    }
+
+    #[test]
+    fn format_representation() {
+        let format = Format {
+            channels: 2,
+            sample_rate: SampleRate(44_100),
+            data_type: SampleFormat::F32,
+            channel_layout: None,
+        };
+        let json = r#"{"channels":2,"sample_rate":44100,"data_type":"F32"}"#;
+        assert_eq!(serde_json::to_string(&format).unwrap(), json);
+        assert_eq!(serde_json::from_str::<Format>(json).unwrap(), format);
+    }
+
+    #[test]
+    fn supported_format_round_trip() {
+        let format = SupportedFormat {
+            channels: 6,
+            min_sample_rate: SampleRate(8_000),
+            max_sample_rate: SampleRate(192_000),
+            data_type: SampleFormat::I16,
+            channel_layout: None,
+        };
+        let json = serde_json::to_string(&format).unwrap();
+        assert_eq!(
+            json,
+            r#"{"channels":6,"min_sample_rate":8000,"max_sample_rate":192000,"data_type":"I16"}"#,
+        );
+        assert_eq!(serde_json::from_str::<SupportedFormat>(&json).unwrap(), format);
+    }
+
+    #[test]
+    fn format_with_channel_layout_representation() {
+        let format = Format {
+            channels: 2,
+            sample_rate: SampleRate(48_000),
+            data_type: SampleFormat::I16,
+            channel_layout: Some(ChannelLayout::new(vec![
+                ChannelPosition::FrontLeft,
+                ChannelPosition::Discrete(1),
+            ])),
+        };
+        let json = r#"{"channels":2,"sample_rate":48000,"data_type":"I16","channel_layout":["FrontLeft",{"Discrete":1}]}"#;
+        assert_eq!(serde_json::to_string(&format).unwrap(), json);
+        assert_eq!(serde_json::from_str::<Format>(json).unwrap(), format);
+    }
+
+    #[test]
+    fn host_id_round_trip() {
+        for &host_id in ALL_HOSTS {
+            let json = serde_json::to_string(&host_id).unwrap();
+            assert_eq!(serde_json::from_str::<HostId>(&json).unwrap(), host_id);
+        }
+    }
+
+    #[test]
+    fn device_id_round_trip() {
+        for &host_id in ALL_HOSTS {
+            let device_id = DeviceId::new(host_id, "hw:CARD=PCH,DEV=0".to_string());
+            let json = serde_json::to_string(&device_id).unwrap();
+            let expected = format!(
+                r#"{{"host_id":{},"id":"hw:CARD=PCH,DEV=0"}}"#,
+                serde_json::to_string(&host_id).unwrap(),
+            );
+            assert_eq!(json, expected);
+            assert_eq!(serde_json::from_str::<DeviceId>(&json).unwrap(), device_id);
+        }
+    }
+
+    #[test]
+    fn unknown_sample_format_is_rejected() {
+        assert!(serde_json::from_str::<SampleFormat>("\"I20\"").is_err());
+    }
+}
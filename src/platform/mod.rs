@@ -60,7 +60,23 @@ macro_rules! impl_platform_host {
 
         /// The **EventLoop** implementation associated with the platform's dynamically dispatched
         /// **Host** type.
-        pub struct EventLoop(EventLoopInner);
+        ///
+        /// In addition to the `EventLoopTrait`, it applies a software volume to each of its
+        /// streams, which works the same way with every host and sample format.
+        ///
+        /// A panic in the `run` callback only stops the stream whose data was being processed. The
+        /// stream is destroyed and reported with `StreamError::CallbackPanicked`, while the other
+        /// streams keep running. CoreAudio cannot destroy a stream from within its callback, so
+        /// such streams, and those that have faded out before being destroyed, stay silent until
+        /// the next call to build, play, pause or destroy a stream.
+        pub struct EventLoop {
+            inner: EventLoopInner,
+            gains: crate::gain::StreamGains<StreamId>,
+            guard: crate::callback_guard::CallbackGuard<StreamId>,
+            // Streams to destroy outside of the `run` callback, on hosts that do not allow
+            // destroying streams from within it.
+            pending_destroys: std::sync::Mutex<Vec<StreamId>>,
+        }
 
         /// The **StreamId** implementation associated with the platform's dynamically dispatched
         /// **Host** type.
@@ -76,7 +92,11 @@ macro_rules! impl_platform_host {
         pub struct SupportedOutputFormats(SupportedOutputFormatsInner);
 
         /// Unique identifier for available hosts on the platform.
+        ///
+        /// With the `serde` feature enabled this is serialized as the name of the variant, e.g.
+        /// `"Alsa"` or `"Wasapi"`.
         #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+        #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
         pub enum HostId {
             $(
                 $HostVariant,
@@ -147,6 +167,108 @@ macro_rules! impl_platform_host {
             }
         }
 
+        impl EventLoop {
+            fn new(inner: EventLoopInner) -> Self {
+                EventLoop {
+                    inner,
+                    gains: crate::gain::StreamGains::new(),
+                    guard: crate::callback_guard::CallbackGuard::new(),
+                    pending_destroys: std::sync::Mutex::new(Vec::new()),
+                }
+            }
+
+            // Destroys the streams that `run` could not destroy from within the callback. Called
+            // before any other operation on the streams of the host.
+            fn destroy_pending_streams(&self) {
+                let pending: Vec<_> = self.pending_destroys.lock().unwrap().drain(..).collect();
+                for stream in pending {
+                    self.destroy_host_stream(stream);
+                }
+            }
+
+            #[allow(unreachable_patterns)]
+            fn destroy_host_stream(&self, stream: StreamId) {
+                self.gains.remove(&stream);
+                match (&self.inner, stream.0.clone()) {
+                    $(
+                        (&EventLoopInner::$HostVariant(ref e), StreamIdInner::$HostVariant(ref s)) => {
+                            crate::traits::EventLoopTrait::destroy_stream(e, s.clone())
+                        }
+                    )*
+                    _ => panic!("tried to destroy a stream with an ID associated with another host"),
+                }
+                self.guard.remove(&stream);
+            }
+
+            /// Sets the software volume of the stream with the given ID, as a factor applied to
+            /// the amplitude of its samples.
+            ///
+            /// The default of `1.0` leaves the samples unchanged. Larger values amplify them and
+            /// may clip, negative values are treated as `0.0`. The change is ramped over the
+            /// `volume_ramp` duration, starting with the next buffer of the stream. Output is
+            /// adjusted after the `run` callback has written it, input before the callback reads
+            /// it.
+            ///
+            /// Has no effect if the stream does not exist.
+            pub fn set_stream_volume(&self, stream: StreamId, volume: f32) {
+                self.gains.set_volume(&stream, volume);
+            }
+
+            /// The software volume of the stream with the given ID, or `None` if the stream does
+            /// not exist.
+            ///
+            /// Muting a stream does not change its volume.
+            pub fn stream_volume(&self, stream: StreamId) -> Option<f32> {
+                self.gains.volume(&stream)
+            }
+
+            /// Mutes or unmutes the stream with the given ID, ramping its volume like
+            /// `set_stream_volume`.
+            ///
+            /// Has no effect if the stream does not exist.
+            pub fn set_stream_muted(&self, stream: StreamId, muted: bool) {
+                self.gains.set_muted(&stream, muted);
+            }
+
+            /// Whether the stream with the given ID is muted, or `None` if the stream does not
+            /// exist.
+            pub fn stream_is_muted(&self, stream: StreamId) -> Option<bool> {
+                self.gains.is_muted(&stream)
+            }
+
+            /// Sets the duration over which the volume of a stream changes after a call to
+            /// `set_stream_volume` or `set_stream_muted`. Defaults to 20 ms.
+            ///
+            /// A duration of zero applies changes immediately.
+            pub fn set_volume_ramp(&self, ramp: std::time::Duration) {
+                self.gains.set_ramp(ramp);
+            }
+
+            /// The duration over which volume changes are ramped.
+            pub fn volume_ramp(&self) -> std::time::Duration {
+                self.gains.ramp()
+            }
+
+            /// Sets the duration over which the output stream with the given ID fades in when it
+            /// is played, and fades out before it is paused or destroyed.
+            ///
+            /// With a fade, `pause_stream` and `destroy_stream` return immediately and the stream
+            /// keeps calling the `run` callback until its output has faded to silence. Playing the
+            /// stream again during a fade-out cancels the pending pause. Defaults to zero, which
+            /// starts and stops the stream abruptly. Input streams are not faded.
+            ///
+            /// Has no effect if the stream does not exist.
+            pub fn set_stream_fade(&self, stream: StreamId, fade: std::time::Duration) {
+                self.gains.set_fade(&stream, fade);
+            }
+
+            /// The fade duration of the stream with the given ID, or `None` if the stream does not
+            /// exist.
+            pub fn stream_fade(&self, stream: StreamId) -> Option<std::time::Duration> {
+                self.gains.fade(&stream)
+            }
+        }
+
         impl Iterator for Devices {
             type Item = Device;
 
@@ -221,6 +343,22 @@ macro_rules! impl_platform_host {
                 }
             }
 
+            fn id(&self) -> Result<crate::DeviceId, crate::DeviceIdError> {
+                match self.0 {
+                    $(
+                        DeviceInner::$HostVariant(ref d) => d.id(),
+                    )*
+                }
+            }
+
+            fn info(&self) -> Result<crate::DeviceInfo, crate::DeviceInfoError> {
+                match self.0 {
+                    $(
+                        DeviceInner::$HostVariant(ref d) => d.info(),
+                    )*
+                }
+            }
+
             fn supported_input_formats(&self) -> Result<Self::SupportedInputFormats, crate::SupportedFormatsError> {
                 match self.0 {
                     $(
@@ -260,6 +398,44 @@ macro_rules! impl_platform_host {
                     )*
                 }
             }
+
+            fn default_input_format_with(
+                &self,
+                preference: &crate::FormatPreference,
+            ) -> Result<crate::Format, crate::DefaultFormatError> {
+                match self.0 {
+                    $(
+                        DeviceInner::$HostVariant(ref d) => d.default_input_format_with(preference),
+                    )*
+                }
+            }
+
+            fn default_output_format_with(
+                &self,
+                preference: &crate::FormatPreference,
+            ) -> Result<crate::Format, crate::DefaultFormatError> {
+                match self.0 {
+                    $(
+                        DeviceInner::$HostVariant(ref d) => d.default_output_format_with(preference),
+                    )*
+                }
+            }
+
+            fn supports_input(&self) -> bool {
+                match self.0 {
+                    $(
+                        DeviceInner::$HostVariant(ref d) => d.supports_input(),
+                    )*
+                }
+            }
+
+            fn supports_output(&self) -> bool {
+                match self.0 {
+                    $(
+                        DeviceInner::$HostVariant(ref d) => d.supports_output(),
+                    )*
+                }
+            }
         }
 
         impl crate::traits::EventLoopTrait for EventLoop {
@@ -272,16 +448,21 @@ macro_rules! impl_platform_host {
                 device: &Self::Device,
                 format: &crate::Format,
             ) -> Result<Self::StreamId, crate::BuildStreamError> {
-                match (&self.0, &device.0) {
+                self.destroy_pending_streams();
+                let stream = match (&self.inner, &device.0) {
                     $(
                         (&EventLoopInner::$HostVariant(ref e), &DeviceInner::$HostVariant(ref d)) => {
                             e.build_input_stream(d, format)
                                 .map(StreamIdInner::$HostVariant)
-                                .map(StreamId)
+                                .map(StreamId)?
                         }
                     )*
                     _ => panic!("tried to build a stream with a device from another host"),
-                }
+                };
+                self.gains.add_input(stream.clone(), format);
+                // Hosts may reuse the ID of a stream destroyed after its callback panicked.
+                self.guard.remove(&stream);
+                Ok(stream)
             }
 
             #[allow(unreachable_patterns)]
@@ -290,33 +471,49 @@ macro_rules! impl_platform_host {
                 device: &Self::Device,
                 format: &crate::Format,
             ) -> Result<Self::StreamId, crate::BuildStreamError> {
-                match (&self.0, &device.0) {
+                self.destroy_pending_streams();
+                let stream = match (&self.inner, &device.0) {
                     $(
                         (&EventLoopInner::$HostVariant(ref e), &DeviceInner::$HostVariant(ref d)) => {
                             e.build_output_stream(d, format)
                                 .map(StreamIdInner::$HostVariant)
-                                .map(StreamId)
+                                .map(StreamId)?
                         }
                     )*
                     _ => panic!("tried to build a stream with a device from another host"),
-                }
+                };
+                self.gains.add_output(stream.clone(), format);
+                // Hosts may reuse the ID of a stream destroyed after its callback panicked.
+                self.guard.remove(&stream);
+                Ok(stream)
             }
 
             #[allow(unreachable_patterns)]
             fn play_stream(&self, stream: Self::StreamId) -> Result<(), crate::PlayStreamError> {
-                match (&self.0, stream.0) {
+                self.destroy_pending_streams();
+                self.gains.play(&stream);
+                let result = match (&self.inner, stream.0.clone()) {
                     $(
                         (&EventLoopInner::$HostVariant(ref e), StreamIdInner::$HostVariant(ref s)) => {
                             e.play_stream(s.clone())
                         }
                     )*
                     _ => panic!("tried to play a stream with an ID associated with another host"),
+                };
+                if result.is_err() {
+                    self.gains.stopped(&stream);
                 }
+                result
             }
 
             #[allow(unreachable_patterns)]
             fn pause_stream(&self, stream: Self::StreamId) -> Result<(), crate::PauseStreamError> {
-                match (&self.0, stream.0) {
+                self.destroy_pending_streams();
+                // The stream is paused by `run` once its output has faded out.
+                if self.gains.fade_out(&stream, crate::gain::FadeAction::Pause) {
+                    return Ok(());
+                }
+                match (&self.inner, stream.0) {
                     $(
                         (&EventLoopInner::$HostVariant(ref e), StreamIdInner::$HostVariant(ref s)) => {
                             e.pause_stream(s.clone())
@@ -326,15 +523,28 @@ macro_rules! impl_platform_host {
                 }
             }
 
-            #[allow(unreachable_patterns)]
             fn destroy_stream(&self, stream: Self::StreamId) {
-                match (&self.0, stream.0) {
+                self.destroy_pending_streams();
+                // The stream is destroyed by `run` once its output has faded out.
+                if self.gains.fade_out(&stream, crate::gain::FadeAction::Destroy) {
+                    return;
+                }
+                self.destroy_host_stream(stream);
+            }
+
+            fn is_destroy_pending(&self, stream: Self::StreamId) -> bool {
+                self.gains.is_destroy_pending(&stream)
+            }
+
+            #[allow(unreachable_patterns)]
+            fn stream_channel_layout(&self, stream: Self::StreamId) -> Option<crate::ChannelLayout> {
+                match (&self.inner, stream.0) {
                     $(
                         (&EventLoopInner::$HostVariant(ref e), StreamIdInner::$HostVariant(ref s)) => {
-                            e.destroy_stream(s.clone())
+                            e.stream_channel_layout(s.clone())
                         }
                     )*
-                    _ => panic!("tried to destroy a stream with an ID associated with another host"),
+                    _ => None,
                 }
             }
 
@@ -342,12 +552,51 @@ macro_rules! impl_platform_host {
             where
                 F: FnMut(Self::StreamId, crate::StreamDataResult) + Send
             {
-                match self.0 {
+                let gains = &self.gains;
+                let guard = &self.guard;
+                let pending_destroys = &self.pending_destroys;
+                match self.inner {
                     $(
                         EventLoopInner::$HostVariant(ref e) => {
+                            let destroy = |id: crate::host::$host_mod::StreamId, stream: &StreamId| {
+                                gains.remove(stream);
+                                if crate::host::$host_mod::CAN_DESTROY_STREAMS_IN_CALLBACK {
+                                    // The host does not call back for the stream again.
+                                    e.destroy_stream(id);
+                                    guard.remove(stream);
+                                } else {
+                                    // The stream stays silent until it is destroyed by the next
+                                    // call to the event loop.
+                                    guard.silence(stream);
+                                    pending_destroys.lock().unwrap().push(stream.clone());
+                                }
+                            };
                             e.run(|id, result| {
-                                let result = result;
-                                callback(StreamId(StreamIdInner::$HostVariant(id)), result);
+                                let stream = StreamId(StreamIdInner::$HostVariant(id.clone()));
+                                let action = guard.call(&stream, result, |result| {
+                                    gains.process(stream.clone(), result, &mut callback)
+                                });
+                                match action {
+                                    Ok(Some(crate::gain::FadeAction::Pause)) => {
+                                        // There is no caller to report a failure to, the stream
+                                        // then keeps running silently.
+                                        let _ = e.pause_stream(id);
+                                    },
+                                    Ok(Some(crate::gain::FadeAction::Destroy)) => {
+                                        destroy(id, &stream);
+                                    },
+                                    Ok(None) => (),
+                                    Err(message) => {
+                                        // Only the stream of the panicking callback is stopped.
+                                        destroy(id, &stream);
+                                        let err = crate::StreamError::CallbackPanicked { message };
+                                        let _ = ::std::panic::catch_unwind(
+                                            ::std::panic::AssertUnwindSafe(|| {
+                                                callback(stream, Err(err))
+                                            }),
+                                        );
+                                    },
+                                }
                             });
                         },
                     )*
@@ -398,7 +647,20 @@ macro_rules! impl_platform_host {
                 match self.0 {
                     $(
                         HostInner::$HostVariant(ref h) => {
-                            EventLoop(EventLoopInner::$HostVariant(h.event_loop()))
+                            EventLoop::new(EventLoopInner::$HostVariant(h.event_loop()))
+                        }
+                    )*
+                }
+            }
+
+            fn device_by_id(
+                &self,
+                id: &crate::DeviceId,
+            ) -> Result<Option<Self::Device>, crate::DevicesError> {
+                match self.0 {
+                    $(
+                        HostInner::$HostVariant(ref h) => {
+                            h.device_by_id(id).map(|d| d.map(DeviceInner::$HostVariant).map(Device))
                         }
                     )*
                 }
@@ -422,7 +684,7 @@ macro_rules! impl_platform_host {
 
             impl From<crate::host::$host_mod::EventLoop> for EventLoop {
                 fn from(h: crate::host::$host_mod::EventLoop) -> Self {
-                    EventLoop(EventLoopInner::$HostVariant(h))
+                    EventLoop::new(EventLoopInner::$HostVariant(h))
                 }
             }
 
@@ -486,6 +748,22 @@ mod platform_impl {
             .expect("the default host should always be available")
             .into()
     }
+
+    /// Functionality specific to the ALSA host.
+    pub mod alsa {
+        pub use crate::host::alsa::card::{
+            Card,
+            CardError,
+            CardInfo,
+            Cards,
+            Element,
+            ElementInterface,
+            ElementType,
+            ElementValue,
+            Elements,
+        };
+        pub use crate::host::alsa::mixer::{Control, ControlDirection, Controls, Mixer, MixerError};
+    }
 }
 
 
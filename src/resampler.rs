@@ -0,0 +1,519 @@
+//! Streaming sample rate conversion of interleaved multi-channel buffers.
+//!
+//! A `Resampler` converts a stream of frames from one sample rate to another. Input is `push`ed
+//! in chunks of any size and output is `pop`ped as it becomes available. This allows it to be used
+//! both when the amount of input is given (e.g. input streams) and when the amount of output is
+//! given (e.g. output streams, using `input_frames_needed`).
+//!
+//! ```
+//! use cpal::resampler::{Quality, Resampler};
+//!
+//! let mut resampler = Resampler::new(2, 44_100, 48_000, Quality::MEDIUM);
+//! let input = vec![0.0f32; 2 * 441];
+//! let mut output = vec![0.0f32; 2 * 480];
+//! resampler.push(&input);
+//! let frames = resampler.pop(&mut output);
+//! assert!(frames <= 480);
+//! ```
+
+use std::f64::consts::PI;
+
+use Sample;
+
+/// The interpolation used by a `Resampler`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quality {
+    /// Linear interpolation between adjacent frames. Very cheap, but attenuates high frequencies
+    /// and does not filter frequencies above the Nyquist frequency of the output when
+    /// downsampling, which causes aliasing.
+    Linear,
+    /// Interpolation with a Kaiser-windowed sinc kernel spanning the given number of zero
+    /// crossings on each side. More zero crossings give a steeper low-pass filter, and so a wider
+    /// passband, at a higher cost and latency. Zero is treated as one zero crossing.
+    Sinc(usize),
+}
+
+impl Quality {
+    /// A short windowed-sinc kernel, passing up to roughly 70% of the Nyquist frequency.
+    pub const LOW: Quality = Quality::Sinc(16);
+    /// A windowed-sinc kernel passing up to roughly 85% of the Nyquist frequency.
+    pub const MEDIUM: Quality = Quality::Sinc(32);
+    /// A long windowed-sinc kernel passing up to roughly 92% of the Nyquist frequency.
+    pub const HIGH: Quality = Quality::Sinc(64);
+}
+
+// The number of kernel values per zero crossing stored in the table.
+const OVERSAMPLING: usize = 256;
+
+// The shape parameter of the Kaiser window, giving a stopband attenuation of about 90 dB.
+const KAISER_BETA: f64 = 9.0;
+
+// The number of frames kept before the oldest frame still needed, so that changing the ratio
+// slightly does not require frames that have already been discarded.
+const HISTORY_MARGIN: usize = 2;
+
+// The factor by which `set_ratio` may lower the ratio between two calls to `pop`. Lowering the
+// ratio below 1 widens the kernel, so history is kept for the kernel of the lowered ratio.
+const MAX_RATIO_DECREASE: f64 = 2.0;
+
+/// A streaming sample rate converter for interleaved frames.
+///
+/// The ratio may be changed at any time with `set_ratio`, e.g. to correct for clock drift between
+/// two devices.
+#[derive(Clone, Debug)]
+pub struct Resampler {
+    channels: usize,
+    quality: Quality,
+    // The cutoff frequency of the kernel relative to the lower of the two Nyquist frequencies.
+    rolloff: f64,
+    // The number of input frames per output frame.
+    step: f64,
+    // The windowed sinc kernel from 0 to the number of zero crossings. Empty for `Linear`.
+    kernel: Vec<f64>,
+    // The position of the next output frame in input frames, relative to the start of `buffer`.
+    position: f64,
+    // Interleaved input frames that are still needed.
+    buffer: Vec<f32>,
+}
+
+impl Resampler {
+    /// Creates a resampler for frames of `channels` samples from `from_rate` to `to_rate`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channels` or either rate is zero.
+    pub fn new(channels: usize, from_rate: u32, to_rate: u32, quality: Quality) -> Self {
+        assert!(channels > 0, "a resampler needs at least one channel");
+        assert!(from_rate > 0 && to_rate > 0, "sample rates must not be zero");
+        let quality = match quality {
+            Quality::Sinc(0) => Quality::Sinc(1),
+            quality => quality,
+        };
+        let (kernel, rolloff) = match quality {
+            Quality::Linear => (Vec::new(), 1.0),
+            Quality::Sinc(zero_crossings) => {
+                (sinc_kernel(zero_crossings), sinc_rolloff(zero_crossings))
+            },
+        };
+        Resampler {
+            channels,
+            quality,
+            rolloff,
+            step: from_rate as f64 / to_rate as f64,
+            kernel,
+            position: 0.0,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// The number of channels per frame.
+    #[inline]
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// The interpolation used by the resampler.
+    #[inline]
+    pub fn quality(&self) -> Quality {
+        self.quality
+    }
+
+    /// The number of output frames per input frame, i.e. the output rate divided by the input rate.
+    #[inline]
+    pub fn ratio(&self) -> f64 {
+        1.0 / self.step
+    }
+
+    /// Changes the number of output frames per input frame for all following output.
+    ///
+    /// The ratio may be lowered by up to a factor of two between two calls to `pop`. Lowering it
+    /// further at once truncates the kernel for the next few output frames, because the input
+    /// frames the wider kernel needs have already been discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ratio` is not a positive number.
+    pub fn set_ratio(&mut self, ratio: f64) {
+        assert!(ratio > 0.0 && ratio.is_finite(), "the ratio must be a positive number");
+        self.step = 1.0 / ratio;
+    }
+
+    /// The delay introduced by the resampler, in input frames.
+    ///
+    /// An output frame can only be produced once this many input frames following it have been
+    /// pushed.
+    pub fn latency(&self) -> usize {
+        self.half_width().ceil() as usize
+    }
+
+    /// Discards all buffered input, as if the resampler was newly created.
+    pub fn reset(&mut self) {
+        self.position = 0.0;
+        self.buffer.clear();
+    }
+
+    /// Appends interleaved input frames. The length of `input` must be a multiple of the number
+    /// of channels.
+    pub fn push<S>(&mut self, input: &[S])
+    where
+        S: Sample,
+    {
+        debug_assert_eq!(input.len() % self.channels, 0);
+        self.buffer.extend(input.iter().map(Sample::to_f32));
+    }
+
+    /// The number of input frames that must be pushed before `frames` output frames are available.
+    pub fn input_frames_needed(&self, frames: usize) -> usize {
+        self.frames_required(frames).saturating_sub(self.buffered_frames())
+    }
+
+    /// The number of output frames that can be popped from the input pushed so far.
+    pub fn available_frames(&self) -> usize {
+        let buffered = self.buffered_frames();
+        if self.frames_required(1) > buffered {
+            return 0;
+        }
+        // Estimate, then correct for rounding so that the result agrees with `frames_required`.
+        let estimate = (buffered as f64 - self.half_width() - self.position) / self.step;
+        let mut frames = estimate.max(0.0) as usize + 1;
+        while frames > 0 && self.frames_required(frames) > buffered {
+            frames -= 1;
+        }
+        while self.frames_required(frames + 1) <= buffered {
+            frames += 1;
+        }
+        frames
+    }
+
+    /// Writes as many available output frames as fit into `output`, which must have a length
+    /// that is a multiple of the number of channels.
+    ///
+    /// Returns the number of frames written.
+    pub fn pop<S>(&mut self, output: &mut [S]) -> usize
+    where
+        S: Sample,
+    {
+        debug_assert_eq!(output.len() % self.channels, 0);
+        let frames = ::std::cmp::min(output.len() / self.channels, self.available_frames());
+        let half_width = self.half_width();
+        let cutoff = self.cutoff();
+        for (i, frame) in output.chunks_mut(self.channels).take(frames).enumerate() {
+            let position = self.position + i as f64 * self.step;
+            match self.quality {
+                Quality::Linear => self.interpolate_linear(position, frame),
+                Quality::Sinc(_) => self.interpolate_sinc(position, half_width, cutoff, frame),
+            }
+        }
+
+        // Discard the frames that are no longer needed, even if the ratio is lowered next.
+        let position = self.position + frames as f64 * self.step;
+        let history = self.half_width_at(self.step * MAX_RATIO_DECREASE);
+        let oldest_needed = (position - history).floor().max(0.0) as usize;
+        let discarded = oldest_needed.saturating_sub(HISTORY_MARGIN);
+        let discarded = ::std::cmp::min(discarded, self.buffered_frames());
+        self.buffer.drain(..discarded * self.channels);
+        self.position = position - discarded as f64;
+        frames
+    }
+
+    /// Pushes `input` and appends all output frames that become available to `output`.
+    pub fn process<S, T>(&mut self, input: &[S], output: &mut Vec<T>)
+    where
+        S: Sample,
+        T: Sample,
+    {
+        self.push(input);
+        let len = output.len();
+        let available = self.available_frames();
+        output.resize(len + available * self.channels, T::EQUILIBRIUM);
+        self.pop(&mut output[len..]);
+    }
+
+    #[inline]
+    fn buffered_frames(&self) -> usize {
+        self.buffer.len() / self.channels
+    }
+
+    // The cutoff frequency of the kernel relative to the Nyquist frequency of the input.
+    #[inline]
+    fn cutoff(&self) -> f64 {
+        self.cutoff_at(self.step)
+    }
+
+    #[inline]
+    fn cutoff_at(&self, step: f64) -> f64 {
+        self.rolloff * if step > 1.0 { 1.0 / step } else { 1.0 }
+    }
+
+    // The number of input frames on each side of an output frame that contribute to it.
+    #[inline]
+    fn half_width(&self) -> f64 {
+        self.half_width_at(self.step)
+    }
+
+    #[inline]
+    fn half_width_at(&self, step: f64) -> f64 {
+        match self.quality {
+            Quality::Linear => 1.0,
+            Quality::Sinc(zero_crossings) => zero_crossings as f64 / self.cutoff_at(step),
+        }
+    }
+
+    // The number of input frames that must be buffered to produce `frames` output frames.
+    fn frames_required(&self, frames: usize) -> usize {
+        if frames == 0 {
+            return 0;
+        }
+        let last = self.position + (frames - 1) as f64 * self.step;
+        (last + self.half_width()).floor() as usize + 1
+    }
+
+    fn frame(&self, index: usize) -> &[f32] {
+        &self.buffer[index * self.channels..(index + 1) * self.channels]
+    }
+
+    fn interpolate_linear<S>(&self, position: f64, output: &mut [S])
+    where
+        S: Sample,
+    {
+        let index = position.floor() as usize;
+        let fraction = (position - index as f64) as f32;
+        let current = self.frame(index);
+        let next = self.frame(index + 1);
+        for ((out, &a), &b) in output.iter_mut().zip(current).zip(next) {
+            *out = S::from(&(a + (b - a) * fraction));
+        }
+    }
+
+    fn interpolate_sinc<S>(&self, position: f64, half_width: f64, cutoff: f64, output: &mut [S])
+    where
+        S: Sample,
+    {
+        let mut sums = [0.0f64; 32];
+        let mut sums_vec;
+        let sums: &mut [f64] = if self.channels <= sums.len() {
+            &mut sums[..self.channels]
+        } else {
+            sums_vec = vec![0.0; self.channels];
+            &mut sums_vec
+        };
+
+        // Frames before the start of the stream are silent.
+        let first = (position - half_width).floor() + 1.0;
+        let first = if first < 0.0 { 0 } else { first as usize };
+        let last = (position + half_width).floor() as usize;
+        for index in first..last + 1 {
+            let weight = cutoff * self.kernel_at((position - index as f64).abs() * cutoff);
+            for (sum, &sample) in sums.iter_mut().zip(self.frame(index)) {
+                *sum += weight * sample as f64;
+            }
+        }
+        for (out, &sum) in output.iter_mut().zip(sums.iter()) {
+            *out = S::from(&(sum as f32));
+        }
+    }
+
+    // The kernel at `x` zero crossings from its center, interpolated from the table.
+    #[inline]
+    fn kernel_at(&self, x: f64) -> f64 {
+        let x = x * OVERSAMPLING as f64;
+        let index = x as usize;
+        if index + 1 >= self.kernel.len() {
+            return 0.0;
+        }
+        let fraction = x - index as f64;
+        self.kernel[index] + (self.kernel[index + 1] - self.kernel[index]) * fraction
+    }
+}
+
+// Places the cutoff so that the transition band of the Kaiser window, which narrows as the
+// kernel gets longer, mostly lies below the Nyquist frequency.
+fn sinc_rolloff(zero_crossings: usize) -> f64 {
+    (1.0 - 2.5 / zero_crossings as f64).max(0.5)
+}
+
+// Tabulates the sinc function windowed with a Kaiser window from 0 to `zero_crossings`, with
+// `OVERSAMPLING` values per zero crossing.
+fn sinc_kernel(zero_crossings: usize) -> Vec<f64> {
+    assert!(zero_crossings > 0, "a sinc kernel needs at least one zero crossing");
+    let len = zero_crossings * OVERSAMPLING + 1;
+    let i0_beta = bessel_i0(KAISER_BETA);
+    (0..len)
+        .map(|i| {
+            let x = i as f64 / OVERSAMPLING as f64;
+            let sinc = if i == 0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+            let t = x / zero_crossings as f64;
+            let window = bessel_i0(KAISER_BETA * (1.0 - t * t).max(0.0).sqrt()) / i0_beta;
+            sinc * window
+        })
+        .collect()
+}
+
+// The zeroth order modified Bessel function of the first kind.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-12 {
+        term *= (x / (2.0 * k)) * (x / (2.0 * k));
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::PI;
+
+    use super::{Quality, Resampler};
+
+    fn sine(frequency: f64, rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|i| (2.0 * PI * frequency * i as f64 / rate as f64).sin() as f32)
+            .collect()
+    }
+
+    // Resamples a mono signal and returns the output, without the first frames that are affected
+    // by the start of the stream, and the number of frames skipped.
+    fn resample(input: &[f32], from: u32, to: u32, quality: Quality) -> (usize, Vec<f32>) {
+        let mut resampler = Resampler::new(1, from, to, quality);
+        let mut output = Vec::new();
+        // Push in uneven chunks to exercise the streaming state.
+        for chunk in input.chunks(317) {
+            resampler.process(chunk, &mut output);
+        }
+        let skip = (resampler.latency() as f64 * resampler.ratio()).ceil() as usize + 1;
+        (skip, output.split_off(skip))
+    }
+
+    fn rms(signal: &[f32]) -> f64 {
+        let sum: f64 = signal.iter().map(|&s| s as f64 * s as f64).sum();
+        (sum / signal.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn output_length_follows_ratio() {
+        for &quality in &[Quality::Linear, Quality::LOW, Quality::HIGH] {
+            let mut resampler = Resampler::new(2, 44_100, 48_000, quality);
+            let mut output = vec![0.0f32; 2 * 512];
+            let mut pushed = 0;
+            for _ in 0..100 {
+                let needed = resampler.input_frames_needed(512);
+                resampler.push(&vec![0.0f32; 2 * needed]);
+                pushed += needed;
+                assert_eq!(resampler.pop(&mut output), 512);
+            }
+            let expected = 100.0 * 512.0 * 44_100.0 / 48_000.0;
+            let expected = expected as usize + resampler.latency();
+            assert!(pushed >= expected - 1 && pushed <= expected + 1, "{} {}", pushed, expected);
+        }
+    }
+
+    #[test]
+    fn passband_is_preserved() {
+        for &frequency in &[100.0, 1_000.0, 10_000.0, 18_000.0] {
+            let input = sine(frequency, 44_100, 11_025);
+            let (skip, output) = resample(&input, 44_100, 48_000, Quality::HIGH);
+            let expected = sine(frequency, 48_000, 12_000);
+            let error: Vec<f32> = output.iter()
+                .zip(&expected[skip..])
+                .map(|(&a, &b)| a - b)
+                .collect();
+            // The sine at the output rate is reproduced to within -60 dB.
+            assert!(rms(&error) < 1e-3, "{} Hz: error {}", frequency, rms(&error));
+        }
+    }
+
+    #[test]
+    fn linear_attenuates_high_frequencies() {
+        let input = sine(15_000.0, 44_100, 11_025);
+        let (_, output) = resample(&input, 44_100, 48_000, Quality::Linear);
+        let amplitude = rms(&output) * 2f64.sqrt();
+        assert!(amplitude < 0.95 && amplitude > 0.5, "amplitude {}", amplitude);
+    }
+
+    #[test]
+    fn stopband_is_rejected_when_downsampling() {
+        // 23 kHz is above the Nyquist frequency of 44.1 kHz and must not alias to 21.1 kHz.
+        let input = sine(23_000.0, 48_000, 12_000);
+        for &(quality, max_level) in &[(Quality::MEDIUM, 1e-3), (Quality::HIGH, 1e-4)] {
+            let (_, output) = resample(&input, 48_000, 44_100, quality);
+            let level = rms(&output);
+            assert!(level < max_level, "{:?}: level {}", quality, level);
+        }
+        // Linear interpolation does not filter, so the alias is barely attenuated.
+        let (_, output) = resample(&input, 48_000, 44_100, Quality::Linear);
+        assert!(rms(&output) > 0.1);
+    }
+
+    #[test]
+    fn channels_are_independent() {
+        let mut resampler = Resampler::new(2, 48_000, 32_000, Quality::MEDIUM);
+        let left = sine(440.0, 48_000, 4_800);
+        let input: Vec<f32> = left.iter().flat_map(|&s| vec![s, 0.25]).collect();
+        let mut output: Vec<f32> = Vec::new();
+        resampler.process(&input, &mut output);
+        assert!(output.len() > 2 * 3_000);
+        let skip = 2 * 100;
+        let right: Vec<f32> = output[skip..].iter().skip(1).step_by(2).cloned().collect();
+        assert!(right.iter().all(|&s| (s - 0.25).abs() < 1e-2), "{:?}", &right[..8]);
+        let left: Vec<f32> = output[skip..].iter().step_by(2).cloned().collect();
+        assert!((rms(&left) - 0.5f64.sqrt()).abs() < 1e-2);
+    }
+
+    #[test]
+    fn variable_ratio() {
+        let mut resampler = Resampler::new(1, 48_000, 48_000, Quality::LOW);
+        let mut output = vec![0i16; 256];
+        let mut produced = 0;
+        for i in 0..200 {
+            // Drift correction slowly speeding up and slowing down the output.
+            resampler.set_ratio(1.0 + 0.001 * ((i as f64) / 10.0).sin());
+            let needed = resampler.input_frames_needed(256);
+            resampler.push(&sine(1_000.0, 48_000, needed));
+            produced += resampler.pop(&mut output);
+        }
+        assert_eq!(produced, 200 * 256);
+    }
+
+    // Halving the ratio doubles the width of the kernel, which then needs input frames from
+    // before the last `pop`.
+    #[test]
+    fn lowering_the_ratio_keeps_the_kernel() {
+        let input = sine(1_000.0, 48_000, 4_096);
+        let mut resampler = Resampler::new(1, 48_000, 48_000, Quality::LOW);
+        resampler.push(&input);
+        let mut output = vec![0.0f32; 64];
+        assert_eq!(resampler.pop(&mut output), 64);
+        resampler.set_ratio(0.5);
+        let mut changed = vec![0.0f32; 256];
+        assert_eq!(resampler.pop(&mut changed), 256);
+
+        // The output after the change continues at input frame 64, which is output frame 32 of a
+        // resampler that ran at the lowered ratio from the start.
+        let mut fresh = Resampler::new(1, 48_000, 24_000, Quality::LOW);
+        fresh.push(&input);
+        let mut expected = vec![0.0f32; 32 + 256];
+        assert_eq!(fresh.pop(&mut expected), 32 + 256);
+        assert_eq!(&changed[..], &expected[32..]);
+    }
+
+    #[test]
+    fn sinc_without_zero_crossings() {
+        let resampler = Resampler::new(1, 44_100, 48_000, Quality::Sinc(0));
+        assert_eq!(resampler.quality(), Quality::Sinc(1));
+    }
+
+    #[test]
+    fn integer_samples() {
+        let mut resampler = Resampler::new(1, 22_050, 44_100, Quality::Linear);
+        let mut output: Vec<i16> = Vec::new();
+        resampler.process(&[0i16, 1_000, 2_000, 3_000], &mut output);
+        let expected = [0, 500, 1_000, 1_500, 2_000, 2_500];
+        assert_eq!(output.len(), expected.len());
+        for (&sample, &expected) in output.iter().zip(expected.iter()) {
+            assert!((sample - expected).abs() <= 1, "{:?}", output);
+        }
+    }
+}
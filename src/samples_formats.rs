@@ -1,14 +1,34 @@
-use std::mem;
+use std::{mem, ptr, slice};
+
+use simd;
 
 /// Format that each sample has.
+///
+/// With the `serde` feature enabled this is serialized as the name of the variant, e.g. `"F32"`.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum SampleFormat {
+    /// The value 0 corresponds to 0.
+    I8,
+    /// The value 0 corresponds to 128.
+    U8,
     /// The value 0 corresponds to 0.
     I16,
     /// The value 0 corresponds to 32768.
     U16,
+    /// Signed 24-bit samples packed into 3 bytes, see `I24`. The value 0 corresponds to 0.
+    I24,
+    /// Signed 24-bit samples in the lower 3 bytes of 4, see `I24In32`. The value 0 corresponds
+    /// to 0.
+    I24In32,
+    /// The value 0 corresponds to 0.
+    I32,
+    /// The value 0 corresponds to 2147483648.
+    U32,
     /// The boundaries are (-1.0, 1.0).
     F32,
+    /// The boundaries are (-1.0, 1.0).
+    F64,
 }
 
 impl SampleFormat {
@@ -16,9 +36,16 @@ impl SampleFormat {
     #[inline]
     pub fn sample_size(&self) -> usize {
         match self {
+            &SampleFormat::I8 => mem::size_of::<i8>(),
+            &SampleFormat::U8 => mem::size_of::<u8>(),
             &SampleFormat::I16 => mem::size_of::<i16>(),
             &SampleFormat::U16 => mem::size_of::<u16>(),
+            &SampleFormat::I24 => mem::size_of::<I24>(),
+            &SampleFormat::I24In32 => mem::size_of::<I24In32>(),
+            &SampleFormat::I32 => mem::size_of::<i32>(),
+            &SampleFormat::U32 => mem::size_of::<u32>(),
             &SampleFormat::F32 => mem::size_of::<f32>(),
+            &SampleFormat::F64 => mem::size_of::<f64>(),
         }
     }
 
@@ -30,130 +57,551 @@ impl SampleFormat {
     }
 }
 
+/// A signed 24-bit sample, packed into 3 bytes in native byte order.
+///
+/// This is the layout of 24-bit samples in most audio files and of the `S24_3LE` ALSA format.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub struct I24([u8; 3]);
+
+impl I24 {
+    /// The smallest value of a 24-bit sample.
+    pub const MIN: i32 = -0x80_0000;
+    /// The largest value of a 24-bit sample.
+    pub const MAX: i32 = 0x7f_ffff;
+
+    /// Creates a sample from the lower 24 bits of `value`.
+    #[inline]
+    pub fn new(value: i32) -> Self {
+        let bytes = [value as u8, (value >> 8) as u8, (value >> 16) as u8];
+        if cfg!(target_endian = "big") {
+            I24([bytes[2], bytes[1], bytes[0]])
+        } else {
+            I24(bytes)
+        }
+    }
+
+    /// The value of the sample, between `I24::MIN` and `I24::MAX`.
+    #[inline]
+    pub fn value(self) -> i32 {
+        let [low, middle, high] = if cfg!(target_endian = "big") {
+            [self.0[2], self.0[1], self.0[0]]
+        } else {
+            self.0
+        };
+        ((high as i8 as i32) << 16) | ((middle as i32) << 8) | low as i32
+    }
+}
+
+/// A signed 24-bit sample, stored in the lower 3 bytes of a 32-bit integer in native byte order.
+///
+/// This is the layout of the `S24_LE` ALSA format. The upper byte is ignored when reading the
+/// value, as not every device sign-extends it.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct I24In32(i32);
+
+impl I24In32 {
+    /// Creates a sample from the lower 24 bits of `value`.
+    #[inline]
+    pub fn new(value: i32) -> Self {
+        I24In32(value << 8 >> 8)
+    }
+
+    /// The value of the sample, between `I24::MIN` and `I24::MAX`.
+    #[inline]
+    pub fn value(self) -> i32 {
+        self.0 << 8 >> 8
+    }
+}
+
+impl PartialEq for I24In32 {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.value() == other.value()
+    }
+}
+
+impl Eq for I24In32 {}
+
 /// Trait for containers that contain PCM data.
+///
+/// Every sample type can be converted to every other one. Conversions between integer formats
+/// shift the value, preserving its most significant bits. Conversions from integer to
+/// floating-point formats map the minimum and maximum value to -1.0 and 1.0, and the
+/// `EQUILIBRIUM` to 0.0. Conversions from floating-point to integer formats round to the nearest
+/// value and saturate.
+///
+/// # Safety
+///
+/// Implementations must have the memory layout of the `SampleFormat` returned by `get_format`, as
+/// slices of samples are reinterpreted according to their format.
 pub unsafe trait Sample: Copy + Clone {
+    /// The value of silence, e.g. 0 for signed and 32768 for `u16` samples.
+    const EQUILIBRIUM: Self;
+
     /// Returns the `SampleFormat` corresponding to this data type.
     // TODO: rename to `format()`. Requires a breaking change.
     fn get_format() -> SampleFormat;
 
     /// Turns the sample into its equivalent as a floating-point.
     fn to_f32(&self) -> f32;
+    /// Turns the sample into its equivalent as a double precision floating-point.
+    fn to_f64(&self) -> f64;
+    /// Converts this sample into a standard i8 sample.
+    fn to_i8(&self) -> i8;
+    /// Converts this sample into a standard u8 sample.
+    fn to_u8(&self) -> u8;
     /// Converts this sample into a standard i16 sample.
     fn to_i16(&self) -> i16;
     /// Converts this sample into a standard u16 sample.
     fn to_u16(&self) -> u16;
+    /// Converts this sample into a standard i32 sample.
+    fn to_i32(&self) -> i32;
+    /// Converts this sample into a standard u32 sample.
+    fn to_u32(&self) -> u32;
 
-    /// Converts any sample type to this one by calling `to_i16`, `to_u16` or `to_f32`.
+    /// Converts any sample type to this one.
     fn from<S>(&S) -> Self
         where S: Sample;
-}
 
-unsafe impl Sample for u16 {
+    /// Converts this sample to any other sample type.
     #[inline]
-    fn get_format() -> SampleFormat {
-        SampleFormat::U16
+    fn to_sample<U>(&self) -> U
+        where U: Sample
+    {
+        U::from(self)
     }
 
-    #[inline]
-    fn to_f32(&self) -> f32 {
-        self.to_i16().to_f32()
-    }
+    /// Adds two samples, mixing their signals.
+    ///
+    /// Integer samples saturate at the limits of their type. Floating-point samples are not
+    /// clamped.
+    fn saturating_add(self, other: Self) -> Self;
 
-    #[inline]
-    fn to_i16(&self) -> i16 {
-        if *self >= 32768 {
-            (*self - 32768) as i16
-        } else {
-            (*self as i16) - 32767 - 1
-        }
-    }
+    /// Multiplies the amplitude of the sample by `amp`, e.g. to apply a gain.
+    ///
+    /// Integer samples are rounded and saturate at the limits of their type. Floating-point samples
+    /// are not clamped.
+    fn mul_amp(self, amp: f32) -> Self;
+}
 
-    #[inline]
-    fn to_u16(&self) -> u16 {
-        *self
+// Converts a signed integer sample of `from` bits to one of `to` bits.
+#[inline]
+fn shift_bits(value: i64, from: u32, to: u32) -> i64 {
+    if to >= from {
+        value << (to - from)
+    } else {
+        value >> (from - to)
     }
+}
 
-    #[inline]
-    fn from<S>(sample: &S) -> Self
-        where S: Sample
-    {
-        sample.to_u16()
-    }
+// Limits a signed integer sample to the range of `bits` bits.
+#[inline]
+fn saturate(value: i64, bits: u32) -> i64 {
+    let max = (1i64 << (bits - 1)) - 1;
+    value.max(-max - 1).min(max)
 }
 
-unsafe impl Sample for i16 {
-    #[inline]
-    fn get_format() -> SampleFormat {
-        SampleFormat::I16
+// Converts a float sample to a signed integer sample of `bits` bits, multiplying negative values
+// by the magnitude of the minimum and positive values by the maximum.
+#[inline]
+fn float_to_signed(value: f64, bits: u32) -> i64 {
+    let max = (1i64 << (bits - 1)) - 1;
+    let scaled = if value >= 0.0 {
+        value * max as f64
+    } else {
+        value * (max + 1) as f64
+    };
+    saturate(scaled.round() as i64, bits)
+}
+
+// Converts any sample to a signed integer sample of `bits` bits.
+#[inline]
+fn to_signed<S>(sample: &S, bits: u32) -> i64
+    where S: Sample
+{
+    match S::get_format() {
+        SampleFormat::F32 | SampleFormat::F64 => float_to_signed(sample.to_f64(), bits),
+        _ => shift_bits(sample.to_i32() as i64, 32, bits),
     }
+}
 
-    #[inline]
-    fn to_f32(&self) -> f32 {
-        if *self < 0 {
-            *self as f32 / -(::std::i16::MIN as f32)
+// Converts a signed integer sample of `bits` bits to a float, dividing negative values by the
+// magnitude of the minimum and positive values by the maximum.
+macro_rules! signed_to_float {
+    ($value:expr, $bits:expr, $F:ty) => {{
+        let value: i64 = $value;
+        if value < 0 {
+            value as $F / (1i64 << ($bits - 1)) as $F
         } else {
-            *self as f32 / ::std::i16::MAX as f32
+            value as $F / ((1i64 << ($bits - 1)) - 1) as $F
         }
-    }
+    }};
+}
 
-    #[inline]
-    fn to_i16(&self) -> i16 {
-        *self
-    }
+// Implements `Sample` for an integer type of `$bits` bits, given the expression converting
+// `$s: Self` to the equivalent signed value and the expression converting the signed value
+// `$v: i64` back to `Self`.
+macro_rules! impl_integer_sample {
+    ($T:ident, $Format:ident, $bits:expr, $equilibrium:expr,
+     |$s:ident| $signed:expr, |$v:ident| $from_signed:expr) => {
+        unsafe impl Sample for $T {
+            const EQUILIBRIUM: Self = $equilibrium;
 
-    #[inline]
-    fn to_u16(&self) -> u16 {
-        if *self < 0 {
-            (*self - ::std::i16::MIN) as u16
-        } else {
-            (*self as u16) + 32768
+            #[inline]
+            fn get_format() -> SampleFormat {
+                SampleFormat::$Format
+            }
+
+            #[inline]
+            fn to_f32(&self) -> f32 {
+                let $s = *self;
+                signed_to_float!($signed, $bits, f32)
+            }
+
+            #[inline]
+            fn to_f64(&self) -> f64 {
+                let $s = *self;
+                signed_to_float!($signed, $bits, f64)
+            }
+
+            #[inline]
+            fn to_i8(&self) -> i8 {
+                let $s = *self;
+                shift_bits($signed, $bits, 8) as i8
+            }
+
+            #[inline]
+            fn to_u8(&self) -> u8 {
+                (self.to_i8() as u8) ^ 0x80
+            }
+
+            #[inline]
+            fn to_i16(&self) -> i16 {
+                let $s = *self;
+                shift_bits($signed, $bits, 16) as i16
+            }
+
+            #[inline]
+            fn to_u16(&self) -> u16 {
+                (self.to_i16() as u16) ^ 0x8000
+            }
+
+            #[inline]
+            fn to_i32(&self) -> i32 {
+                let $s = *self;
+                shift_bits($signed, $bits, 32) as i32
+            }
+
+            #[inline]
+            fn to_u32(&self) -> u32 {
+                (self.to_i32() as u32) ^ 0x8000_0000
+            }
+
+            #[inline]
+            fn from<S>(sample: &S) -> Self
+                where S: Sample
+            {
+                let $v = to_signed(sample, $bits);
+                $from_signed
+            }
+
+            #[inline]
+            fn saturating_add(self, other: Self) -> Self {
+                let a = {
+                    let $s = self;
+                    $signed
+                };
+                let b = {
+                    let $s = other;
+                    $signed
+                };
+                let $v = saturate(a + b, $bits);
+                $from_signed
+            }
+
+            #[inline]
+            fn mul_amp(self, amp: f32) -> Self {
+                let $s = self;
+                let $v = saturate(($signed as f64 * amp as f64).round() as i64, $bits);
+                $from_signed
+            }
         }
+    };
+}
+
+impl_integer_sample!(i8, I8, 8, 0, |s| s as i64, |v| v as i8);
+impl_integer_sample!(u8, U8, 8, 0x80, |s| s as i64 - 0x80, |v| (v + 0x80) as u8);
+impl_integer_sample!(i16, I16, 16, 0, |s| s as i64, |v| v as i16);
+impl_integer_sample!(u16, U16, 16, 0x8000, |s| s as i64 - 0x8000, |v| (v + 0x8000) as u16);
+impl_integer_sample!(I24, I24, 24, I24([0; 3]), |s| s.value() as i64, |v| I24::new(v as i32));
+impl_integer_sample!(I24In32, I24In32, 24, I24In32(0), |s| s.value() as i64,
+                     |v| I24In32::new(v as i32));
+impl_integer_sample!(i32, I32, 32, 0, |s| s as i64, |v| v as i32);
+impl_integer_sample!(u32, U32, 32, 0x8000_0000, |s| s as i64 - 0x8000_0000,
+                     |v| (v + 0x8000_0000) as u32);
+
+macro_rules! impl_float_sample {
+    ($T:ident, $Format:ident, $to:ident) => {
+        unsafe impl Sample for $T {
+            const EQUILIBRIUM: Self = 0.0;
+
+            #[inline]
+            fn get_format() -> SampleFormat {
+                SampleFormat::$Format
+            }
+
+            #[inline]
+            fn to_f32(&self) -> f32 {
+                *self as f32
+            }
+
+            #[inline]
+            fn to_f64(&self) -> f64 {
+                *self as f64
+            }
+
+            #[inline]
+            fn to_i8(&self) -> i8 {
+                float_to_signed(*self as f64, 8) as i8
+            }
+
+            #[inline]
+            fn to_u8(&self) -> u8 {
+                (self.to_i8() as u8) ^ 0x80
+            }
+
+            #[inline]
+            fn to_i16(&self) -> i16 {
+                float_to_signed(*self as f64, 16) as i16
+            }
+
+            #[inline]
+            fn to_u16(&self) -> u16 {
+                (self.to_i16() as u16) ^ 0x8000
+            }
+
+            #[inline]
+            fn to_i32(&self) -> i32 {
+                float_to_signed(*self as f64, 32) as i32
+            }
+
+            #[inline]
+            fn to_u32(&self) -> u32 {
+                (self.to_i32() as u32) ^ 0x8000_0000
+            }
+
+            #[inline]
+            fn from<S>(sample: &S) -> Self
+                where S: Sample
+            {
+                sample.$to()
+            }
+
+            #[inline]
+            fn saturating_add(self, other: Self) -> Self {
+                self + other
+            }
+
+            #[inline]
+            fn mul_amp(self, amp: f32) -> Self {
+                self * amp as $T
+            }
+        }
+    };
+}
+
+impl_float_sample!(f32, F32, to_f32);
+impl_float_sample!(f64, F64, to_f64);
+
+/// Converts the samples of `input` into `output`, as many as both slices hold.
+///
+/// The result is the same as converting each sample with `Sample::from`, but conversions between
+/// `f32` and `i16` or `i32` samples use SIMD instructions where available: AVX2 or SSE2 on x86,
+/// detected at runtime, and NEON on AArch64.
+///
+/// ```
+/// let input = [0.5f32, -0.5, 1.0, -1.0];
+/// let mut output = [0i16; 4];
+/// cpal::convert_slice(&input, &mut output);
+/// assert_eq!(output, [16384, -16384, 32767, -32768]);
+/// ```
+pub fn convert_slice<S, D>(input: &[S], output: &mut [D])
+    where S: Sample,
+          D: Sample
+{
+    let len = input.len().min(output.len());
+    unsafe { convert_raw(input.as_ptr(), output.as_mut_ptr(), len) }
+}
+
+/// Converts the samples of `buffer` in place to a sample type of the same size, such as `f32` to
+/// `i32`, and returns the buffer as the new type.
+///
+/// The result is the same as with `convert_slice`.
+///
+/// # Panics
+///
+/// Panics if the sample types differ in size, or if `D` has a larger alignment than `S`.
+pub fn convert_in_place<S, D>(buffer: &mut [S]) -> &mut [D]
+    where S: Sample,
+          D: Sample
+{
+    assert!(
+        mem::size_of::<S>() == mem::size_of::<D>() && mem::align_of::<S>() >= mem::align_of::<D>(),
+        "in-place conversion requires sample types of the same size and compatible alignment"
+    );
+    let len = buffer.len();
+    let input = buffer.as_mut_ptr();
+    let output = input as *mut D;
+    unsafe {
+        convert_raw(input, output, len);
+        slice::from_raw_parts_mut(output, len)
     }
+}
 
-    #[inline]
-    fn from<S>(sample: &S) -> Self
-        where S: Sample
-    {
-        sample.to_i16()
+// Converts `len` samples, which may be in place if both types have the same size.
+unsafe fn convert_raw<S, D>(input: *const S, output: *mut D, len: usize)
+    where S: Sample,
+          D: Sample
+{
+    let converted = simd::convert(input, output, len);
+    for i in converted..len {
+        let sample = ptr::read(input.add(i));
+        ptr::write(output.add(i), D::from(&sample));
     }
 }
 
-unsafe impl Sample for f32 {
-    #[inline]
-    fn get_format() -> SampleFormat {
-        SampleFormat::F32
+#[cfg(test)]
+mod test {
+    use std::fmt::Debug;
+    use std::mem;
+
+    use super::{I24, I24In32, Sample, convert_in_place, convert_slice};
+
+    // A sample type with values spread over its whole range, used for property tests.
+    trait TestSample: Sample + Debug + PartialEq {
+        // The number of bits of precision.
+        const PRECISION: u32;
+        const FLOAT: bool;
+
+        // Values in ascending order, from the minimum to the maximum of the type.
+        fn test_values() -> Vec<Self>;
+
+        // The largest difference between two adjacent values, as a float.
+        fn step() -> f64 {
+            1.0 / (1u64 << (Self::PRECISION - 1)) as f64
+        }
     }
 
-    #[inline]
-    fn to_f32(&self) -> f32 {
-        *self
+    // Every value of `bits` bits for narrow types and a spread of values otherwise, including the
+    // values around zero.
+    fn signed_values(bits: u32) -> Vec<i64> {
+        let min = -(1i64 << (bits - 1));
+        let max = (1i64 << (bits - 1)) - 1;
+        let step = ((max - min) >> 16) | 1;
+        let mut values: Vec<i64> = (0..).map(|i| min + i * step).take_while(|&v| v < max).collect();
+        values.extend(&[-1, 0, 1, max - 1, max]);
+        values.sort();
+        values.dedup();
+        values
     }
 
-    #[inline]
-    fn to_i16(&self) -> i16 {
-        if *self >= 0.0 {
-            (*self * ::std::i16::MAX as f32) as i16
-        } else {
-            (-*self * ::std::i16::MIN as f32) as i16
-        }
+    macro_rules! impl_test_sample {
+        ($T:ident, $bits:expr, |$v:ident| $from_signed:expr) => {
+            impl TestSample for $T {
+                const PRECISION: u32 = $bits;
+                const FLOAT: bool = false;
+
+                fn test_values() -> Vec<Self> {
+                    signed_values($bits).into_iter().map(|$v| $from_signed).collect()
+                }
+            }
+        };
+        ($T:ident, $bits:expr) => {
+            impl TestSample for $T {
+                const PRECISION: u32 = $bits;
+                const FLOAT: bool = true;
+
+                fn test_values() -> Vec<Self> {
+                    (-4096..4097).map(|i| i as $T / 4096.0).collect()
+                }
+            }
+        };
     }
 
-    #[inline]
-    fn to_u16(&self) -> u16 {
-        (((*self + 1.0) * 0.5) * ::std::u16::MAX as f32).round() as u16
+    impl_test_sample!(i8, 8, |v| v as i8);
+    impl_test_sample!(u8, 8, |v| (v + 0x80) as u8);
+    impl_test_sample!(i16, 16, |v| v as i16);
+    impl_test_sample!(u16, 16, |v| (v + 0x8000) as u16);
+    impl_test_sample!(I24, 24, |v| I24::new(v as i32));
+    impl_test_sample!(I24In32, 24, |v| I24In32::new(v as i32));
+    impl_test_sample!(i32, 32, |v| v as i32);
+    impl_test_sample!(u32, 32, |v| (v + 0x8000_0000) as u32);
+    impl_test_sample!(f32, 24);
+    impl_test_sample!(f64, 53);
+
+    // Calls the generic function `$f` once for every sample type, after the given type arguments.
+    macro_rules! for_each_type {
+        ($f:ident $(, $A:ident)*) => {{
+            $f::<$($A,)* i8>();
+            $f::<$($A,)* u8>();
+            $f::<$($A,)* i16>();
+            $f::<$($A,)* u16>();
+            $f::<$($A,)* I24>();
+            $f::<$($A,)* I24In32>();
+            $f::<$($A,)* i32>();
+            $f::<$($A,)* u32>();
+            $f::<$($A,)* f32>();
+            $f::<$($A,)* f64>();
+        }};
     }
 
-    #[inline]
-    fn from<S>(sample: &S) -> Self
-        where S: Sample
+    fn check_conversion<A, B>()
+        where A: TestSample,
+              B: TestSample
     {
-        sample.to_f32()
+        let name = format!("{:?} to {:?}", A::get_format(), B::get_format());
+        let values = A::test_values();
+        let converted: Vec<B> = values.iter().map(|a| a.to_sample()).collect();
+
+        // Silence and the minimum are preserved.
+        assert_eq!(A::EQUILIBRIUM.to_sample::<B>(), B::EQUILIBRIUM, "{}", name);
+        assert_eq!(converted[0], B::test_values()[0], "{}", name);
+
+        // The value changes by at most one step of the less precise type, and order is kept.
+        let tolerance = A::step().max(B::step()) * 1.001;
+        for (a, b) in values.iter().zip(&converted) {
+            let error = (a.to_f64() - b.to_f64()).abs();
+            assert!(error <= tolerance, "{}: {:?} became {:?}", name, a, b);
+        }
+        for pair in converted.windows(2) {
+            assert!(pair[0].to_f64() <= pair[1].to_f64(), "{}: {:?}", name, pair);
+        }
+
+        // Converting to a type at least as precise and back is lossless, except from floats to
+        // integers.
+        if B::PRECISION >= A::PRECISION && (B::FLOAT || !A::FLOAT) {
+            for (a, b) in values.iter().zip(&converted) {
+                assert_eq!(b.to_sample::<A>(), *a, "{} and back", name);
+            }
+        }
+
+        // Floats of opposite signs convert to values symmetric around the equilibrium.
+        if A::FLOAT {
+            for a in &values {
+                let positive = a.to_sample::<B>().to_f64();
+                let negative = (-a.to_f64()).to_sample::<B>().to_f64();
+                assert!((positive + negative).abs() <= tolerance, "{}: {:?}", name, a);
+            }
+        }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::Sample;
+    fn check_conversions_from<A>()
+        where A: TestSample
+    {
+        for_each_type!(check_conversion, A);
+    }
 
     #[test]
     fn i16_to_i16() {
@@ -223,4 +671,153 @@ mod test {
         assert_eq!((-0.7f32).to_f32(), -0.7);
         assert_eq!(1.0f32.to_f32(), 1.0);
     }
+
+    #[test]
+    fn conversions_between_all_formats() {
+        for_each_type!(check_conversions_from);
+    }
+
+    fn check_arithmetic<A>()
+        where A: TestSample
+    {
+        let values = A::test_values();
+        let (min, max) = (values[0], values[values.len() - 1]);
+        for &a in &values {
+            assert_eq!(a.saturating_add(A::EQUILIBRIUM), a);
+            assert_eq!(A::EQUILIBRIUM.saturating_add(a), a);
+            assert_eq!(a.mul_amp(1.0), a);
+            assert_eq!(a.mul_amp(0.0), A::EQUILIBRIUM);
+            let half = a.mul_amp(0.5).to_f64();
+            assert!((half - a.to_f64() * 0.5).abs() <= A::step(), "{:?} * 0.5", a);
+        }
+        if !A::FLOAT {
+            for &a in &values {
+                let sum = a.saturating_add(a).to_f64();
+                let expected = (a.to_f64() * 2.0).max(-1.0).min(1.0);
+                assert!((sum - expected).abs() <= A::step(), "{:?} + {:?}", a, a);
+            }
+            assert_eq!(max.saturating_add(max), max);
+            assert_eq!(min.saturating_add(min), min);
+            assert_eq!(max.mul_amp(2.0), max);
+            assert_eq!(min.mul_amp(2.0), min);
+            assert_eq!(min.mul_amp(-1.0), max);
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        for_each_type!(check_arithmetic);
+    }
+
+    fn check_slice_conversion<A, B>()
+        where A: TestSample,
+              B: TestSample
+    {
+        let values = A::test_values();
+        let expected: Vec<B> = values.iter().map(|a| a.to_sample()).collect();
+        // Odd lengths leave a remainder after the vectorized part.
+        for &len in &[0, 1, 7, 33, values.len()] {
+            let mut output = vec![B::EQUILIBRIUM; len + 1];
+            convert_slice(&values[..len], &mut output);
+            assert_eq!(&output[..len], &expected[..len]);
+            assert_eq!(output[len], B::EQUILIBRIUM);
+        }
+    }
+
+    fn check_slice_conversions_from<A>()
+        where A: TestSample
+    {
+        for_each_type!(check_slice_conversion, A);
+    }
+
+    #[test]
+    fn slice_conversions_match_samples() {
+        for_each_type!(check_slice_conversions_from);
+    }
+
+    #[test]
+    fn in_place_conversion() {
+        let values = f32::test_values();
+        let expected: Vec<i32> = values.iter().map(|a| a.to_sample()).collect();
+        let mut buffer = values.clone();
+        assert_eq!(convert_in_place::<f32, i32>(&mut buffer), &expected[..]);
+        let back: Vec<f32> = expected.iter().map(|a| a.to_sample()).collect();
+        let mut buffer = expected.clone();
+        assert_eq!(convert_in_place::<i32, f32>(&mut buffer), &back[..]);
+
+        let mut buffer = vec![0i16, -1, 0x7fff];
+        assert_eq!(convert_in_place::<i16, u16>(&mut buffer), &[0x8000, 0x7fff, 0xffff]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn in_place_conversion_requires_equal_sizes() {
+        convert_in_place::<i16, f32>(&mut [0i16; 4]);
+    }
+
+    #[test]
+    fn integer_widths() {
+        assert_eq!((-128i8).to_i16(), -32768);
+        assert_eq!(127i8.to_i32(), 127 << 24);
+        assert_eq!(0x80u8.to_i16(), 0);
+        assert_eq!(0xffu8.to_u32(), 0xff00_0000);
+        assert_eq!(0x1234_5678i32.to_i16(), 0x1234);
+        assert_eq!((-1i32).to_i8(), -1);
+        assert_eq!(0x8000_0000u32.to_u8(), 0x80);
+        assert_eq!(0u32.to_i8(), -128);
+        assert_eq!(16384i16.to_u8(), 0xc0);
+        assert_eq!(0x1234i16.to_sample::<I24>(), I24::new(0x12_3400));
+        assert_eq!(I24::new(0x12_3456).to_i16(), 0x1234);
+    }
+
+    #[test]
+    fn float_to_integer() {
+        assert_eq!(1.0f64.to_i32(), ::std::i32::MAX);
+        assert_eq!((-1.0f64).to_i32(), ::std::i32::MIN);
+        assert_eq!(0.5f64.to_i8(), 64);
+        assert_eq!((-0.5f32).to_i16(), ::std::i16::MIN / 2);
+        assert_eq!(0.0f64.to_u8(), 128);
+        assert_eq!(1.0f32.to_u32(), ::std::u32::MAX);
+        assert_eq!((-1.0f32).to_u32(), 0);
+        // Values outside of the range saturate.
+        assert_eq!(2.0f32.to_i8(), 127);
+        assert_eq!((-2.0f64).to_u16(), 0);
+        assert_eq!(2.0f32.to_sample::<I24>().value(), I24::MAX);
+        assert_eq!(::std::f32::NAN.to_i16(), 0);
+    }
+
+    #[test]
+    fn i24_layout() {
+        assert_eq!(mem::size_of::<I24>(), 3);
+        assert_eq!(mem::size_of::<I24In32>(), 4);
+        let bytes: [u8; 3] = unsafe { mem::transmute(I24::new(0x12_3456)) };
+        if cfg!(target_endian = "big") {
+            assert_eq!(bytes, [0x12, 0x34, 0x56]);
+        } else {
+            assert_eq!(bytes, [0x56, 0x34, 0x12]);
+        }
+    }
+
+    #[test]
+    fn i24_sign_extension() {
+        for &value in &[I24::MIN, -0x12_3456, -1, 0, 1, 0x12_3456, I24::MAX] {
+            assert_eq!(I24::new(value).value(), value);
+            assert_eq!(I24In32::new(value).value(), value);
+        }
+        assert_eq!(I24::new(0xff_ffff).value(), -1);
+        assert_eq!(I24::new(0x80_0000).value(), I24::MIN);
+        // The upper byte of a padded sample is ignored.
+        let padded: I24In32 = unsafe { mem::transmute(0x7f80_0000i32) };
+        assert_eq!(padded.value(), I24::MIN);
+        assert_eq!(padded, I24In32::new(I24::MIN));
+    }
+
+    #[test]
+    fn equilibrium_is_silence() {
+        assert_eq!(u8::EQUILIBRIUM, 128);
+        assert_eq!(u16::EQUILIBRIUM, 32768);
+        assert_eq!(u32::EQUILIBRIUM, 0x8000_0000);
+        assert_eq!(I24::EQUILIBRIUM.value(), 0);
+        assert_eq!(u16::EQUILIBRIUM.to_f32(), 0.0);
+    }
 }
@@ -0,0 +1,456 @@
+//! Vectorized kernels behind `convert_slice` and `convert_in_place`.
+//!
+//! Each kernel produces exactly the same bits as the `Sample` implementations. Floats are
+//! converted to integers in double precision like `Sample::to_i16` and `Sample::to_i32`, rounding
+//! half away from zero and saturating, with NaN becoming 0. Integers are converted to floats by a
+//! division, whose result does not depend on the instruction set.
+//!
+//! x86 kernels are selected at runtime, preferring AVX2 over SSE2. NEON is always available on
+//! AArch64.
+
+use {Sample, SampleFormat};
+
+/// Converts the first samples of `input` into `output` with a vectorized kernel, if there is one
+/// for the pair of formats and the CPU supports it.
+///
+/// Returns the number of samples converted, leaving the rest for the caller.
+///
+/// Safety: `input` and `output` must be valid for `len` samples. They may point to the same
+/// buffer if both sample types have the same size, as every vector is loaded before it is stored.
+pub unsafe fn convert<S, D>(input: *const S, output: *mut D, len: usize) -> usize
+where
+    S: Sample,
+    D: Sample,
+{
+    // The layout of a sample type is the one of its format, as required by `Sample`.
+    match (S::get_format(), D::get_format()) {
+        (SampleFormat::F32, SampleFormat::I16) => f32_to_i16(input as _, output as _, len),
+        (SampleFormat::F32, SampleFormat::I32) => f32_to_i32(input as _, output as _, len),
+        (SampleFormat::I16, SampleFormat::F32) => i16_to_f32(input as _, output as _, len),
+        (SampleFormat::I32, SampleFormat::F32) => i32_to_f32(input as _, output as _, len),
+        _ => 0,
+    }
+}
+
+// Defines the kernel `$name`, dispatching to the best implementation for the current CPU.
+macro_rules! kernel {
+    ($name:ident, $In:ty, $Out:ty) => {
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        unsafe fn $name(input: *const $In, output: *mut $Out, len: usize) -> usize {
+            if is_x86_feature_detected!("avx2") {
+                x86::avx2::$name(input, output, len)
+            } else if is_x86_feature_detected!("sse2") {
+                x86::sse2::$name(input, output, len)
+            } else {
+                0
+            }
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        unsafe fn $name(input: *const $In, output: *mut $Out, len: usize) -> usize {
+            neon::$name(input, output, len)
+        }
+
+        #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+        unsafe fn $name(_input: *const $In, _output: *mut $Out, _len: usize) -> usize {
+            0
+        }
+    };
+}
+
+kernel!(f32_to_i16, f32, i16);
+kernel!(f32_to_i32, f32, i32);
+kernel!(i16_to_f32, i16, f32);
+kernel!(i32_to_f32, i32, f32);
+
+// The largest values of the integer types, as used by the scalar conversions.
+const I16_MAX: f64 = 32_767.0;
+const I32_MAX: f64 = 2_147_483_647.0;
+
+// The divisors of negative and positive integers converted to `f32`, computed like
+// `signed_to_float!`.
+const I16_MIN_DIVISOR: f32 = (1i64 << 15) as f32;
+const I16_MAX_DIVISOR: f32 = ((1i64 << 15) - 1) as f32;
+const I32_MIN_DIVISOR: f32 = (1i64 << 31) as f32;
+const I32_MAX_DIVISOR: f32 = ((1i64 << 31) - 1) as f32;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    // The largest double below 0.5. Adding it with the sign of a value and truncating rounds half
+    // away from zero.
+    const HALF_BELOW: f64 = 0.5 - 1.0 / (1u64 << 54) as f64;
+
+    pub mod sse2 {
+        #[cfg(target_arch = "x86")]
+        use std::arch::x86::*;
+        #[cfg(target_arch = "x86_64")]
+        use std::arch::x86_64::*;
+
+        use super::HALF_BELOW;
+        use super::super::{
+            I16_MAX,
+            I16_MAX_DIVISOR,
+            I16_MIN_DIVISOR,
+            I32_MAX,
+            I32_MAX_DIVISOR,
+            I32_MIN_DIVISOR,
+        };
+
+        // Scales, rounds and saturates two samples to integers of the range `-max - 1..=max`,
+        // returned in the lower two lanes.
+        #[inline]
+        #[target_feature(enable = "sse2")]
+        unsafe fn round_f64x2(x: __m128d, max: f64) -> __m128i {
+            // NaN converts to 0.
+            let x = _mm_and_pd(x, _mm_cmpord_pd(x, x));
+            let positive = _mm_cmpge_pd(x, _mm_setzero_pd());
+            let scale = _mm_or_pd(
+                _mm_and_pd(positive, _mm_set1_pd(max)),
+                _mm_andnot_pd(positive, _mm_set1_pd(max + 1.0)),
+            );
+            let scaled = _mm_mul_pd(x, scale);
+            let half = _mm_or_pd(
+                _mm_and_pd(scaled, _mm_set1_pd(-0.0)),
+                _mm_set1_pd(HALF_BELOW),
+            );
+            let rounded = _mm_add_pd(scaled, half);
+            let clamped = _mm_min_pd(_mm_max_pd(rounded, _mm_set1_pd(-max - 1.0)), _mm_set1_pd(max));
+            _mm_cvttpd_epi32(clamped)
+        }
+
+        #[inline]
+        #[target_feature(enable = "sse2")]
+        unsafe fn round_f32x4(x: __m128, max: f64) -> __m128i {
+            let low = round_f64x2(_mm_cvtps_pd(x), max);
+            let high = round_f64x2(_mm_cvtps_pd(_mm_movehl_ps(x, x)), max);
+            _mm_unpacklo_epi64(low, high)
+        }
+
+        #[inline]
+        #[target_feature(enable = "sse2")]
+        unsafe fn to_f32x4(x: __m128i, min_divisor: f32, max_divisor: f32) -> __m128 {
+            let negative = _mm_castsi128_ps(_mm_cmplt_epi32(x, _mm_setzero_si128()));
+            let divisor = _mm_or_ps(
+                _mm_and_ps(negative, _mm_set1_ps(min_divisor)),
+                _mm_andnot_ps(negative, _mm_set1_ps(max_divisor)),
+            );
+            _mm_div_ps(_mm_cvtepi32_ps(x), divisor)
+        }
+
+        #[target_feature(enable = "sse2")]
+        pub unsafe fn f32_to_i16(input: *const f32, output: *mut i16, len: usize) -> usize {
+            let chunks = len / 8;
+            for i in 0..chunks {
+                let low = _mm_loadu_ps(input.add(i * 8));
+                let high = _mm_loadu_ps(input.add(i * 8 + 4));
+                let packed = _mm_packs_epi32(round_f32x4(low, I16_MAX), round_f32x4(high, I16_MAX));
+                _mm_storeu_si128(output.add(i * 8) as *mut __m128i, packed);
+            }
+            chunks * 8
+        }
+
+        #[target_feature(enable = "sse2")]
+        pub unsafe fn f32_to_i32(input: *const f32, output: *mut i32, len: usize) -> usize {
+            let chunks = len / 4;
+            for i in 0..chunks {
+                let x = _mm_loadu_ps(input.add(i * 4));
+                _mm_storeu_si128(output.add(i * 4) as *mut __m128i, round_f32x4(x, I32_MAX));
+            }
+            chunks * 4
+        }
+
+        #[target_feature(enable = "sse2")]
+        pub unsafe fn i16_to_f32(input: *const i16, output: *mut f32, len: usize) -> usize {
+            let chunks = len / 8;
+            for i in 0..chunks {
+                let x = _mm_loadu_si128(input.add(i * 8) as *const __m128i);
+                let low = _mm_srai_epi32(_mm_unpacklo_epi16(x, x), 16);
+                let high = _mm_srai_epi32(_mm_unpackhi_epi16(x, x), 16);
+                let low = to_f32x4(low, I16_MIN_DIVISOR, I16_MAX_DIVISOR);
+                let high = to_f32x4(high, I16_MIN_DIVISOR, I16_MAX_DIVISOR);
+                _mm_storeu_ps(output.add(i * 8), low);
+                _mm_storeu_ps(output.add(i * 8 + 4), high);
+            }
+            chunks * 8
+        }
+
+        #[target_feature(enable = "sse2")]
+        pub unsafe fn i32_to_f32(input: *const i32, output: *mut f32, len: usize) -> usize {
+            let chunks = len / 4;
+            for i in 0..chunks {
+                let x = _mm_loadu_si128(input.add(i * 4) as *const __m128i);
+                let converted = to_f32x4(x, I32_MIN_DIVISOR, I32_MAX_DIVISOR);
+                _mm_storeu_ps(output.add(i * 4), converted);
+            }
+            chunks * 4
+        }
+    }
+
+    pub mod avx2 {
+        #[cfg(target_arch = "x86")]
+        use std::arch::x86::*;
+        #[cfg(target_arch = "x86_64")]
+        use std::arch::x86_64::*;
+
+        use super::HALF_BELOW;
+        use super::super::{
+            I16_MAX,
+            I16_MAX_DIVISOR,
+            I16_MIN_DIVISOR,
+            I32_MAX,
+            I32_MAX_DIVISOR,
+            I32_MIN_DIVISOR,
+        };
+
+        // Scales, rounds and saturates four samples to integers of the range `-max - 1..=max`.
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        unsafe fn round_f32x4(x: __m128, max: f64) -> __m128i {
+            let x = _mm256_cvtps_pd(x);
+            // NaN converts to 0.
+            let x = _mm256_and_pd(x, _mm256_cmp_pd(x, x, _CMP_ORD_Q));
+            let positive = _mm256_cmp_pd(x, _mm256_setzero_pd(), _CMP_GE_OQ);
+            let scale = _mm256_blendv_pd(_mm256_set1_pd(max + 1.0), _mm256_set1_pd(max), positive);
+            let scaled = _mm256_mul_pd(x, scale);
+            let half = _mm256_or_pd(
+                _mm256_and_pd(scaled, _mm256_set1_pd(-0.0)),
+                _mm256_set1_pd(HALF_BELOW),
+            );
+            let rounded = _mm256_add_pd(scaled, half);
+            let clamped = _mm256_min_pd(
+                _mm256_max_pd(rounded, _mm256_set1_pd(-max - 1.0)),
+                _mm256_set1_pd(max),
+            );
+            _mm256_cvttpd_epi32(clamped)
+        }
+
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        unsafe fn to_f32x8(x: __m256i, min_divisor: f32, max_divisor: f32) -> __m256 {
+            let negative = _mm256_castsi256_ps(_mm256_cmpgt_epi32(_mm256_setzero_si256(), x));
+            let divisor = _mm256_blendv_ps(
+                _mm256_set1_ps(max_divisor),
+                _mm256_set1_ps(min_divisor),
+                negative,
+            );
+            _mm256_div_ps(_mm256_cvtepi32_ps(x), divisor)
+        }
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn f32_to_i16(input: *const f32, output: *mut i16, len: usize) -> usize {
+            let chunks = len / 8;
+            for i in 0..chunks {
+                let low = _mm_loadu_ps(input.add(i * 8));
+                let high = _mm_loadu_ps(input.add(i * 8 + 4));
+                let packed = _mm_packs_epi32(round_f32x4(low, I16_MAX), round_f32x4(high, I16_MAX));
+                _mm_storeu_si128(output.add(i * 8) as *mut __m128i, packed);
+            }
+            chunks * 8
+        }
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn f32_to_i32(input: *const f32, output: *mut i32, len: usize) -> usize {
+            let chunks = len / 8;
+            for i in 0..chunks {
+                let low = _mm_loadu_ps(input.add(i * 8));
+                let high = _mm_loadu_ps(input.add(i * 8 + 4));
+                let low = round_f32x4(low, I32_MAX);
+                let high = round_f32x4(high, I32_MAX);
+                _mm_storeu_si128(output.add(i * 8) as *mut __m128i, low);
+                _mm_storeu_si128(output.add(i * 8 + 4) as *mut __m128i, high);
+            }
+            chunks * 8
+        }
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn i16_to_f32(input: *const i16, output: *mut f32, len: usize) -> usize {
+            let chunks = len / 8;
+            for i in 0..chunks {
+                let x = _mm_loadu_si128(input.add(i * 8) as *const __m128i);
+                let x = _mm256_cvtepi16_epi32(x);
+                let converted = to_f32x8(x, I16_MIN_DIVISOR, I16_MAX_DIVISOR);
+                _mm256_storeu_ps(output.add(i * 8), converted);
+            }
+            chunks * 8
+        }
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn i32_to_f32(input: *const i32, output: *mut f32, len: usize) -> usize {
+            let chunks = len / 8;
+            for i in 0..chunks {
+                let x = _mm256_loadu_si256(input.add(i * 8) as *const __m256i);
+                let converted = to_f32x8(x, I32_MIN_DIVISOR, I32_MAX_DIVISOR);
+                _mm256_storeu_ps(output.add(i * 8), converted);
+            }
+            chunks * 8
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+
+    use super::{
+        I16_MAX,
+        I16_MAX_DIVISOR,
+        I16_MIN_DIVISOR,
+        I32_MAX,
+        I32_MAX_DIVISOR,
+        I32_MIN_DIVISOR,
+    };
+
+    // Scales, rounds and saturates two samples to integers of the range `-max - 1..=max`.
+    #[inline]
+    unsafe fn round_f64x2(x: float64x2_t, max: f64) -> int64x2_t {
+        let positive = vcgezq_f64(x);
+        let scale = vbslq_f64(positive, vdupq_n_f64(max), vdupq_n_f64(max + 1.0));
+        let rounded = vrndaq_f64(vmulq_f64(x, scale));
+        let clamped = vminq_f64(vmaxq_f64(rounded, vdupq_n_f64(-max - 1.0)), vdupq_n_f64(max));
+        // NaN propagates through the clamp and converts to 0.
+        vcvtq_s64_f64(clamped)
+    }
+
+    #[inline]
+    unsafe fn round_f32x4(x: float32x4_t, max: f64) -> int32x4_t {
+        let low = round_f64x2(vcvt_f64_f32(vget_low_f32(x)), max);
+        let high = round_f64x2(vcvt_high_f64_f32(x), max);
+        vcombine_s32(vmovn_s64(low), vmovn_s64(high))
+    }
+
+    #[inline]
+    unsafe fn to_f32x4(x: int32x4_t, min_divisor: f32, max_divisor: f32) -> float32x4_t {
+        let negative = vcltzq_s32(x);
+        let divisor = vbslq_f32(negative, vdupq_n_f32(min_divisor), vdupq_n_f32(max_divisor));
+        vdivq_f32(vcvtq_f32_s32(x), divisor)
+    }
+
+    pub unsafe fn f32_to_i16(input: *const f32, output: *mut i16, len: usize) -> usize {
+        let chunks = len / 4;
+        for i in 0..chunks {
+            let x = vld1q_f32(input.add(i * 4));
+            vst1_s16(output.add(i * 4), vqmovn_s32(round_f32x4(x, I16_MAX)));
+        }
+        chunks * 4
+    }
+
+    pub unsafe fn f32_to_i32(input: *const f32, output: *mut i32, len: usize) -> usize {
+        let chunks = len / 4;
+        for i in 0..chunks {
+            let x = vld1q_f32(input.add(i * 4));
+            vst1q_s32(output.add(i * 4), round_f32x4(x, I32_MAX));
+        }
+        chunks * 4
+    }
+
+    pub unsafe fn i16_to_f32(input: *const i16, output: *mut f32, len: usize) -> usize {
+        let chunks = len / 4;
+        for i in 0..chunks {
+            let x = vmovl_s16(vld1_s16(input.add(i * 4)));
+            vst1q_f32(output.add(i * 4), to_f32x4(x, I16_MIN_DIVISOR, I16_MAX_DIVISOR));
+        }
+        chunks * 4
+    }
+
+    pub unsafe fn i32_to_f32(input: *const i32, output: *mut f32, len: usize) -> usize {
+        let chunks = len / 4;
+        for i in 0..chunks {
+            let x = vld1q_s32(input.add(i * 4));
+            vst1q_f32(output.add(i * 4), to_f32x4(x, I32_MIN_DIVISOR, I32_MAX_DIVISOR));
+        }
+        chunks * 4
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f32;
+
+    use Sample;
+
+    type Kernel<S, D> = unsafe fn(*const S, *mut D, usize) -> usize;
+
+    // Floats covering the whole range of bit patterns, including NaN, infinities and subnormals,
+    // and the values around each rounding boundary of `bits`-bit integers.
+    fn float_values(bits: u32) -> Vec<f32> {
+        let mut values: Vec<f32> = (0..=0xffff_u32).map(|i| f32::from_bits(i * 0x1_0001)).collect();
+        values.extend(&[
+            0.0, -0.0, 1.0, -1.0, 2.0, -2.0, f32::INFINITY, f32::NEG_INFINITY, f32::NAN,
+            f32::MAX, f32::MIN, f32::MIN_POSITIVE, f32::EPSILON,
+        ]);
+        let max = ((1i64 << (bits - 1)) - 1) as f64;
+        for i in (-(1i64 << 15)..(1i64 << 15)).map(|i| i << (bits - 16)) {
+            for &offset in &[0.5, -0.5] {
+                let scale = if i < 0 { max + 1.0 } else { max };
+                let boundary = ((i as f64 + offset) / scale) as f32;
+                let below = f32::from_bits(boundary.to_bits().wrapping_sub(1));
+                let above = f32::from_bits(boundary.to_bits().wrapping_add(1));
+                values.extend(&[below, boundary, above]);
+            }
+        }
+        values
+    }
+
+    // Runs `kernel` on `input` and checks that it matches `Sample::from` bit for bit.
+    fn check<S, D>(kernel: Kernel<S, D>, input: &[S], equal: fn(&D, &D) -> bool)
+    where
+        S: Sample + ::std::fmt::Debug,
+        D: Sample + ::std::fmt::Debug,
+    {
+        let mut output = vec![D::EQUILIBRIUM; input.len()];
+        let converted = unsafe { kernel(input.as_ptr(), output.as_mut_ptr(), input.len()) };
+        assert!(converted > input.len() - 8);
+        for (sample, out) in input.iter().zip(&output[..converted]) {
+            let expected = D::from(sample);
+            assert!(equal(out, &expected), "{:?}: {:?} != {:?}", sample, out, expected);
+        }
+    }
+
+    fn same_int<T: PartialEq>(a: &T, b: &T) -> bool {
+        a == b
+    }
+
+    fn same_float(a: &f32, b: &f32) -> bool {
+        a.to_bits() == b.to_bits()
+    }
+
+    fn check_kernels(
+        f32_to_i16: Kernel<f32, i16>,
+        f32_to_i32: Kernel<f32, i32>,
+        i16_to_f32: Kernel<i16, f32>,
+        i32_to_f32: Kernel<i32, f32>,
+    ) {
+        check(f32_to_i16, &float_values(16), same_int);
+        check(f32_to_i32, &float_values(32), same_int);
+        let i16_values: Vec<i16> = (-32_768..=32_767).collect();
+        check(i16_to_f32, &i16_values, same_float);
+        let mut i32_values: Vec<i32> = (0..=0xffff_u32).map(|i| (i * 0x1_0001) as i32).collect();
+        i32_values.extend(&[::std::i32::MIN, ::std::i32::MAX, -1, 0, 1, 0x7fff_ff7f, 0x7fff_ff80]);
+        check(i32_to_f32, &i32_values, same_float);
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[test]
+    fn sse2_matches_scalar() {
+        use super::x86::sse2;
+        if is_x86_feature_detected!("sse2") {
+            check_kernels(sse2::f32_to_i16, sse2::f32_to_i32, sse2::i16_to_f32, sse2::i32_to_f32);
+        }
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[test]
+    fn avx2_matches_scalar() {
+        use super::x86::avx2;
+        if is_x86_feature_detected!("avx2") {
+            check_kernels(avx2::f32_to_i16, avx2::f32_to_i32, avx2::i16_to_f32, avx2::i32_to_f32);
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[test]
+    fn neon_matches_scalar() {
+        use super::neon;
+        check_kernels(neon::f32_to_i16, neon::f32_to_i32, neon::i16_to_f32, neon::i32_to_f32);
+    }
+}
@@ -2,10 +2,19 @@
 
 use {
     BuildStreamError,
+    ChannelLayout,
+    ClosestFormat,
+    ClosestFormatError,
     DefaultFormatError,
+    DeviceId,
+    DeviceIdError,
+    DeviceInfo,
+    DeviceInfoError,
     DeviceNameError,
     DevicesError,
     Format,
+    FormatPreference,
+    FormatRequest,
     InputDevices,
     OutputDevices,
     PauseStreamError,
@@ -63,15 +72,21 @@ pub trait HostTrait {
     /// Initialise the event loop, ready for managing audio streams.
     fn event_loop(&self) -> Self::EventLoop;
 
+    /// Finds the currently available device with the given identifier.
+    ///
+    /// Returns `Ok(None)` if no such device is currently available, for example because it has been
+    /// unplugged or because the identifier belongs to another host.
+    fn device_by_id(&self, id: &DeviceId) -> Result<Option<Self::Device>, DevicesError> {
+        Ok(self.devices()?.find(|device| device.id().ok().as_ref() == Some(id)))
+    }
+
     /// An iterator yielding all `Device`s currently available to the system that support one or more
     /// input stream formats.
     ///
     /// Can be empty if the system does not support audio input.
     fn input_devices(&self) -> Result<InputDevices<Self::Devices>, DevicesError> {
         fn supports_input<D: DeviceTrait>(device: &D) -> bool {
-            device.supported_input_formats()
-                .map(|mut iter| iter.next().is_some())
-                .unwrap_or(false)
+            device.supports_input()
         }
         Ok(self.devices()?.filter(supports_input::<Self::Device>))
     }
@@ -82,9 +97,7 @@ pub trait HostTrait {
     /// Can be empty if the system does not support audio output.
     fn output_devices(&self) -> Result<OutputDevices<Self::Devices>, DevicesError> {
         fn supports_output<D: DeviceTrait>(device: &D) -> bool {
-            device.supported_output_formats()
-                .map(|mut iter| iter.next().is_some())
-                .unwrap_or(false)
+            device.supports_output()
         }
         Ok(self.devices()?.filter(supports_output::<Self::Device>))
     }
@@ -103,6 +116,16 @@ pub trait DeviceTrait {
     /// The human-readable name of the device.
     fn name(&self) -> Result<String, DeviceNameError>;
 
+    /// An identifier that uniquely and persistently identifies the device within the system.
+    ///
+    /// In contrast to `name`, this may be used to find the same device again via
+    /// `HostTrait::device_by_id`.
+    fn id(&self) -> Result<DeviceId, DeviceIdError>;
+
+    /// Metadata describing the device, such as a human-readable description, the sound card and
+    /// driver it belongs to and the stream directions it supports.
+    fn info(&self) -> Result<DeviceInfo, DeviceInfoError>;
+
     /// An iterator yielding formats that are supported by the backend.
     ///
     /// Can return an error if the device is no longer valid (eg. it has been disconnected).
@@ -118,6 +141,70 @@ pub trait DeviceTrait {
 
     /// The default output stream format for the device.
     fn default_output_format(&self) -> Result<Format, DefaultFormatError>;
+
+    /// The default input stream format for the device, ranked by the given preference.
+    ///
+    /// Hosts that do not provide default formats select the preferred supported format. Hosts
+    /// that do provide one return it regardless of the preference.
+    fn default_input_format_with(
+        &self,
+        _preference: &FormatPreference,
+    ) -> Result<Format, DefaultFormatError> {
+        self.default_input_format()
+    }
+
+    /// The default output stream format for the device, ranked by the given preference.
+    ///
+    /// Hosts that do not provide default formats select the preferred supported format. Hosts
+    /// that do provide one return it regardless of the preference.
+    fn default_output_format_with(
+        &self,
+        _preference: &FormatPreference,
+    ) -> Result<Format, DefaultFormatError> {
+        self.default_output_format()
+    }
+
+    /// The supported input stream format closest to the given request.
+    ///
+    /// See `FormatRequest::closest_format` for how the format is selected.
+    fn closest_input_format(
+        &self,
+        request: &FormatRequest,
+    ) -> Result<ClosestFormat, ClosestFormatError> {
+        let formats = self.supported_input_formats()?;
+        request.closest_format(formats).ok_or(ClosestFormatError::NoMatchingFormat)
+    }
+
+    /// The supported output stream format closest to the given request.
+    ///
+    /// See `FormatRequest::closest_format` for how the format is selected.
+    fn closest_output_format(
+        &self,
+        request: &FormatRequest,
+    ) -> Result<ClosestFormat, ClosestFormatError> {
+        let formats = self.supported_output_formats()?;
+        request.closest_format(formats).ok_or(ClosestFormatError::NoMatchingFormat)
+    }
+
+    /// Whether or not the device supports one or more input stream formats.
+    ///
+    /// By default this queries the supported input formats. Hosts that already know the
+    /// directions of their devices may answer without probing the device.
+    fn supports_input(&self) -> bool {
+        self.supported_input_formats()
+            .map(|mut iter| iter.next().is_some())
+            .unwrap_or(false)
+    }
+
+    /// Whether or not the device supports one or more output stream formats.
+    ///
+    /// By default this queries the supported output formats. Hosts that already know the
+    /// directions of their devices may answer without probing the device.
+    fn supports_output(&self) -> bool {
+        self.supported_output_formats()
+            .map(|mut iter| iter.next().is_some())
+            .unwrap_or(false)
+    }
 }
 
 /// Collection of streams managed together.
@@ -168,7 +255,9 @@ pub trait EventLoopTrait {
     ///
     /// Has no effect is the stream was already paused.
     ///
-    /// If you call `play` afterwards, the playback will resume where it was.
+    /// If you call `play` afterwards, the playback will resume where it was. The `run` callback
+    /// is not called for the stream while it is paused. Devices that cannot pause in hardware
+    /// are stopped instead, which discards the data they have buffered.
     ///
     /// # Panic
     ///
@@ -182,6 +271,23 @@ pub trait EventLoopTrait {
     /// If the stream does not exist, this function can either panic or be a no-op.
     fn destroy_stream(&self, stream: Self::StreamId);
 
+    /// Whether `destroy_stream` was called for the stream with the given ID but the stream has
+    /// not been destroyed yet, e.g. because its output is still fading out. The `run` callback is
+    /// called for the stream until it is destroyed.
+    ///
+    /// Returns `false` for event loops that destroy streams immediately.
+    fn is_destroy_pending(&self, _stream: Self::StreamId) -> bool {
+        false
+    }
+
+    /// The channel map in effect for the stream with the given ID.
+    ///
+    /// Returns `None` if the stream does not exist or if the host does not report the channel
+    /// maps of its streams.
+    fn stream_channel_layout(&self, _stream: Self::StreamId) -> Option<ChannelLayout> {
+        None
+    }
+
     /// Takes control of the current thread and begins the stream processing.
     ///
     /// > **Note**: Since it takes control of the thread, this method is best called on a separate